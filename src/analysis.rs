@@ -0,0 +1,219 @@
+use std::fmt;
+
+// Penalty weights from the QR Code specification, as used by qrcodegen when choosing a mask.
+const PENALTY_N1: i32 = 3;
+const PENALTY_N2: i32 = 3;
+const PENALTY_N3: i32 = 40;
+const PENALTY_N4: i32 = 10;
+
+/// The penalty score of each of the eight masks for a payload, along with the mask that was used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaskAnalysis {
+    pub chosen: u8,
+    pub penalties: [i32; 8],
+}
+
+impl MaskAnalysis {
+    /// Re-encode the payload with every mask at the same version and error correction level as the
+    /// given QR Code and score each one.
+    pub fn new(qr_code: &qrcodegen::QrCode, segments: &[qrcodegen::QrSegment]) -> Self {
+        let mut penalties = [0; 8];
+
+        for (mask, penalty) in penalties.iter_mut().enumerate() {
+            // The payload already fits this version and level, so this cannot fail.
+            let masked = qrcodegen::QrCode::encode_segments_advanced(
+                segments,
+                qr_code.error_correction_level(),
+                qr_code.version(),
+                qr_code.version(),
+                Some(qrcodegen::Mask::new(mask as u8)),
+                false,
+            )
+            .expect("payload fits the version it was encoded with");
+
+            *penalty = penalty_score(&masked);
+        }
+
+        MaskAnalysis {
+            chosen: qr_code.mask().value(),
+            penalties,
+        }
+    }
+}
+
+impl fmt::Display for MaskAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chosen mask {} |", self.chosen)?;
+
+        for (mask, penalty) in self.penalties.iter().enumerate() {
+            write!(
+                f,
+                " {}:{}{}",
+                mask,
+                penalty,
+                if mask as u8 == self.chosen { "*" } else { "" }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Calculate the penalty score of a QR Code's modules, following the same rules qrcodegen uses
+/// when it automatically chooses a mask.
+pub fn penalty_score(qr_code: &qrcodegen::QrCode) -> i32 {
+    let size = qr_code.size();
+    let mut result = 0;
+
+    // Adjacent modules in rows and columns having the same colour, and finder-like patterns.
+    for transpose in &[false, true] {
+        for i in 0..size {
+            let module = |j: i32| {
+                if *transpose {
+                    qr_code.get_module(i, j)
+                } else {
+                    qr_code.get_module(j, i)
+                }
+            };
+
+            let mut run_colour = false;
+            let mut run_length = 0;
+            let mut history = FinderPenalty::new(size);
+
+            for j in 0..size {
+                if module(j) == run_colour {
+                    run_length += 1;
+                    if run_length == 5 {
+                        result += PENALTY_N1;
+                    } else if run_length > 5 {
+                        result += 1;
+                    }
+                } else {
+                    history.add_history(run_length);
+                    if !run_colour {
+                        result += history.count_patterns() * PENALTY_N3;
+                    }
+                    run_colour = module(j);
+                    run_length = 1;
+                }
+            }
+
+            result += history.terminate_and_count(run_colour, run_length) * PENALTY_N3;
+        }
+    }
+
+    // 2*2 blocks of modules having the same colour.
+    for y in 0..size - 1 {
+        for x in 0..size - 1 {
+            let colour = qr_code.get_module(x, y);
+            if colour == qr_code.get_module(x + 1, y)
+                && colour == qr_code.get_module(x, y + 1)
+                && colour == qr_code.get_module(x + 1, y + 1)
+            {
+                result += PENALTY_N2;
+            }
+        }
+    }
+
+    // Balance of dark and light modules.
+    let dark: i32 = (0..size)
+        .flat_map(|y| (0..size).map(move |x| (x, y)))
+        .filter(|&(x, y)| qr_code.get_module(x, y))
+        .count() as i32;
+    let total = size * size;
+    let k = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+    result += k * PENALTY_N4;
+
+    result
+}
+
+struct FinderPenalty {
+    qr_size: i32,
+    run_history: [i32; 7],
+}
+
+impl FinderPenalty {
+    fn new(qr_size: i32) -> Self {
+        FinderPenalty {
+            qr_size,
+            run_history: [0; 7],
+        }
+    }
+
+    // Pushes the given value to the front and drops the last value.
+    fn add_history(&mut self, mut run_length: i32) {
+        if self.run_history[0] == 0 {
+            // Add light border to initial run.
+            run_length += self.qr_size;
+        }
+
+        self.run_history.rotate_right(1);
+        self.run_history[0] = run_length;
+    }
+
+    // Can only be called immediately after a light run is added, and returns either 0, 1, or 2.
+    fn count_patterns(&self) -> i32 {
+        let rh = &self.run_history;
+        let n = rh[1];
+        let core = n > 0 && rh[2] == n && rh[3] == n * 3 && rh[4] == n && rh[5] == n;
+
+        i32::from(core && rh[0] >= n * 4 && rh[6] >= n)
+            + i32::from(core && rh[6] >= n * 4 && rh[0] >= n)
+    }
+
+    // Must be called at the end of a row or column of modules.
+    fn terminate_and_count(mut self, run_colour: bool, mut run_length: i32) -> i32 {
+        if run_colour {
+            // Terminate dark run.
+            self.add_history(run_length);
+            run_length = 0;
+        }
+
+        // Add light border to final run.
+        run_length += self.qr_size;
+        self.add_history(run_length);
+        self.count_patterns()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_agree_with_automatic_mask_choice() {
+        for text in &["ha", "https://ihamlin.co.uk", "9998819191919191"] {
+            let segments = qrcodegen::QrSegment::make_segments(text);
+            let qr_code =
+                qrcodegen::QrCode::encode_segments(&segments, qrcodegen::QrCodeEcc::Medium)
+                    .unwrap();
+
+            let analysis = MaskAnalysis::new(&qr_code, &segments);
+            let min = *analysis.penalties.iter().min().unwrap();
+            let first_min = analysis.penalties.iter().position(|&p| p == min).unwrap();
+
+            assert_eq!(first_min as u8, analysis.chosen);
+        }
+    }
+
+    #[test]
+    fn should_report_forced_mask() {
+        let segments = qrcodegen::QrSegment::make_segments("ha");
+        let qr_code = qrcodegen::QrCode::encode_segments_advanced(
+            &segments,
+            qrcodegen::QrCodeEcc::Low,
+            qrcodegen::Version::MIN,
+            qrcodegen::Version::MAX,
+            Some(qrcodegen::Mask::new(5)),
+            false,
+        )
+        .unwrap();
+
+        let analysis = MaskAnalysis::new(&qr_code, &segments);
+
+        assert_eq!(5, analysis.chosen);
+        assert_eq!(penalty_score(&qr_code), analysis.penalties[5]);
+        assert!(analysis.to_string().contains(" 5:"));
+        assert!(analysis.to_string().starts_with("chosen mask 5 |"));
+    }
+}
@@ -0,0 +1,333 @@
+use flate2::{write::GzEncoder, Compression};
+use log::info;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Where an archive is written, which may need more than a flush after the last write, e.g. to end a gzip stream.
+pub trait Sink: Write + Send {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write + Send> Sink for BufWriter<W> {}
+
+impl Sink for File {}
+
+impl<W: Write + Send> Sink for GzEncoder<W> {
+    fn finish(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+/// True if the output path names an archive file rather than a directory, i.e. it ends in .tar, .tar.gz or .tgz.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    [".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+// Tar stores each file as a header block followed by its data padded to whole blocks, and ends with two empty blocks.
+const BLOCK: u64 = 512;
+const END: u64 = 2 * BLOCK;
+
+/// The most a single volume of an archive holds before the next one is started, by size before compression and by
+/// number of files.  With neither set the archive is a single file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Volumes {
+    max_bytes: Option<u64>,
+    max_files: Option<u64>,
+}
+
+impl Volumes {
+    pub fn new(max_bytes: Option<u64>, max_files: Option<u64>) -> Self {
+        Volumes {
+            max_bytes,
+            max_files,
+        }
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.max_bytes.is_some() || self.max_files.is_some()
+    }
+
+    // An entry that is too big for a volume on its own still goes in one, as it can't be split.
+    fn is_full(&self, volume: &Volume, entry: u64) -> bool {
+        volume.files > 0
+            && (self
+                .max_bytes
+                .is_some_and(|max| volume.bytes + entry + END > max)
+                || self.max_files.is_some_and(|max| volume.files >= max))
+    }
+}
+
+/// The path of a numbered volume of the archive, with the number before the extension, e.g. codes-002.tar.gz.
+pub fn volume_path(path: &Path, number: u32) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lower = name.to_lowercase();
+    let stem = [".tar.gz", ".tgz", ".tar"]
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map_or(name.len(), |extension| name.len() - extension.len());
+
+    path.with_file_name(format!("{}-{:03}{}", &name[..stem], number, &name[stem..]))
+}
+
+struct Volume {
+    builder: tar::Builder<Box<dyn Sink>>,
+    number: u32,
+    bytes: u64,
+    files: u64,
+}
+
+impl Volume {
+    fn new(writer: Box<dyn Sink>, number: u32) -> Self {
+        Volume {
+            builder: tar::Builder::new(writer),
+            number,
+            bytes: 0,
+            files: 0,
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.builder.finish()?;
+        self.builder.get_mut().finish()
+    }
+}
+
+/// A tar archive the outputs are written into instead of files in the output directory, e.g. to stream them to
+/// standard output without touching the disk.
+pub struct Archive {
+    volume: Mutex<Volume>,
+    path: Option<PathBuf>,
+    volumes: Volumes,
+}
+
+impl Archive {
+    /// Create the archive file, compressed with gzip as it is written when the name ends in .gz or .tgz.  When it is
+    /// split into volumes the first is created now, and the rest as they are needed.
+    pub fn create(path: &Path, volumes: Volumes) -> io::Result<Self> {
+        let writer = if volumes.is_split() {
+            open(&volume_path(path, 1))?
+        } else {
+            open(path)?
+        };
+
+        Ok(Archive {
+            volume: Mutex::new(Volume::new(writer, 1)),
+            path: Some(path.to_path_buf()),
+            volumes,
+        })
+    }
+
+    pub fn new(writer: Box<dyn Sink>) -> Self {
+        Archive {
+            volume: Mutex::new(Volume::new(writer, 1)),
+            path: None,
+            volumes: Volumes::default(),
+        }
+    }
+
+    /// Add a file to the archive under the path, which is relative to the output directory, starting the next volume
+    /// first if it wouldn't fit in this one.
+    pub fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        );
+
+        let entry = entry_size(path, data.len() as u64);
+        let mut volume = self.volume.lock().unwrap();
+        if let Some(base) = self
+            .path
+            .as_ref()
+            .filter(|_| self.volumes.is_full(&volume, entry))
+        {
+            volume.finish()?;
+            let next = volume_path(base, volume.number + 1);
+            info!("starting archive volume {}", next.display());
+            *volume = Volume::new(open(&next)?, volume.number + 1);
+        }
+
+        volume.builder.append_data(&mut header, path, data)?;
+        volume.bytes += entry;
+        volume.files += 1;
+        Ok(())
+    }
+
+    /// Write the end of the archive, after which nothing more can be added.
+    pub fn finish(&self) -> io::Result<()> {
+        self.volume.lock().unwrap().finish()
+    }
+}
+
+fn open(path: &Path) -> io::Result<Box<dyn Sink>> {
+    let file = BufWriter::new(File::create(path)?);
+    let name = path.to_string_lossy().to_lowercase();
+
+    Ok(if name.ends_with(".gz") || name.ends_with(".tgz") {
+        Box::new(GzEncoder::new(file, Compression::default()))
+    } else {
+        Box::new(file)
+    })
+}
+
+// The bytes an entry takes in the archive, including the extra header and name blocks a GNU archive needs for a path
+// too long for the header.
+fn entry_size(path: &Path, len: u64) -> u64 {
+    let name = path.to_string_lossy().len() as u64;
+    let long_name = if name > 100 {
+        BLOCK + (name + 1).div_ceil(BLOCK) * BLOCK
+    } else {
+        0
+    };
+    long_name + BLOCK + len.div_ceil(BLOCK) * BLOCK
+}
+
+impl fmt::Debug for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Archive")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Read};
+
+    #[test]
+    fn should_append_outputs() {
+        let path = std::env::temp_dir().join("qrgen_should_append_outputs.tar");
+        let archive = Archive::new(Box::new(fs::File::create(&path).unwrap()));
+
+        archive.append(Path::new("a.svg"), b"<svg/>").unwrap();
+        archive
+            .append(&Path::new("ab").join("b.svg"), b"<svg></svg>")
+            .unwrap();
+        archive.finish().unwrap();
+
+        let mut read = tar::Archive::new(fs::File::open(&path).unwrap());
+        let entries: Vec<_> = read
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = String::new();
+                entry.read_to_string(&mut data).unwrap();
+                (entry.path().unwrap().display().to_string(), data)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("a.svg".to_string(), "<svg/>".to_string()),
+                (
+                    Path::new("ab").join("b.svg").display().to_string(),
+                    "<svg></svg>".to_string()
+                )
+            ],
+            entries
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_compress_tar_gz() {
+        let path = std::env::temp_dir().join("qrgen_should_compress_tar_gz.tar.gz");
+        let archive = Archive::create(&path, Volumes::default()).unwrap();
+
+        archive.append(Path::new("a.svg"), b"<svg/>").unwrap();
+        archive.finish().unwrap();
+
+        let gz = flate2::read::GzDecoder::new(fs::File::open(&path).unwrap());
+        let mut read = tar::Archive::new(gz);
+        let names: Vec<_> = read
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert_eq!(vec!["a.svg".to_string()], names);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_split_into_volumes() {
+        let path = std::env::temp_dir().join("qrgen_should_split_into_volumes.tar");
+        let archive = Archive::create(&path, Volumes::new(None, Some(2))).unwrap();
+
+        for name in ["a.svg", "b.svg", "c.svg"] {
+            archive.append(Path::new(name), b"<svg/>").unwrap();
+        }
+        archive.finish().unwrap();
+
+        let names = |number| {
+            let mut read = tar::Archive::new(fs::File::open(volume_path(&path, number)).unwrap());
+            read.entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["a.svg", "b.svg"], names(1));
+        assert_eq!(vec!["c.svg"], names(2));
+        assert!(!path.exists());
+        fs::remove_file(volume_path(&path, 1)).unwrap();
+        fs::remove_file(volume_path(&path, 2)).unwrap();
+    }
+
+    #[test]
+    fn should_keep_volumes_under_max_bytes() {
+        let path = std::env::temp_dir().join("qrgen_should_keep_volumes_under_max_bytes.tar");
+        let archive = Archive::create(&path, Volumes::new(Some(4096), None)).unwrap();
+
+        for name in ["a.svg", "b.svg", "c.svg"] {
+            archive.append(Path::new(name), &[b' '; 1000]).unwrap();
+        }
+        archive.finish().unwrap();
+
+        for number in 1..=2 {
+            let volume = volume_path(&path, number);
+            assert!(fs::metadata(&volume).unwrap().len() <= 4096);
+            fs::remove_file(volume).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_number_volumes_before_the_extension() {
+        assert_eq!(
+            Path::new("out/codes-002.tar.gz"),
+            volume_path(Path::new("out/codes.tar.gz"), 2)
+        );
+        assert_eq!(
+            Path::new("codes-010.TGZ"),
+            volume_path(Path::new("codes.TGZ"), 10)
+        );
+    }
+
+    #[test]
+    fn should_recognise_archive_paths() {
+        assert!(is_archive(Path::new("out/codes.tar")));
+        assert!(is_archive(Path::new("codes.TAR.GZ")));
+        assert!(is_archive(Path::new("codes.tgz")));
+        assert!(!is_archive(Path::new("codes")));
+        assert!(!is_archive(Path::new("codes.gz")));
+    }
+}
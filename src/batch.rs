@@ -0,0 +1,108 @@
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+/// A QR Code kept for an output that holds the whole batch, with the record's name to caption it with.
+#[derive(Clone)]
+pub struct Code {
+    input: String,
+    row: u64,
+    pub name: String,
+    pub qr_code: qrcodegen::QrCode,
+}
+
+/// How the QR Codes are drawn in the outputs for the whole batch, the same as in the file for each record.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    pub border: u8,
+    pub scale: u32,
+    pub no_rect: bool,
+    pub foreground: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+}
+
+impl Style {
+    /// The number of modules across the QR Code including the border on both sides.
+    pub fn modules(&self, qr_code: &qrcodegen::QrCode) -> i32 {
+        qr_code.size() + 2 * i32::from(self.border)
+    }
+}
+
+/// The QR Codes generated during a run, kept when an output needs them all at once, e.g. a PDF with a page for each.
+#[derive(Default)]
+pub struct Batch(Mutex<Vec<Code>>);
+
+impl Batch {
+    pub fn add(&self, input: &Path, row: u64, name: &str, qr_code: &qrcodegen::QrCode) {
+        self.0.lock().unwrap().push(Code {
+            input: input.display().to_string(),
+            row,
+            name: name.to_string(),
+            qr_code: qr_code.clone(),
+        });
+    }
+
+    /// The QR Codes in input order, as records are generated in parallel.
+    pub fn codes(&self) -> MutexGuard<'_, Vec<Code>> {
+        let mut codes = self.0.lock().unwrap();
+        codes.sort_by(|a, b| a.input.cmp(&b.input).then(a.row.cmp(&b.row)));
+        codes
+    }
+}
+
+/// The dark modules of the QR Code as runs along each row, as the column and row of the first module and the length
+/// of the run, so vector outputs draw one rectangle for each run rather than each module.
+pub fn runs(qr_code: &qrcodegen::QrCode) -> impl Iterator<Item = (i32, i32, i32)> + '_ {
+    let size = qr_code.size();
+    (0..size).flat_map(move |y| {
+        let mut x = 0;
+        std::iter::from_fn(move || {
+            while x < size && !qr_code.get_module(x, y) {
+                x += 1;
+            }
+            let start = x;
+            while x < size && qr_code.get_module(x, y) {
+                x += 1;
+            }
+            (x > start).then_some((start, y, x - start))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_codes_in_input_order() {
+        let batch = Batch::default();
+        let qr_code = qrcodegen::QrCode::encode_text("1", qrcodegen::QrCodeEcc::Low).unwrap();
+        batch.add(Path::new("b.csv"), 1, "c", &qr_code);
+        batch.add(Path::new("a.csv"), 2, "b", &qr_code);
+        batch.add(Path::new("a.csv"), 1, "a", &qr_code);
+
+        let names: Vec<_> = batch.codes().iter().map(|code| code.name.clone()).collect();
+        assert_eq!(vec!["a", "b", "c"], names);
+    }
+
+    #[test]
+    fn should_cover_dark_modules_with_runs() {
+        let qr_code = qrcodegen::QrCode::encode_text("12345", qrcodegen::QrCodeEcc::Low).unwrap();
+
+        let mut covered = 0;
+        for (x, y, len) in runs(&qr_code) {
+            assert!((x..x + len).all(|x| qr_code.get_module(x, y)));
+            assert!(!qr_code.get_module(x - 1, y) && !qr_code.get_module(x + len, y));
+            covered += len;
+        }
+
+        let dark = (0..qr_code.size())
+            .flat_map(|y| (0..qr_code.size()).map(move |x| (x, y)))
+            .filter(|(x, y)| qr_code.get_module(*x, *y))
+            .count();
+        assert_eq!(dark as i32, covered);
+        // The finder pattern's top edge is a run of seven.
+        assert_eq!(Some((0, 0, 7)), runs(&qr_code).next());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::exporter::{self, ExportFormat};
+use crate::space;
+use std::sync::{Condvar, Mutex};
+
+// A generous allowance for a record waiting to be encoded, most of which is its data.
+const RECORD_BYTES: u64 = 1024;
+
+/// A limit on the memory used by a run, e.g. 256M, split evenly between the records read ahead of the encoders and
+/// the images being drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryBudget(u64);
+
+impl MemoryBudget {
+    /// Parse a number of bytes with an optional K, M or G suffix.
+    pub fn parse(src: &str) -> Result<Self, String> {
+        space::parse_bytes(src).map(MemoryBudget).ok_or_else(|| {
+            String::from(
+                "Max memory must be a number of bytes greater than 0, optionally followed by K, M or G.",
+            )
+        })
+    }
+
+    /// The number of records that can be read ahead in half the budget, shared between the files read at once.
+    pub fn records(self, files: usize) -> usize {
+        (self.0 / 2 / RECORD_BYTES / files.max(1) as u64) as usize
+    }
+
+    /// The number of images of the given size that can be drawn at once in half the budget, at least one.
+    pub fn images(self, image_bytes: u64) -> usize {
+        ((self.0 / 2 / image_bytes.max(1)) as usize).max(1)
+    }
+}
+
+/// The most memory drawing and writing a single QR Code of the largest version can take.
+pub fn image_bytes(version_max: u8, border: u8, scale: u32, format: ExportFormat) -> u64 {
+    let modules = 17 + 4 * u64::from(version_max) + 2 * u64::from(border);
+    // Saturates for scales too big to draw, which then take the whole budget.
+    let pixels = (modules * u64::from(scale)).saturating_pow(2);
+    match format {
+        // A path command for each module.
+        ExportFormat::SVG => modules * modules * 16,
+        // The RGB pixels and, at worst, an encoded image as large again.
+        ExportFormat::PNG => pixels.saturating_mul(3 * 2),
+        // The RGB pixels, copied for the encoder, which holds the image again as planes of YUV.
+        ExportFormat::AVIF => pixels.saturating_mul(3 * 4),
+        // The RGB pixels of a frame, its palette indices and the encoded frame.
+        ExportFormat::GIF => pixels.saturating_mul(3 + 1 + 1),
+        // The RGBA pixels of every size and the encoded images.
+        ExportFormat::ICO => exporter::ICO_SIZES
+            .iter()
+            .map(|size| u64::from(*size).pow(2) * 4 * 2)
+            .sum(),
+    }
+}
+
+/// A counting semaphore that limits how many images are drawn at the same time, with a permit for each image of the
+/// usual size that fits the budget.
+pub struct Permits {
+    available: Mutex<usize>,
+    released: Condvar,
+    count: usize,
+    image_bytes: u64,
+}
+
+impl Permits {
+    pub fn new(count: usize, image_bytes: u64) -> Self {
+        Permits {
+            available: Mutex::new(count),
+            released: Condvar::new(),
+            count,
+            image_bytes,
+        }
+    }
+
+    /// Wait for the permits an image of the given size needs, one for each image of the usual size it could hold,
+    /// or all of them for an image larger than the budget.  They're given back when the returned guard is dropped.
+    pub fn acquire(&self, image_bytes: u64) -> Permit<'_> {
+        let needed = (image_bytes.div_ceil(self.image_bytes.max(1)) as usize).clamp(1, self.count);
+        let mut available = self.available.lock().unwrap();
+        while *available < needed {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= needed;
+
+        Permit(self, needed)
+    }
+}
+
+pub struct Permit<'a>(&'a Permits, usize);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap() += self.1;
+        // Waiting images may need different numbers of permits, so any of them might now have enough.
+        self.0.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_sizes() {
+        assert_eq!(Ok(MemoryBudget(256 << 20)), MemoryBudget::parse("256M"));
+        assert_eq!(Ok(MemoryBudget(1 << 30)), MemoryBudget::parse("1g"));
+        assert_eq!(Ok(MemoryBudget(4096)), MemoryBudget::parse("4096"));
+        assert!(MemoryBudget::parse("0M").is_err());
+        assert!(MemoryBudget::parse("M").is_err());
+        assert!(MemoryBudget::parse("lots").is_err());
+    }
+
+    #[test]
+    fn should_share_budget() {
+        let budget = MemoryBudget(256 << 20);
+
+        assert_eq!(65536, budget.records(2));
+        assert_eq!(128, budget.images(1 << 20));
+        assert_eq!(1, MemoryBudget(1024).images(1 << 20));
+    }
+
+    #[test]
+    fn should_saturate_image_bytes_for_huge_scales() {
+        assert_eq!(
+            21 * 21 * 300 * 300 * 6,
+            image_bytes(1, 0, 300, ExportFormat::PNG)
+        );
+        assert_eq!(u64::MAX, image_bytes(40, 255, u32::MAX, ExportFormat::AVIF));
+    }
+
+    #[test]
+    fn should_limit_permits() {
+        let permits = Permits::new(1, 1024);
+
+        let permit = permits.acquire(1024);
+        assert_eq!(0, *permits.available.lock().unwrap());
+        drop(permit);
+        assert_eq!(1, *permits.available.lock().unwrap());
+    }
+
+    #[test]
+    fn should_take_more_permits_for_larger_images() {
+        let permits = Permits::new(4, 1024);
+
+        let large = permits.acquire(2048);
+        assert_eq!(2, *permits.available.lock().unwrap());
+        let small = permits.acquire(10);
+        assert_eq!(1, *permits.available.lock().unwrap());
+        drop((large, small));
+
+        let huge = permits.acquire(1 << 30);
+        assert_eq!(0, *permits.available.lock().unwrap());
+        drop(huge);
+        assert_eq!(4, *permits.available.lock().unwrap());
+    }
+}
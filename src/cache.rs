@@ -0,0 +1,113 @@
+use crate::exporter;
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const CACHE_FILE: &str = ".qrgen-cache.json";
+
+/// The hashes of the data and settings each output file in a directory was generated from, kept beside the outputs
+/// so a later run can skip the records whose QR Code wouldn't change.
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    hashes: Mutex<BTreeMap<String, String>>,
+}
+
+impl Cache {
+    /// Load the cache for the directory, starting empty if there isn't one yet or it can't be read, which only costs
+    /// generating everything again.
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(CACHE_FILE);
+        let hashes = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("ignoring cache {} {:?}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Cache {
+            path,
+            hashes: Mutex::new(hashes),
+        }
+    }
+
+    /// True if the output was generated from the same hash and is still there.
+    pub fn is_current(&self, output: &Path, hash: &str) -> bool {
+        let cached = self.hashes.lock().unwrap().get(&file_name(output)).cloned();
+        cached.as_deref() == Some(hash)
+            && exporter::long_path(output)
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() > 0)
+    }
+
+    pub fn insert(&self, output: &Path, hash: String) {
+        self.hashes.lock().unwrap().insert(file_name(output), hash);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let file = File::create(&self.path)?;
+        serde_json::to_writer_pretty(file, &*self.hashes.lock().unwrap())?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The hash of a record's data and overrides together with the settings that change its QR Code.
+pub fn hash(settings: &str, overrides: &str, data: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [settings, overrides, data] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Outputs are keyed by file name alone so the directory can be moved between runs.
+fn file_name(output: &Path) -> String {
+    output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_unchanged_outputs() {
+        let dir = std::env::temp_dir().join("qrgen_should_match_unchanged_outputs");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("a.svg");
+        fs::write(&output, "<svg/>").unwrap();
+
+        let cache = Cache::load(&dir);
+        let hash = hash("settings", "", "1");
+        assert!(!cache.is_current(&output, &hash));
+        cache.insert(&output, hash.clone());
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir);
+        assert!(cache.is_current(&output, &hash));
+        assert!(!cache.is_current(&output, &super::hash("settings", "", "2")));
+        assert!(!cache.is_current(&output, &super::hash("changed", "", "1")));
+        fs::remove_file(&output).unwrap();
+        assert!(!cache.is_current(&output, &hash));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+// qrcodegen keeps its capacity tables private, so these mirror the ones it uses when choosing a
+// version.
+static ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    // Version: (index 0 is padding)
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // Low
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ], // Medium
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // Quartile
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ], // High
+];
+
+static NUM_ERROR_CORRECTION_BLOCKS: [[u8; 41]; 4] = [
+    // Version: (index 0 is padding)
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ], // Low
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ], // Medium
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ], // Quartile
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ], // High
+];
+
+/// How much of a symbol's data capacity is taken up by its payload, measured in 8-bit codewords.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utilization {
+    pub used: usize,
+    pub capacity: usize,
+}
+
+impl Utilization {
+    pub fn new(qr_code: &qrcodegen::QrCode, segments: &[qrcodegen::QrSegment]) -> Self {
+        let version = qr_code.version();
+
+        Utilization {
+            used: data_bits(segments, version).div_ceil(8),
+            capacity: num_data_codewords(version, qr_code.error_correction_level()),
+        }
+    }
+
+    pub fn percent(&self) -> f64 {
+        self.used as f64 * 100.0 / self.capacity as f64
+    }
+}
+
+fn ordinal(ecc: qrcodegen::QrCodeEcc) -> usize {
+    match ecc {
+        qrcodegen::QrCodeEcc::Low => 0,
+        qrcodegen::QrCodeEcc::Medium => 1,
+        qrcodegen::QrCodeEcc::Quartile => 2,
+        qrcodegen::QrCodeEcc::High => 3,
+    }
+}
+
+/// The number of data bits that can be stored in the given version once all function modules are
+/// excluded.
+fn num_raw_data_modules(version: qrcodegen::Version) -> usize {
+    let ver = usize::from(version.value());
+    let mut result = (16 * ver + 128) * ver + 64;
+
+    if ver >= 2 {
+        let num_align = ver / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if ver >= 7 {
+            result -= 36;
+        }
+    }
+
+    result
+}
+
+/// The number of 8-bit data codewords (i.e. excluding error correction) available in the given
+/// version at the given error correction level.
+pub fn num_data_codewords(version: qrcodegen::Version, ecc: qrcodegen::QrCodeEcc) -> usize {
+    let ver = usize::from(version.value());
+    let ecc = ordinal(ecc);
+
+    num_raw_data_modules(version) / 8
+        - usize::from(ECC_CODEWORDS_PER_BLOCK[ecc][ver])
+            * usize::from(NUM_ERROR_CORRECTION_BLOCKS[ecc][ver])
+}
+
+/// The bit width of the character count field for a segment mode at the given version.
+pub fn num_char_count_bits(mode: qrcodegen::QrSegmentMode, version: qrcodegen::Version) -> usize {
+    let widths = match mode {
+        qrcodegen::QrSegmentMode::Numeric => [10, 12, 14],
+        qrcodegen::QrSegmentMode::Alphanumeric => [9, 11, 13],
+        qrcodegen::QrSegmentMode::Byte => [8, 16, 16],
+        qrcodegen::QrSegmentMode::Kanji => [8, 10, 12],
+        qrcodegen::QrSegmentMode::Eci => [0, 0, 0],
+    };
+
+    widths[usize::from((version.value() + 7) / 17)]
+}
+
+/// The number of bits the given segments occupy at the given version, including mode indicators
+/// and character count fields.
+pub fn data_bits(segments: &[qrcodegen::QrSegment], version: qrcodegen::Version) -> usize {
+    segments
+        .iter()
+        .map(|s| 4 + num_char_count_bits(s.mode(), version) + s.data().len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_published_capacity() {
+        // Values from the QR Code specification's capacity table.
+        let v1 = qrcodegen::Version::new(1);
+        let v40 = qrcodegen::Version::new(40);
+
+        assert_eq!(19, num_data_codewords(v1, qrcodegen::QrCodeEcc::Low));
+        assert_eq!(9, num_data_codewords(v1, qrcodegen::QrCodeEcc::High));
+        assert_eq!(2956, num_data_codewords(v40, qrcodegen::QrCodeEcc::Low));
+        assert_eq!(1276, num_data_codewords(v40, qrcodegen::QrCodeEcc::High));
+    }
+
+    #[test]
+    fn should_calculate_utilization() {
+        let segments = qrcodegen::QrSegment::make_segments("hello");
+        let qr_code = qrcodegen::QrCode::encode_segments_advanced(
+            &segments,
+            qrcodegen::QrCodeEcc::Low,
+            qrcodegen::Version::MIN,
+            qrcodegen::Version::MAX,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 4 bit mode + 8 bit count + 40 bits of data = 52 bits = 7 codewords.
+        let utilization = Utilization::new(&qr_code, &segments);
+        assert_eq!(7, utilization.used);
+        assert_eq!(19, utilization.capacity);
+    }
+}
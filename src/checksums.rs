@@ -0,0 +1,78 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// The SHA-256 of each output written during a run by its path relative to the output directory, so recipients can
+/// check a batch arrived intact with `sha256sum -c`.
+#[derive(Default)]
+pub struct Checksums(Mutex<BTreeMap<String, String>>);
+
+impl Checksums {
+    pub fn add(&self, output: &Path, sum: String) {
+        self.0.lock().unwrap().insert(key(output), sum);
+    }
+
+    /// Give a link the checksum of the output it links to, which has the same contents.
+    pub fn link(&self, target: &Path, original: &Path) {
+        let mut sums = self.0.lock().unwrap();
+        if let Some(sum) = sums.get(&key(original)).cloned() {
+            sums.insert(key(target), sum);
+        }
+    }
+
+    /// Write the checksums in the format of sha256sum, one output to a line in path order.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (output, sum) in self.0.lock().unwrap().iter() {
+            writeln!(writer, "{}  {}", sum, output)?;
+        }
+        writer.flush()
+    }
+}
+
+/// The SHA-256 of the data as lowercase hex.
+pub fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// sha256sum expects forward slashes, even on Windows.
+fn key(output: &Path) -> String {
+    output
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_write_sha256sums() {
+        let path = std::env::temp_dir().join("qrgen_should_write_sha256sums");
+        let checksums = Checksums::default();
+        checksums.add(&Path::new("ab").join("b.svg"), sha256(b"abc"));
+        checksums.add(Path::new("a.svg"), sha256(b""));
+        checksums.link(Path::new("c.svg"), Path::new("a.svg"));
+
+        checksums.write(&path).unwrap();
+
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  a.svg\n\
+             ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  ab/b.svg\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  c.svg\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use crate::record;
+use std::{borrow::Cow, fmt};
+
+/// A column of a CSV or Excel row, selected by its zero-based position or by its name in the header row.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl Column {
+    /// Find the zero-based position of the column, looking names up in the header row of the file.
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<usize, String> {
+        match self {
+            Column::Index(index) => Ok(*index),
+            Column::Name(name) => match headers {
+                Some(headers) => headers
+                    .iter()
+                    .position(|h| h == name)
+                    .ok_or_else(|| format!("There is no column named {} in the header row.", name)),
+                None => Err(format!(
+                    "The column {} can only be found by name when the input has a header row.",
+                    name
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Column::Index(index) => write!(f, "{}", index + 1),
+            Column::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// The columns that hold the file name and the payload of each record, the modification time to give its file and
+/// the QR settings it overrides.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnMap {
+    pub name: Column,
+    pub data: Column,
+    pub mtime: Option<Column>,
+    pub overrides: OverrideColumns,
+}
+
+impl ColumnMap {
+    pub fn new(
+        name: Column,
+        data: Column,
+        mtime: Option<Column>,
+        overrides: OverrideColumns,
+    ) -> Self {
+        ColumnMap {
+            name,
+            data,
+            mtime,
+            overrides,
+        }
+    }
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        ColumnMap::new(
+            Column::Index(0),
+            Column::Index(1),
+            None,
+            OverrideColumns::default(),
+        )
+    }
+}
+
+/// The columns holding QR settings for each record that take precedence over the command line, so one file can mix
+/// e.g. codes printed under a logo that need High error correction with plain ones kept small at Low, short SKUs held
+/// to small versions with long URLs allowed larger ones, SVG for print with PNG for the web, or each label size at its
+/// own scale.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverrideColumns {
+    pub ecc: Option<Column>,
+    pub min: Option<Column>,
+    pub max: Option<Column>,
+    pub mask: Option<Column>,
+    pub format: Option<Column>,
+    pub scale: Option<Column>,
+    pub border: Option<Column>,
+}
+
+impl OverrideColumns {
+    /// Find the zero-based positions of the columns, looking names up in the header row of the file.
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<OverrideFields, String> {
+        let resolve = |column: &Option<Column>| {
+            column
+                .as_ref()
+                .map(|column| column.resolve(headers))
+                .transpose()
+        };
+
+        Ok(OverrideFields {
+            ecc: resolve(&self.ecc)?,
+            min: resolve(&self.min)?,
+            max: resolve(&self.max)?,
+            mask: resolve(&self.mask)?,
+            format: resolve(&self.format)?,
+            scale: resolve(&self.scale)?,
+            border: resolve(&self.border)?,
+        })
+    }
+}
+
+/// The positions of the override columns in the rows of a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OverrideFields {
+    ecc: Option<usize>,
+    min: Option<usize>,
+    max: Option<usize>,
+    mask: Option<usize>,
+    format: Option<usize>,
+    scale: Option<usize>,
+    border: Option<usize>,
+}
+
+impl OverrideFields {
+    /// Read the overrides from a row, given the field at each position or an error when it can't be read as text.
+    /// Empty or missing fields override nothing.
+    pub fn read<'a, F>(&self, field: F) -> Result<record::Overrides, String>
+    where
+        F: Fn(usize) -> Result<Option<Cow<'a, str>>, String>,
+    {
+        let value = |index: Option<usize>| index.map(&field).transpose().map(Option::flatten);
+
+        record::OverrideValues {
+            ecc: value(self.ecc)?,
+            min: value(self.min)?,
+            max: value(self.max)?,
+            mask: value(self.mask)?,
+            format: value(self.format)?,
+            scale: value(self.scale)?,
+            border: value(self.border)?,
+        }
+        .parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exporter;
+
+    #[test]
+    fn should_resolve_index_without_headers() {
+        assert_eq!(Ok(4), Column::Index(4).resolve(None));
+    }
+
+    #[test]
+    fn should_resolve_name_from_headers() {
+        let headers = csv::StringRecord::from(vec!["sku", "batch", "url"]);
+
+        assert_eq!(Ok(2), Column::Name("url".into()).resolve(Some(&headers)));
+        assert_eq!(
+            Err("There is no column named URL in the header row.".to_string()),
+            Column::Name("URL".into()).resolve(Some(&headers))
+        );
+    }
+
+    #[test]
+    fn should_read_overrides_from_their_columns() {
+        let headers = csv::StringRecord::from(vec!["sku", "url", "ecc"]);
+        let fields = OverrideColumns {
+            ecc: Some(Column::Name("ecc".into())),
+            ..Default::default()
+        }
+        .resolve(Some(&headers))
+        .unwrap();
+        let read = |row: Vec<&'static str>| fields.read(|i| Ok(row.get(i).map(|f| Cow::from(*f))));
+
+        assert_eq!(
+            Ok(Some(qrcodegen::QrCodeEcc::High)),
+            read(vec!["a", "https://a", "high"]).map(|o| o.error_correction)
+        );
+        assert_eq!(
+            Ok(record::Overrides::default()),
+            read(vec!["b", "https://b", ""])
+        );
+        assert_eq!(
+            Ok(record::Overrides::default()),
+            read(vec!["c", "https://c"])
+        );
+        assert!(read(vec!["d", "https://d", "extreme"]).is_err());
+    }
+
+    #[test]
+    fn should_read_version_and_mask_overrides() {
+        let fields = OverrideColumns {
+            min: Some(Column::Index(2)),
+            max: Some(Column::Index(3)),
+            mask: Some(Column::Index(4)),
+            ..Default::default()
+        }
+        .resolve(None)
+        .unwrap();
+        let read = |row: Vec<&'static str>| fields.read(|i| Ok(row.get(i).map(|f| Cow::from(*f))));
+
+        let overrides = read(vec!["a", "https://a", "2", "10", "3"]).unwrap();
+        assert_eq!(Some(2), overrides.qr_version_min.map(|v| v.value()));
+        assert_eq!(Some(10), overrides.qr_version_max.map(|v| v.value()));
+        assert_eq!(Some(3), overrides.mask.map(|m| m.value()));
+        assert_eq!(
+            None,
+            read(vec!["b", "https://b", "", "5"])
+                .unwrap()
+                .qr_version_min
+        );
+        assert!(read(vec!["c", "https://c", "41"]).is_err());
+        assert!(read(vec!["d", "https://d", "", "", "8"]).is_err());
+    }
+
+    #[test]
+    fn should_read_format_overrides() {
+        let fields = OverrideColumns {
+            format: Some(Column::Index(2)),
+            ..Default::default()
+        }
+        .resolve(None)
+        .unwrap();
+        let read = |row: Vec<&'static str>| fields.read(|i| Ok(row.get(i).map(|f| Cow::from(*f))));
+
+        assert_eq!(
+            Ok(exporter::Formats::new(vec![
+                exporter::ExportFormat::PNG,
+                exporter::ExportFormat::SVG
+            ])),
+            read(vec!["a", "https://a", "png, svg"]).map(|o| o.formats)
+        );
+        assert_eq!(
+            Ok(None),
+            read(vec!["b", "https://b", ""]).map(|o| o.formats)
+        );
+        assert!(read(vec!["c", "https://c", "bmp"]).is_err());
+    }
+
+    #[test]
+    fn should_read_scale_and_border_overrides() {
+        let fields = OverrideColumns {
+            scale: Some(Column::Index(2)),
+            border: Some(Column::Index(3)),
+            ..Default::default()
+        }
+        .resolve(None)
+        .unwrap();
+        let read = |row: Vec<&'static str>| fields.read(|i| Ok(row.get(i).map(|f| Cow::from(*f))));
+
+        let overrides = read(vec!["a", "https://a", "20", "0"]).unwrap();
+        assert_eq!((Some(20), Some(0)), (overrides.scale, overrides.border));
+        assert!(read(vec!["b", "https://b", "0"]).is_err());
+        assert!(read(vec!["c", "https://c", "", "256"]).is_err());
+    }
+
+    #[test]
+    fn should_need_headers_to_resolve_name() {
+        assert!(Column::Name("url".into()).resolve(None).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// The checksum constants of bech32, used by version 0 witness programs, and bech32m, used by later versions.
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+// The version bytes of mainnet and testnet pay to public key hash and pay to script hash addresses.
+const BASE58_VERSIONS: [u8; 4] = [0x00, 0x05, 0x6f, 0xc4];
+
+/// Check a mainnet or testnet Bitcoin address, either base58 or bech32, including its checksum.
+pub fn is_bitcoin_address(address: &str) -> bool {
+    let lower = address.to_lowercase();
+    if lower.starts_with("bc1") || lower.starts_with("tb1") {
+        is_segwit_address(address)
+    } else {
+        is_base58_address(address)
+    }
+}
+
+fn is_base58_address(address: &str) -> bool {
+    match base58_decode(address) {
+        Some(bytes) if bytes.len() == 25 && BASE58_VERSIONS.contains(&bytes[0]) => {
+            let checksum = Sha256::digest(Sha256::digest(&bytes[..21]));
+            checksum[..4] == bytes[21..]
+        }
+        _ => false,
+    }
+}
+
+fn base58_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for c in text.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for b in bytes.iter_mut().rev() {
+            carry += u32::from(*b) * 58;
+            *b = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, (carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    // Each leading 1 stands for a leading zero byte.
+    let mut decoded = vec![0; text.bytes().take_while(|&c| c == b'1').count()];
+    decoded.extend(bytes);
+    Some(decoded)
+}
+
+fn is_segwit_address(address: &str) -> bool {
+    if address.len() > 90
+        || (address != address.to_lowercase() && address != address.to_uppercase())
+    {
+        return false;
+    }
+
+    let address = address.to_lowercase();
+    let (hrp, data) = match address.rsplit_once('1') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    let data: Option<Vec<u8>> = data
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|&b| b == c).map(|p| p as u8))
+        .collect();
+    let data = match data {
+        // A witness version, a program of 2 to 40 bytes and a 6 character checksum.
+        Some(data) if (hrp == "bc" || hrp == "tb") && data.len() >= 11 => data,
+        _ => return false,
+    };
+
+    let mut values: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|c| c & 31));
+    values.extend(&data);
+
+    let version = data[0];
+    let program_length = (data.len() - 7) * 5 / 8;
+    match (version, bech32_polymod(&values)) {
+        (0, BECH32_CONST) => program_length == 20 || program_length == 32,
+        (1..=16, BECH32M_CONST) => (2..=40).contains(&program_length),
+        _ => false,
+    }
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+
+    values.iter().fold(1, |checksum, &value| {
+        let top = checksum >> 25;
+        let checksum = ((checksum & 0x1ff_ffff) << 5) ^ u32::from(value);
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(checksum, |checksum, (_, g)| checksum ^ g)
+    })
+}
+
+/// Check an Ethereum address is 0x and 40 hex digits, and that the EIP-55 checksum is right when it has mixed case.
+pub fn is_ethereum_address(address: &str) -> bool {
+    let hex = match address.strip_prefix("0x") {
+        Some(hex) if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) => hex,
+        _ => return false,
+    };
+
+    if hex == hex.to_lowercase() || hex == hex.to_uppercase() {
+        return true;
+    }
+
+    let hash = Keccak256::digest(hex.to_lowercase().as_bytes());
+    hex.chars().enumerate().all(|(i, c)| {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf;
+        !c.is_ascii_alphabetic() || c.is_ascii_uppercase() == (nibble >= 8)
+    })
+}
+
+/// Parse a positive decimal amount into its smallest units, e.g. satoshis for 8 decimal places, None if it has more
+/// decimal places than that or is too large.
+pub fn parse_units(amount: &str, decimals: usize) -> Option<u128> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || fraction.len() > decimals
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let whole: u128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: u128 = format!("{:0<width$}", fraction, width = decimals)
+        .parse()
+        .ok()?;
+
+    whole
+        .checked_mul(10u128.pow(decimals as u32))?
+        .checked_add(fraction)
+        .filter(|&units| units > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_check_base58_addresses() {
+        assert!(is_bitcoin_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"));
+        assert!(is_bitcoin_address("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"));
+        assert!(!is_bitcoin_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"));
+        assert!(!is_bitcoin_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN0"));
+    }
+
+    #[test]
+    fn should_check_segwit_addresses() {
+        assert!(is_bitcoin_address(
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+        ));
+        assert!(is_bitcoin_address(
+            "BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ"
+        ));
+        assert!(is_bitcoin_address(
+            "bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297"
+        ));
+        assert!(!is_bitcoin_address(
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdp"
+        ));
+        assert!(!is_bitcoin_address(
+            "bc1Qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"
+        ));
+    }
+
+    #[test]
+    fn should_check_ethereum_addresses() {
+        assert!(is_ethereum_address(
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        ));
+        assert!(is_ethereum_address(
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        ));
+        assert!(!is_ethereum_address(
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"
+        ));
+        assert!(!is_ethereum_address(
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea"
+        ));
+        assert!(!is_ethereum_address(
+            "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        ));
+    }
+
+    #[test]
+    fn should_parse_units() {
+        assert_eq!(Some(150_000_000), parse_units("1.5", 8));
+        assert_eq!(Some(1), parse_units("0.00000001", 8));
+        assert_eq!(Some(10u128.pow(17)), parse_units(".1", 18));
+        assert_eq!(None, parse_units("0.000000001", 8));
+        assert_eq!(None, parse_units("0", 8));
+        assert_eq!(None, parse_units("1e3", 8));
+        assert_eq!(None, parse_units(".", 8));
+    }
+}
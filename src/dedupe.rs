@@ -0,0 +1,182 @@
+use crate::exporter;
+use crate::record::Record;
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// What happens to a record whose data is the same as an earlier record in the run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dedupe {
+    /// Don't generate an output for the record.
+    Skip,
+    /// Hard link the output of the earlier record under the record's own name.
+    Link,
+}
+
+/// A record that was not generated because an earlier record had the same data.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Duplicate {
+    pub input: String,
+    pub row: u64,
+    pub name: String,
+    pub duplicate_of: String,
+    pub output: Option<String>,
+}
+
+/// Tracks the data seen during a run and the records that repeated it.
+#[derive(Default)]
+pub struct Deduper {
+    seen: Mutex<HashMap<String, String>>,
+    outputs: Mutex<HashMap<String, Vec<PathBuf>>>,
+    duplicates: Mutex<Vec<Duplicate>>,
+}
+
+impl Deduper {
+    /// Returns true the first time the record's data is seen, otherwise remembers the record as a duplicate.
+    pub fn is_first(&self, input: &Path, record: &Record) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+
+        match seen.get(&record.data) {
+            Some(original) => {
+                info!("{} has the same data as {}", record.name, original);
+                self.duplicates.lock().unwrap().push(Duplicate {
+                    input: input.display().to_string(),
+                    row: record.row,
+                    name: record.name.clone(),
+                    duplicate_of: original.clone(),
+                    output: None,
+                });
+                false
+            }
+            None => {
+                seen.insert(record.data.clone(), record.name.clone());
+                true
+            }
+        }
+    }
+
+    /// Remember where an output for a name was written so duplicates can be linked to it, once for each format.
+    pub fn add_output(&self, name: &str, output: &Path) {
+        self.outputs
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .push(output.to_path_buf());
+    }
+
+    /// Hard link the output of each original record under the names of its duplicates, next to the original.
+    /// Returns each link made along with the output it links to.
+    pub fn link(&self) -> Vec<(PathBuf, PathBuf)> {
+        let outputs = self.outputs.lock().unwrap();
+        let mut links = Vec::new();
+
+        for duplicate in self.duplicates.lock().unwrap().iter_mut() {
+            let originals = match outputs.get(&duplicate.duplicate_of) {
+                Some(originals) => originals,
+                None => continue,
+            };
+
+            for original in originals {
+                let mut target = original.with_file_name(&duplicate.name);
+                if let Some(extension) = original.extension() {
+                    target.set_extension(extension);
+                }
+
+                if &target == original {
+                    continue;
+                }
+
+                // Replace anything already there, the same as generating the file would.
+                let _ = fs::remove_file(exporter::long_path(&target));
+                match fs::hard_link(exporter::long_path(original), exporter::long_path(&target)) {
+                    // The report lists the link for the main format, the first written.
+                    Ok(_) => {
+                        if duplicate.output.is_none() {
+                            duplicate.output = Some(target.display().to_string());
+                        }
+                        links.push((target, original.clone()));
+                    }
+                    Err(e) => warn!(
+                        "error linking {} to {} {:?}",
+                        target.display(),
+                        original.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        links
+    }
+
+    pub fn count(&self) -> usize {
+        self.duplicates.lock().unwrap().len()
+    }
+
+    /// Write the duplicates to the given path, as a JSON array when it ends in .json and CSV otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let duplicates = self.duplicates.lock().unwrap();
+        let file = File::create(path)?;
+
+        if path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("json"))
+            .unwrap_or(false)
+        {
+            serde_json::to_writer_pretty(file, &*duplicates)?;
+        } else {
+            let mut writer = csv::Writer::from_writer(file);
+            for duplicate in duplicates.iter() {
+                writer.serialize(duplicate)?;
+            }
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_remember_duplicates() {
+        let deduper = Deduper::default();
+        let input = Path::new("in.csv");
+
+        assert!(deduper.is_first(input, &Record::new(1, "a".into(), "x".into())));
+        assert!(deduper.is_first(input, &Record::new(2, "b".into(), "y".into())));
+        assert!(!deduper.is_first(input, &Record::new(3, "c".into(), "x".into())));
+
+        let duplicates = deduper.duplicates.lock().unwrap();
+        assert_eq!(1, duplicates.len());
+        assert_eq!("c", duplicates[0].name);
+        assert_eq!("a", duplicates[0].duplicate_of);
+        assert_eq!(3, duplicates[0].row);
+    }
+
+    #[test]
+    fn should_link_duplicates_to_original() {
+        let dir = std::env::temp_dir().join("qrgen_should_link_duplicates_to_original");
+        fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("a.svg");
+        fs::write(&original, "<svg/>").unwrap();
+
+        let deduper = Deduper::default();
+        deduper.is_first(&dir, &Record::new(1, "a".into(), "x".into()));
+        deduper.is_first(&dir, &Record::new(2, "b".into(), "x".into()));
+        deduper.add_output("a", &original);
+        deduper.link();
+
+        assert_eq!("<svg/>", fs::read_to_string(dir.join("b.svg")).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
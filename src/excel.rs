@@ -0,0 +1,67 @@
+use calamine::{open_workbook_auto, Data, Reader};
+use std::{error::Error, path::Path};
+
+/// Read the rows of a worksheet as CSV records, using the first sheet in the workbook unless one is named.  Each
+/// record's position holds its 1-based row number in the sheet.
+pub fn read_rows(
+    path: &Path,
+    sheet: Option<&str>,
+) -> Result<impl Iterator<Item = csv::StringRecord>, Box<dyn Error>> {
+    let mut workbook = open_workbook_auto(path)?;
+
+    let range = match sheet {
+        Some(name) => workbook.worksheet_range(name)?,
+        None => workbook
+            .worksheet_range_at(0)
+            .ok_or("the workbook does not contain any sheets")??,
+    };
+
+    let first_row = range.start().map_or(0, |(row, _)| u64::from(row));
+
+    let records: Vec<csv::StringRecord> = range
+        .rows()
+        .enumerate()
+        .map(|(i, cells)| to_record(cells, first_row + i as u64 + 1))
+        .collect();
+
+    Ok(records.into_iter())
+}
+
+fn to_record(cells: &[Data], line: u64) -> csv::StringRecord {
+    let mut record: csv::StringRecord = cells.iter().map(|c| c.to_string()).collect();
+    record.trim();
+
+    let mut position = csv::Position::new();
+    position.set_line(line);
+    record.set_position(Some(position));
+
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_cells_to_record() {
+        let cells = vec![
+            Data::String(" name ".into()),
+            Data::Float(9998819191.0),
+            Data::Int(7),
+            Data::Empty,
+        ];
+
+        let record = to_record(&cells, 3);
+
+        assert_eq!(
+            csv::StringRecord::from(vec!["name", "9998819191", "7", ""]),
+            record
+        );
+        assert_eq!(Some(3), record.position().map(|p| p.line()));
+    }
+
+    #[test]
+    fn should_error_for_missing_workbook() {
+        assert!(read_rows(Path::new("does/not/exist.xlsx"), None).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bad arguments, or another error that stops qrgen, e.g. a payload that fails to verify.
+pub const ERROR: i32 = 1;
+
+/// The kinds of failure during a run, from the least to the most serious.  Each is the exit code used when it is the
+/// most serious failure of the run, so wrapper scripts can branch on the kind of failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Failure {
+    /// A record was skipped or its QR Code couldn't be generated.
+    Record = 2,
+    /// An input file couldn't be opened or read to the end.
+    Input = 3,
+    /// A QR Code, report or mapping file couldn't be written.
+    Output = 4,
+}
+
+const FAILURES: [Failure; 3] = [Failure::Record, Failure::Input, Failure::Output];
+
+impl Failure {
+    fn index(self) -> usize {
+        self as usize - Failure::Record as usize
+    }
+}
+
+/// A count of each kind of failure over a run.
+#[derive(Debug, Default)]
+pub struct Failures([AtomicU64; 3]);
+
+impl Failures {
+    pub fn add(&self, failure: Failure) {
+        self.0[failure.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, failure: Failure) -> u64 {
+        self.0[failure.index()].load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> u64 {
+        FAILURES.iter().map(|failure| self.get(*failure)).sum()
+    }
+
+    /// The most serious kind of failure seen, if any.
+    pub fn worst(&self) -> Option<Failure> {
+        FAILURES
+            .iter()
+            .rev()
+            .copied()
+            .find(|failure| self.get(*failure) > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_report_worst_failure() {
+        let failures = Failures::default();
+        assert_eq!(None, failures.worst());
+
+        failures.add(Failure::Record);
+        failures.add(Failure::Record);
+        assert_eq!(Some(Failure::Record), failures.worst());
+
+        failures.add(Failure::Input);
+        assert_eq!(Some(Failure::Input), failures.worst());
+        assert_eq!(3, failures.total());
+        assert_eq!(3, failures.worst().unwrap() as i32);
+    }
+}
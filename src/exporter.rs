@@ -1,30 +1,116 @@
+use crate::record;
 use itertools::Itertools;
 use log::trace;
 use std::convert::TryFrom;
-use std::{error::Error, fs::OpenOptions, io::prelude::*, path::PathBuf};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub enum ExportFormat {
+    #[default]
     SVG,
     PNG,
+    ICO,
+    AVIF,
+    GIF,
 }
 
-impl Default for ExportFormat {
+/// The sizes in pixels of the images in an ICO, from the smallest favicon browsers show to the largest Windows uses.
+pub const ICO_SIZES: [u32; 6] = [16, 32, 48, 64, 128, 256];
+
+// Quality from 1 to 100 and speed from 1, slowest and smallest, to 10.  QR Codes are flat colour, so a fast speed
+// costs little in size.
+const AVIF_QUALITY: f32 = 90.0;
+const AVIF_SPEED: u8 = 8;
+
+impl ExportFormat {
+    /// The file extension of outputs in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::SVG => "svg",
+            ExportFormat::PNG => "png",
+            ExportFormat::ICO => "ico",
+            ExportFormat::AVIF => "avif",
+            ExportFormat::GIF => "gif",
+        }
+    }
+}
+
+/// The formats each QR Code is written in, at least one and each only once.  The first is the main format, used where
+/// only one can be, e.g. for the extension in a mapping file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formats(Vec<ExportFormat>);
+
+impl Formats {
+    /// The formats in the order given with any repeats left out, None if there are none.
+    pub fn new(formats: Vec<ExportFormat>) -> Option<Self> {
+        let formats: Vec<_> = formats.into_iter().unique().collect();
+        (!formats.is_empty()).then_some(Formats(formats))
+    }
+
+    pub fn first(&self) -> ExportFormat {
+        self.0[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ExportFormat> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// The longest extension of the formats, so a name cut short to fit is short enough for every one.
+    pub fn longest_extension(&self) -> &'static str {
+        self.iter()
+            .map(ExportFormat::extension)
+            .max_by_key(|extension| extension.len())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Formats {
     fn default() -> Self {
-        ExportFormat::SVG
+        ExportFormat::default().into()
+    }
+}
+
+impl From<ExportFormat> for Formats {
+    fn from(format: ExportFormat) -> Self {
+        Formats(vec![format])
     }
 }
 
+/// What happens when a QR Code's file already exists in the output directory.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum OnExists {
+    /// Leave the existing file and don't write the QR Code.
+    Skip,
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Fail the record, leaving the existing file.
+    Error,
+    /// Write the QR Code under the first free name with a number added, e.g. A-1-1.svg.
+    Rename,
+}
+
 pub struct Exporter {
     qr_code: qrcodegen::QrCode,
     output: PathBuf,
     border: u8,
     format: ExportFormat,
     file_name: String,
-    scale: u8,
+    scale: u32,
     no_rect: bool,
     foreground: (u8, u8, u8),
     background: (u8, u8, u8),
+    frames: Vec<qrcodegen::QrCode>,
+    interval: Duration,
 }
 
 impl Exporter {
@@ -34,7 +120,7 @@ impl Exporter {
         border: u8,
         format: ExportFormat,
         file_name: String,
-        scale: u8,
+        scale: u32,
         no_rect: bool,
         foreground: (u8, u8, u8),
         background: (u8, u8, u8),
@@ -49,37 +135,50 @@ impl Exporter {
             no_rect,
             foreground,
             background,
+            frames: Vec::new(),
+            interval: Duration::ZERO,
         }
     }
 
-    pub fn export(&mut self) -> Result<(), Box<dyn Error>> {
-        self.output.push(&self.file_name);
+    /// Draw and write the same QR Code in another format.
+    pub fn set_format(&mut self, format: ExportFormat) {
+        self.format = format;
+    }
 
-        match self.format {
-            ExportFormat::SVG => {
-                self.output.set_extension("svg");
-                trace!("Writing svg file {}", self.output.display());
-            }
-            ExportFormat::PNG => {
-                self.output.set_extension("png");
-                trace!("Writing png file {}", self.output.display());
-            }
-        }
+    /// Show the other QR Codes of a structured append sequence after this one in a GIF, each for the interval.
+    pub fn set_frames(&mut self, frames: Vec<qrcodegen::QrCode>, interval: Duration) {
+        self.frames = frames;
+        self.interval = interval;
+    }
 
-        let mut writer = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(false)
-            .open(&self.output)?;
+    /// Draw the QR Code as the contents of an SVG, PNG, ICO, AVIF or GIF file.
+    pub fn render(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut image = Vec::new();
 
         match self.format {
-            ExportFormat::SVG => self.export_svg(&mut writer, &self.qr_code, self.border),
+            ExportFormat::SVG => self.export_svg(&mut image, &self.qr_code, self.border),
             ExportFormat::PNG => {
-                self.export_png(&mut writer, &self.qr_code, self.border, self.scale)
+                self.export_png(&mut image, &self.qr_code, self.border, self.scale)
+            }
+            ExportFormat::ICO => self.export_ico(&mut image, &self.qr_code, self.border),
+            ExportFormat::AVIF => {
+                self.export_avif(&mut image, &self.qr_code, self.border, self.scale)
             }
+            ExportFormat::GIF => self.export_gif(&mut image, self.border, self.scale),
         }?;
 
-        Ok(())
+        Ok(image)
+    }
+
+    /// The path the image is written to, the file name in the output directory with the format's extension.
+    pub fn path(&self) -> PathBuf {
+        output_path(&self.output, &self.file_name, self.format)
+    }
+
+    /// Write a rendered image to the output directory, returning the path of the file written or None if it was
+    /// skipped because a file was already there.
+    pub fn write(&self, image: &[u8], on_exists: OnExists) -> io::Result<Option<PathBuf>> {
+        write_file(&self.path(), image, on_exists)
     }
 
     fn export_svg<W: Write>(
@@ -154,88 +253,218 @@ impl Exporter {
         writer: &mut W,
         qr_code: &qrcodegen::QrCode,
         border: u8,
-        scale: u8,
+        scale: u32,
     ) -> Result<(), Box<dyn Error>> {
-        // Make everything a bit simpler to work with.
-        let scale = i32::from(scale);
+        let (size, data) = self.rgb_pixels(qr_code, border, scale)?;
+
+        let mut encoder = png::Encoder::new(writer, size, size);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    /// Writes an AVIF of the same pixels as the PNG.  AV1 is lossy, so it is encoded at a high quality to keep the
+    /// edges of the modules sharp enough to scan.
+    fn export_avif<W: Write>(
+        &self,
+        writer: &mut W,
+        qr_code: &qrcodegen::QrCode,
+        border: u8,
+        scale: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let (size, data) = self.rgb_pixels(qr_code, border, scale)?;
+        let pixels: Vec<_> = data
+            .chunks_exact(3)
+            .map(|rgb| ravif::RGB8::new(rgb[0], rgb[1], rgb[2]))
+            .collect();
+
+        let avif = ravif::Encoder::new()
+            .with_quality(AVIF_QUALITY)
+            .with_speed(AVIF_SPEED)
+            .with_bit_depth(ravif::BitDepth::Eight)
+            .encode_rgb(ravif::Img::new(
+                pixels.as_slice(),
+                size as usize,
+                size as usize,
+            ))?;
+
+        writer.write_all(&avif.avif_file)?;
+        Ok(())
+    }
+
+    /// Writes a GIF of the same pixels as the PNG, animated through the frames when there are any so a scanner that
+    /// supports structured append can read the whole sequence from one file.  Without the background rectangle the
+    /// background is transparent.
+    fn export_gif<W: Write>(
+        &self,
+        writer: &mut W,
+        border: u8,
+        scale: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let (size, _) = self.rgb_pixels(&self.qr_code, border, scale)?;
+        let size = u16::try_from(size).map_err(|_| "size is too big for a GIF.")?;
+        let palette = [
+            self.background.0,
+            self.background.1,
+            self.background.2,
+            self.foreground.0,
+            self.foreground.1,
+            self.foreground.2,
+        ];
+        // GIF delays are in hundredths of a second.
+        let delay = u16::try_from(self.interval.as_millis() / 10).unwrap_or(u16::MAX);
+
+        let mut encoder = gif::Encoder::new(writer, size, size, &palette)?;
+        if !self.frames.is_empty() {
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+        }
+        for qr_code in std::iter::once(&self.qr_code).chain(&self.frames) {
+            let (_, data) = self.rgb_pixels(qr_code, border, scale)?;
+            let foreground = [self.foreground.0, self.foreground.1, self.foreground.2];
+            let indices: Vec<u8> = data
+                .chunks_exact(3)
+                .map(|rgb| u8::from(rgb == foreground))
+                .collect();
+
+            encoder.write_frame(&gif::Frame {
+                width: size,
+                height: size,
+                delay,
+                transparent: self.no_rect.then_some(0),
+                buffer: Cow::Owned(indices),
+                ..Default::default()
+            })?;
+        }
+
+        encoder.into_inner()?;
+        Ok(())
+    }
+
+    /// The RGB pixels of the QR Code at the scale, row by row, and the number of pixels across.
+    fn rgb_pixels(
+        &self,
+        qr_code: &qrcodegen::QrCode,
+        border: u8,
+        scale: u32,
+    ) -> Result<(u32, Vec<u8>), Box<dyn Error>> {
+        // Make everything a bit simpler to work with, a scale too big for an i32 is too big for any image.
+        let scale = i32::try_from(scale).ok();
         let border = i32::from(border);
 
         // Set the colour type and get the samples per pixel.
-        let colour_type = png::ColorType::Rgb;
-        let colour_type_samples = colour_type.samples();
+        let colour_type_samples = png::ColorType::Rgb.samples();
 
         // Get the size of the code.
-        let size = Some(qr_code.size()).checked_size(scale, border);
+        let size = scale.and_then(|scale| Some(qr_code.size()).checked_size(scale, border));
 
         // Multiple by the colour sample length.
         let data_length = size.checked_length(colour_type_samples);
 
-        if size.is_some() && data_length.is_some() {
-            // Both are some, so this is OK.
-            let size = size.unwrap();
-            let data_length = data_length.unwrap();
-
-            let mut encoder = png::Encoder::new(writer, size as u32, size as u32);
-            encoder.set_color(colour_type);
-            encoder.set_depth(png::BitDepth::Eight);
+        let (scale, size, data_length) = match (scale, size, data_length) {
+            (Some(scale), Some(size), Some(data_length)) => (scale, size, data_length),
+            _ => Err("size or data length are out of bounds.")?,
+        };
 
-            let mut writer = encoder.write_header()?;
-            let mut data = vec![255_u8; data_length as usize];
+        let mut data = vec![255_u8; data_length as usize];
 
-            if self.background != (255, 255, 255) {
-                trace!("Setting background {:?}", self.background);
-                for chunk in data.chunks_exact_mut(3) {
-                    chunk[0] = self.background.0;
-                    chunk[1] = self.background.1;
-                    chunk[2] = self.background.2;
-                }
+        if self.background != (255, 255, 255) {
+            trace!("Setting background {:?}", self.background);
+            for chunk in data.chunks_exact_mut(3) {
+                chunk[0] = self.background.0;
+                chunk[1] = self.background.1;
+                chunk[2] = self.background.2;
             }
+        }
 
-            trace!(
-                "version = {:?}, errorcorrectionlevel = {:?}, mask = {:?}, size = {}, data length = {}",
-                qr_code.version().value(),
-                match qr_code.error_correction_level() {
-                    qrcodegen::QrCodeEcc::High => "High",
-                    qrcodegen::QrCodeEcc::Low => "Low",
-                    qrcodegen::QrCodeEcc::Quartile => "Quartile",
-                    qrcodegen::QrCodeEcc::Medium => "Medium",
-                },
-                qr_code.mask().value(),
-                size,
-                data_length,
-            );
+        trace!(
+            "version = {:?}, errorcorrectionlevel = {:?}, mask = {:?}, size = {}, data length = {}",
+            qr_code.version().value(),
+            match qr_code.error_correction_level() {
+                qrcodegen::QrCodeEcc::High => "High",
+                qrcodegen::QrCodeEcc::Low => "Low",
+                qrcodegen::QrCodeEcc::Quartile => "Quartile",
+                qrcodegen::QrCodeEcc::Medium => "Medium",
+            },
+            qr_code.mask().value(),
+            size,
+            data_length,
+        );
 
-            let offset_fn = |x: i32, y: i32, s: i32, cts: usize| -> usize {
-                (x as usize * cts) + (y as usize * (s as usize * cts))
-            };
+        let offset_fn = |x: i32, y: i32, s: i32, cts: usize| -> usize {
+            (x as usize * cts) + (y as usize * (s as usize * cts))
+        };
+
+        // this does not combine with itself so zip with (size,size).
+        let points = (0..size)
+            .tuple_combinations::<(_, _)>()
+            .chain((0..size).zip(0..size));
 
-            // this does not combine with itself so zip with (size,size).
-            let points = (0..size)
-                .tuple_combinations::<(_, _)>()
-                .chain((0..size).zip(0..size));
+        for point in points {
+            let offset_yx = offset_fn(point.1, point.0, size, colour_type_samples);
+            let offset_xy = offset_fn(point.0, point.1, size, colour_type_samples);
 
-            for point in points {
-                let offset_yx = offset_fn(point.1, point.0, size, colour_type_samples);
-                let offset_xy = offset_fn(point.0, point.1, size, colour_type_samples);
+            if qr_code.get_module(point.1 / scale - border, point.0 / scale - border) {
+                data[offset_yx] = self.foreground.0;
+                data[offset_yx + 1] = self.foreground.1;
+                data[offset_yx + 2] = self.foreground.2;
+            }
+
+            if qr_code.get_module(point.0 / scale - border, point.1 / scale - border) {
+                data[offset_xy] = self.foreground.0;
+                data[offset_xy + 1] = self.foreground.1;
+                data[offset_xy + 2] = self.foreground.2;
+            }
+        }
+
+        Ok((size as u32, data))
+    }
+
+    /// Writes an ICO with an image of the QR Code at each of the ICO sizes, drawn at the largest whole number of
+    /// pixels to a module that fits and centred, so the modules stay sharp.  Sizes smaller than the QR Code sample its
+    /// modules instead.  Without the background rectangle the background is transparent.
+    fn export_ico<W: Write>(
+        &self,
+        writer: &mut W,
+        qr_code: &qrcodegen::QrCode,
+        border: u8,
+    ) -> Result<(), Box<dyn Error>> {
+        let modules = qr_code.size() + 2 * i32::from(border);
+        let background = if self.no_rect {
+            [0, 0, 0, 0]
+        } else {
+            [self.background.0, self.background.1, self.background.2, 255]
+        };
+        let foreground = [self.foreground.0, self.foreground.1, self.foreground.2, 255];
+        let mut icon = ico::IconDir::new(ico::ResourceType::Icon);
 
-                if qr_code.get_module(point.1 / scale - border, point.0 / scale - border) {
-                    data[offset_yx] = self.foreground.0;
-                    data[offset_yx + 1] = self.foreground.1;
-                    data[offset_yx + 2] = self.foreground.2;
+        for size in ICO_SIZES {
+            let size = size as i32;
+            let scale = size / modules;
+            let offset = (size - scale * modules) / 2;
+            let module = |pixel: i32| {
+                if scale > 0 {
+                    (pixel - offset).div_euclid(scale) - i32::from(border)
+                } else {
+                    pixel * modules / size - i32::from(border)
                 }
+            };
 
-                if qr_code.get_module(point.0 / scale - border, point.1 / scale - border) {
-                    data[offset_xy] = self.foreground.0;
-                    data[offset_xy + 1] = self.foreground.1;
-                    data[offset_xy + 2] = self.foreground.2;
+            let mut data = Vec::with_capacity((size * size * 4) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    let dark = qr_code.get_module(module(x), module(y));
+                    data.extend_from_slice(if dark { &foreground } else { &background });
                 }
             }
 
-            writer.write_image_data(&data)?
-        } else {
-            Err("size or data length are out of bounds.")?
+            let image = ico::IconImage::from_rgba_data(size as u32, size as u32, data);
+            icon.add_entry(ico::IconDirEntry::encode(&image)?);
         }
 
+        icon.write(writer)?;
         Ok(())
     }
 }
@@ -263,6 +492,179 @@ impl Checked for Option<i32> {
     }
 }
 
+/// The path of the file for the named QR Code in the output directory.
+pub fn output_path(output: &Path, file_name: &str, format: ExportFormat) -> PathBuf {
+    let mut path = output.join(file_name);
+    path.set_extension(format.extension());
+    path
+}
+
+/// The most levels of shard directories, one for each pair of hex digits in the hash of a name.
+pub const MAX_SHARD_LEVELS: u8 = 8;
+
+/// The subdirectories a named file is spread into when sharding, one level for each pair of hex digits of the hash of
+/// its name, e.g. ab/cd for two levels.
+pub fn shard_dir(name: &str, levels: u8) -> PathBuf {
+    let hash = record::hash_name(name);
+    (0..usize::from(levels.min(MAX_SHARD_LEVELS)))
+        .map(|level| &hash[level * 2..level * 2 + 2])
+        .collect()
+}
+
+// Windows refuses paths of this many characters or more, counting the terminating null, unless they start with \\?\.
+const MAX_PATH: usize = 260;
+
+/// The path in a form Windows can open when it is too long for the usual limit, absolute and starting with \\?\, so
+/// deep output trees work.  Elsewhere, and for shorter paths, the path is unchanged.
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) || path.as_os_str().len() < MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+
+    match std::path::absolute(path) {
+        Ok(absolute) => Cow::Owned(extended(&absolute).unwrap_or(absolute)),
+        Err(_) => Cow::Borrowed(path),
+    }
+}
+
+// The absolute Windows path, e.g. C:\out\a.svg or \\server\share\a.svg, with the prefix that lifts the length limit.
+fn extended(absolute: &Path) -> Option<PathBuf> {
+    let absolute = absolute.to_str()?;
+    Some(PathBuf::from(if absolute.starts_with(r"\\?\") {
+        absolute.to_string()
+    } else if let Some(share) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", absolute)
+    }))
+}
+
+/// Write an image to the given path, following the policy when a file is already there.  Returns the path written,
+/// which is numbered when renamed, or None when skipped.
+pub fn write_file(path: &Path, image: &[u8], on_exists: OnExists) -> io::Result<Option<PathBuf>> {
+    trace!("Writing file {}", path.display());
+
+    let mut options = OpenOptions::new();
+    match on_exists {
+        OnExists::Overwrite => options.write(true).create(true).truncate(true),
+        _ => options.write(true).create_new(true),
+    };
+
+    create(path, on_exists, |path| {
+        options.open(long_path(path))?.write_all(image)
+    })
+}
+
+/// Hard link the path to an identical output written earlier, following the policy when a file is already there the
+/// same as write_file.  A file being replaced is removed first so the output linked to is left as it is.
+pub fn link_file(path: &Path, original: &Path, on_exists: OnExists) -> io::Result<Option<PathBuf>> {
+    trace!("Linking file {} to {}", path.display(), original.display());
+
+    create(path, on_exists, |path| {
+        if on_exists == OnExists::Overwrite {
+            remove_file(path)?;
+        }
+        fs::hard_link(long_path(original), long_path(path))
+    })
+}
+
+/// Write an image to a hidden file beside the given path, to be moved into place with move_file once it's complete,
+/// so a write that is given up on part way leaves no output behind.  Returns the path of the hidden file.
+pub fn write_temp_file(path: &Path, image: &[u8]) -> io::Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    trace!("Writing file {}", temp.display());
+
+    fs::write(long_path(&temp), image).inspect_err(|_| {
+        let _ = remove_file(&temp);
+    })?;
+    Ok(temp)
+}
+
+/// Move a file written by write_temp_file to the given path, following the policy when a file is already there the
+/// same as write_file.  The hidden file is removed when it isn't moved.
+pub fn move_file(temp: &Path, path: &Path, on_exists: OnExists) -> io::Result<Option<PathBuf>> {
+    let moved = create(path, on_exists, |path| {
+        if on_exists != OnExists::Overwrite {
+            // Claim the name first, as moving the file there would replace one already there.
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(long_path(path))?;
+        }
+        fs::rename(long_path(temp), long_path(path)).inspect_err(|_| {
+            if on_exists != OnExists::Overwrite {
+                let _ = remove_file(path);
+            }
+        })
+    });
+
+    if !matches!(moved, Ok(Some(_))) {
+        remove_file(temp)?;
+    }
+    moved
+}
+
+/// Remove the file if it is there, e.g. so writing an output can't change another one hard linked to it.
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(long_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Create the file with the function, which fails with AlreadyExists when there is a file in the way, trying numbered
+// names in turn when renaming.
+fn create(
+    original: &Path,
+    on_exists: OnExists,
+    mut create: impl FnMut(&Path) -> io::Result<()>,
+) -> io::Result<Option<PathBuf>> {
+    let mut path = original.to_path_buf();
+    let mut number = 0;
+    loop {
+        match create(&path) {
+            Ok(_) => return Ok(Some(path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match on_exists {
+                OnExists::Skip => return Ok(None),
+                OnExists::Rename => {
+                    number += 1;
+                    path = numbered(original, number);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!("{} already exists", path.display()),
+                    ))
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// The path with a number added to the end of the file name, e.g. A-1.svg becomes A-1-2.svg.
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut name = format!("{}-{}", stem, number);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,14 +672,14 @@ mod tests {
     #[test]
     fn checked_length_should_return_none_for_large_colour_depth() {
         let s = Some(1_i32);
-        let res = s.checked_length(usize::max_value());
+        let res = s.checked_length(usize::MAX);
 
         assert_eq!(None, res);
     }
 
     #[test]
     fn checked_length_should_return_none_for_large_self() {
-        let s = Some(i32::max_value());
+        let s = Some(i32::MAX);
         let res = s.checked_length(2_usize);
 
         assert_eq!(None, res);
@@ -286,7 +688,7 @@ mod tests {
     #[test]
     fn checked_length_should_return_none() {
         let s = Some(22);
-        let res = s.checked_length(i32::max_value() as usize);
+        let res = s.checked_length(i32::MAX as usize);
 
         assert_eq!(None, res);
     }
@@ -294,15 +696,15 @@ mod tests {
     #[test]
     fn checked_size_should_return_none_for_large_border() {
         let s = Some(1_i32);
-        let res = s.checked_size(1_i32, i32::max_value());
+        let res = s.checked_size(1_i32, i32::MAX);
 
         assert_eq!(None, res);
     }
 
     #[test]
     fn checked_size_should_return_none_for_large_add() {
-        let s = Some(i32::max_value());
-        let res = s.checked_size(1_i32, i32::max_value() - 1);
+        let s = Some(i32::MAX);
+        let res = s.checked_size(1_i32, i32::MAX - 1);
 
         assert_eq!(None, res);
     }
@@ -310,7 +712,7 @@ mod tests {
     #[test]
     fn checked_size_should_return_none_for_large_scale() {
         let s = Some(2);
-        let res = s.checked_size(i32::max_value(), 2);
+        let res = s.checked_size(i32::MAX, 2);
 
         assert_eq!(None, res);
     }
@@ -782,4 +1184,285 @@ mod tests {
             buffer
         );
     }
+
+    #[test]
+    fn should_write_avif_to_buffer() {
+        // Arrange.
+        let mut buffer: Vec<u8> = Vec::new();
+        let qr_code = qrcodegen::QrCode::encode_text("ha", qrcodegen::QrCodeEcc::Low).unwrap();
+        let exp = Exporter::new(
+            qr_code,
+            PathBuf::new(),
+            4,
+            ExportFormat::AVIF,
+            "".into(),
+            4,
+            false,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+
+        // Act.
+        exp.export_avif(&mut buffer, &exp.qr_code, exp.border, exp.scale)
+            .unwrap();
+
+        // Assert.
+        assert_eq!(b"ftypavif", &buffer[4..12]);
+        let (size, pixels) = exp.rgb_pixels(&exp.qr_code, exp.border, exp.scale).unwrap();
+        assert_eq!(116, size);
+        assert!(buffer.len() < pixels.len() / 10);
+    }
+
+    #[test]
+    fn should_write_gif_frames_for_each_part() {
+        // Arrange.
+        let mut buffer: Vec<u8> = Vec::new();
+        let qr_code =
+            |text| qrcodegen::QrCode::encode_text(text, qrcodegen::QrCodeEcc::Low).unwrap();
+        let mut exp = Exporter::new(
+            qr_code("ha"),
+            PathBuf::new(),
+            4,
+            ExportFormat::GIF,
+            "".into(),
+            2,
+            true,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+        exp.set_frames(vec![qr_code("he")], Duration::from_millis(750));
+
+        // Act.
+        exp.export_gif(&mut buffer, exp.border, exp.scale).unwrap();
+
+        // Assert.
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(io::Cursor::new(buffer)).unwrap();
+        assert_eq!((58, 58), (decoder.width(), decoder.height()));
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((75, Some(0)), (frame.delay, frame.transparent));
+            frames.push(frame.buffer.to_vec());
+        }
+        assert_eq!(2, frames.len());
+        // The border is light and the top left finder pattern inside it dark.
+        assert_eq!([0, 1], [frames[0][0], frames[0][8 * 58 + 8]]);
+        assert_ne!(frames[0], frames[1]);
+    }
+
+    #[test]
+    fn should_draw_pixels_at_a_scale_above_255() {
+        // Arrange.
+        let qr_code = qrcodegen::QrCode::encode_text("ha", qrcodegen::QrCodeEcc::Low).unwrap();
+        let exp = Exporter::new(
+            qr_code,
+            PathBuf::new(),
+            0,
+            ExportFormat::PNG,
+            "".into(),
+            300,
+            false,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+
+        // Act.
+        let (size, pixels) = exp.rgb_pixels(&exp.qr_code, exp.border, exp.scale).unwrap();
+
+        // Assert.
+        assert_eq!(21 * 300, size);
+        assert_eq!(3 * 6300 * 6300, pixels.len());
+        // The top left finder pattern is dark, the module beside it light.
+        assert_eq!([0, 0, 0], pixels[..3]);
+        assert_eq!([255, 255, 255], pixels[3 * 7 * 300..3 * 7 * 300 + 3]);
+    }
+
+    #[test]
+    fn should_error_for_a_scale_too_big_for_an_image() {
+        // Arrange.
+        let qr_code = qrcodegen::QrCode::encode_text("ha", qrcodegen::QrCodeEcc::Low).unwrap();
+        let exp = Exporter::new(
+            qr_code,
+            PathBuf::new(),
+            4,
+            ExportFormat::PNG,
+            "".into(),
+            u32::MAX,
+            false,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+
+        // Act.
+        let res = exp.rgb_pixels(&exp.qr_code, exp.border, exp.scale);
+
+        // Assert.
+        assert_eq!(
+            "size or data length are out of bounds.",
+            res.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn should_write_ico_at_each_size() {
+        // Arrange.
+        let mut buffer: Vec<u8> = Vec::new();
+        let qr_code = qrcodegen::QrCode::encode_text("ha", qrcodegen::QrCodeEcc::Low).unwrap();
+        let exp = Exporter::new(
+            qr_code,
+            PathBuf::new(),
+            4,
+            ExportFormat::ICO,
+            "".into(),
+            1,
+            true,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+
+        // Act.
+        exp.export_ico(&mut buffer, &exp.qr_code, exp.border)
+            .unwrap();
+
+        // Assert.
+        let icon = ico::IconDir::read(io::Cursor::new(buffer)).unwrap();
+        let sizes: Vec<_> = icon.entries().iter().map(|entry| entry.width()).collect();
+        assert_eq!(ICO_SIZES.to_vec(), sizes);
+
+        // 29 modules fit 2 pixels each into 64 with 3 to spare either side, so the finder pattern starts at 3 + 4 * 2.
+        let image = icon.entries()[3].decode().unwrap();
+        let pixel = |x: usize, y: usize| &image.rgba_data()[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4];
+        assert_eq!(&[0, 0, 0, 0], pixel(10, 10));
+        assert_eq!(&[0, 0, 0, 255], pixel(11, 11));
+        assert_eq!(&[0, 0, 0, 255], pixel(12, 12));
+    }
+
+    #[test]
+    fn should_follow_on_exists_policy() {
+        let dir = std::env::temp_dir().join("qrgen_should_follow_on_exists_policy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.svg");
+        std::fs::write(&path, "old").unwrap();
+
+        assert_eq!(None, write_file(&path, b"new", OnExists::Skip).unwrap());
+        assert!(write_file(&path, b"new", OnExists::Error).is_err());
+        assert_eq!("old", std::fs::read_to_string(&path).unwrap());
+
+        assert_eq!(
+            Some(dir.join("a-1.svg")),
+            write_file(&path, b"new", OnExists::Rename).unwrap()
+        );
+        assert_eq!(
+            Some(dir.join("a-2.svg")),
+            write_file(&path, b"new", OnExists::Rename).unwrap()
+        );
+
+        assert_eq!(
+            Some(path.clone()),
+            write_file(&path, b"new", OnExists::Overwrite).unwrap()
+        );
+        assert_eq!("new", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_link_without_changing_the_original() {
+        let dir = std::env::temp_dir().join("qrgen_should_link_without_changing_the_original");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("a.svg");
+        let path = dir.join("b.svg");
+        std::fs::write(&original, "same").unwrap();
+        std::fs::write(&path, "old").unwrap();
+
+        assert_eq!(None, link_file(&path, &original, OnExists::Skip).unwrap());
+        assert_eq!(
+            Some(dir.join("b-1.svg")),
+            link_file(&path, &original, OnExists::Rename).unwrap()
+        );
+        assert_eq!(
+            Some(path.clone()),
+            link_file(&path, &original, OnExists::Overwrite).unwrap()
+        );
+        assert_eq!("same", std::fs::read_to_string(&path).unwrap());
+
+        remove_file(&path).unwrap();
+        write_file(&path, b"new", OnExists::Overwrite).unwrap();
+        assert_eq!("same", std::fs::read_to_string(&original).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_move_temp_files_into_place() {
+        let dir = std::env::temp_dir().join("qrgen_should_move_temp_files_into_place");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.svg");
+        std::fs::write(&path, "old").unwrap();
+
+        let temp = write_temp_file(&path, b"new").unwrap();
+        assert_eq!(None, move_file(&temp, &path, OnExists::Skip).unwrap());
+        assert!(!temp.exists());
+
+        let temp = write_temp_file(&path, b"new").unwrap();
+        assert_eq!(
+            Some(dir.join("a-1.svg")),
+            move_file(&temp, &path, OnExists::Rename).unwrap()
+        );
+        let temp = write_temp_file(&path, b"new").unwrap();
+        assert!(move_file(&temp, &path, OnExists::Error).is_err());
+        assert_eq!("old", std::fs::read_to_string(&path).unwrap());
+
+        let temp = write_temp_file(&path, b"new").unwrap();
+        assert_eq!(
+            Some(path.clone()),
+            move_file(&temp, &path, OnExists::Overwrite).unwrap()
+        );
+        assert_eq!("new", std::fs::read_to_string(&path).unwrap());
+        assert_eq!(2, std::fs::read_dir(&dir).unwrap().count());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_keep_formats_in_order_once() {
+        let formats = Formats::new(vec![
+            ExportFormat::PNG,
+            ExportFormat::SVG,
+            ExportFormat::PNG,
+        ])
+        .unwrap();
+
+        assert_eq!(ExportFormat::PNG, formats.first());
+        assert_eq!(
+            vec![ExportFormat::PNG, ExportFormat::SVG],
+            formats.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(None, Formats::new(Vec::new()));
+    }
+
+    #[test]
+    fn should_shard_by_name_hash() {
+        // The hash of hello starts 2cf24dba.
+        assert_eq!(Path::new("2c").join("f2"), shard_dir("hello", 2));
+        assert_eq!(PathBuf::from("2c"), shard_dir("hello", 1));
+        assert_eq!(PathBuf::new(), shard_dir("hello", 0));
+    }
+
+    #[test]
+    fn should_extend_long_paths() {
+        assert_eq!(
+            Some(PathBuf::from(r"\\?\C:\out\a.svg")),
+            extended(Path::new(r"C:\out\a.svg"))
+        );
+        assert_eq!(
+            Some(PathBuf::from(r"\\?\UNC\server\share\a.svg")),
+            extended(Path::new(r"\\server\share\a.svg"))
+        );
+        assert_eq!(
+            Some(PathBuf::from(r"\\?\C:\a.svg")),
+            extended(Path::new(r"\\?\C:\a.svg"))
+        );
+        assert_eq!(Path::new("out/a.svg"), long_path(Path::new("out/a.svg")));
+    }
 }
@@ -0,0 +1,350 @@
+use crate::columns::Column;
+use std::{cmp::Ordering, fmt, iter::Peekable, str::Chars};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr<C> {
+    Compare(C, Op, String),
+    Not(Box<Expr<C>>),
+    And(Box<Expr<C>>, Box<Expr<C>>),
+    Or(Box<Expr<C>>, Box<Expr<C>>),
+}
+
+impl Expr<Column> {
+    fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<Expr<usize>, String> {
+        Ok(match self {
+            Expr::Compare(column, op, value) => {
+                Expr::Compare(column.resolve(headers)?, *op, value.clone())
+            }
+            Expr::Not(e) => Expr::Not(Box::new(e.resolve(headers)?)),
+            Expr::And(l, r) => {
+                Expr::And(Box::new(l.resolve(headers)?), Box::new(r.resolve(headers)?))
+            }
+            Expr::Or(l, r) => {
+                Expr::Or(Box::new(l.resolve(headers)?), Box::new(r.resolve(headers)?))
+            }
+        })
+    }
+}
+
+impl Expr<usize> {
+    fn matches(&self, row: &csv::StringRecord) -> bool {
+        match self {
+            Expr::Compare(index, op, value) => match row.get(*index) {
+                Some(field) => op.test(compare(field, value)),
+                None => false,
+            },
+            Expr::Not(e) => !e.matches(row),
+            Expr::And(l, r) => l.matches(row) && r.matches(row),
+            Expr::Or(l, r) => l.matches(row) || r.matches(row),
+        }
+    }
+}
+
+/// Compare numerically when both sides are numbers, otherwise compare the text.
+fn compare(field: &str, value: &str) -> Ordering {
+    match (field.parse::<f64>(), value.parse::<f64>()) {
+        (Ok(f), Ok(v)) => f.partial_cmp(&v).unwrap_or(Ordering::Equal),
+        _ => field.cmp(value),
+    }
+}
+
+/// A condition rows must meet to be generated, e.g. country == "DE" && (stock > 0 || 5 != "").  Columns are given by
+/// number starting at 1 or by header name and compared with ==, !=, <, <=, > or >= against a quoted string or a
+/// number.  Conditions can be combined with &&, || and !, and grouped with parentheses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    source: String,
+    expr: Expr<Column>,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: source.chars().peekable(),
+        };
+
+        let expr = parser.or()?;
+        match parser.token()? {
+            None => Ok(Filter {
+                source: source.to_string(),
+                expr,
+            }),
+            Some(token) => Err(format!("Unexpected {} in the filter.", token)),
+        }
+    }
+
+    /// Find the position of every column in the filter, looking names up in the header row of the file.
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<ResolvedFilter, String> {
+        Ok(ResolvedFilter {
+            expr: self.expr.resolve(headers)?,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A filter whose columns have been found in a particular file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedFilter {
+    expr: Expr<usize>,
+}
+
+impl ResolvedFilter {
+    pub fn matches(&self, row: &csv::StringRecord) -> bool {
+        self.expr.matches(row)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expr<Column>, String> {
+        let mut expr = self.and()?;
+        while self.next_is(Token::Or)? {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr<Column>, String> {
+        let mut expr = self.unary()?;
+        while self.next_is(Token::And)? {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr<Column>, String> {
+        match self.token()? {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.token()? {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(String::from("Missing ) in the filter.")),
+                }
+            }
+            Some(Token::Word(column)) => {
+                let column = crate::parse_column(&column)?;
+                let op = match self.token()? {
+                    Some(Token::Op(op)) => op,
+                    _ => {
+                        return Err(format!(
+                            "Expected a comparison after {} in the filter.",
+                            column
+                        ))
+                    }
+                };
+                match self.token()? {
+                    Some(Token::Text(value)) | Some(Token::Word(value)) => {
+                        Ok(Expr::Compare(column, op, value))
+                    }
+                    _ => Err(format!(
+                        "Expected a value to compare {} with in the filter.",
+                        column
+                    )),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {} in the filter.", token)),
+            None => Err(String::from("The filter ended unexpectedly.")),
+        }
+    }
+
+    /// Consume the next token if it is the expected one.
+    fn next_is(&mut self, expected: Token) -> Result<bool, String> {
+        let start = self.chars.clone();
+        if self.token()? == Some(expected) {
+            return Ok(true);
+        }
+
+        self.chars = start;
+        Ok(false)
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, String> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let c = match self.chars.next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if self.chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if self.chars.next_if_eq(&'|').is_some() => Token::Or,
+            '=' if self.chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+            '!' if self.chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if self.chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if self.chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.extend(self.chars.next()),
+                        Some(c) => text.push(c),
+                        None => return Err(String::from("A string in the filter is not closed.")),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if is_word(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = self.chars.next_if(|&c| is_word(c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            c => return Err(format!("Unexpected {} in the filter.", c)),
+        };
+
+        Ok(Some(token))
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(filter: &str, headers: &[&str], row: &[&str]) -> bool {
+        let headers = csv::StringRecord::from(headers.to_vec());
+        Filter::parse(filter)
+            .unwrap()
+            .resolve(Some(&headers))
+            .unwrap()
+            .matches(&csv::StringRecord::from(row.to_vec()))
+    }
+
+    #[test]
+    fn should_compare_named_and_numbered_columns() {
+        assert!(matches(
+            "country == \"DE\"",
+            &["name", "country"],
+            &["a", "DE"]
+        ));
+        assert!(!matches(
+            "country == \"DE\"",
+            &["name", "country"],
+            &["a", "FR"]
+        ));
+        assert!(matches("2 != \"FR\"", &["name", "country"], &["a", "DE"]));
+    }
+
+    #[test]
+    fn should_compare_numbers_numerically() {
+        assert!(matches("stock > 9", &["stock"], &["10"]));
+        assert!(matches("stock <= 10.0", &["stock"], &["10"]));
+        assert!(!matches("stock > \"9\"", &["stock"], &["10.5x"]));
+    }
+
+    #[test]
+    fn should_combine_conditions() {
+        let headers = ["country", "stock"];
+        let filter = "country == \"DE\" && !(stock == 0 || stock < 0)";
+
+        assert!(matches(filter, &headers, &["DE", "3"]));
+        assert!(!matches(filter, &headers, &["DE", "0"]));
+        assert!(!matches(filter, &headers, &["FR", "3"]));
+        assert!(matches(
+            "country == \"FR\" || stock > 1",
+            &headers,
+            &["DE", "3"]
+        ));
+    }
+
+    #[test]
+    fn should_not_match_missing_fields() {
+        assert!(!matches("3 == \"\"", &[], &["a", "b"]));
+    }
+
+    #[test]
+    fn should_reject_invalid_filters() {
+        assert_eq!(
+            Err("Expected a comparison after country in the filter.".to_string()),
+            Filter::parse("country \"DE\"")
+        );
+        assert!(Filter::parse("country == \"DE").is_err());
+        assert!(Filter::parse("(country == \"DE\"").is_err());
+        assert!(Filter::parse("country == \"DE\" extra").is_err());
+        assert!(Filter::parse("").is_err());
+    }
+}
@@ -1,15 +1,83 @@
-use crate::chunker;
+use crate::analysis;
+use crate::archive;
+use crate::batch;
+use crate::budget;
+use crate::cache;
+use crate::capacity;
+use crate::checksums;
+use crate::columns;
+use crate::dedupe;
+use crate::excel;
+use crate::exit;
 use crate::exporter;
-use log::{trace, warn};
-use qrcodegen;
+use crate::filter;
+use crate::html;
+use crate::input;
+use crate::json;
+use crate::jwt;
+use crate::labels;
+use crate::manifest;
+use crate::minisign;
+use crate::payload;
+use crate::pdf;
+use crate::poster;
+use crate::progress;
+use crate::record;
+use crate::rejects;
+use crate::retry;
+use crate::sheet;
+use crate::sign;
+use crate::space;
+use crate::stats;
+use crate::structured;
+use crate::template;
+use crate::terminal;
+use crate::timings;
+use itertools::Either;
+use log::{debug, info, trace, warn};
 use rayon::prelude::*;
-use std::{error::Error, fmt, fs::File, io, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The most records read ahead of the encoders, whatever the chunk size.
+const MAX_QUEUED_RECORDS: usize = 10_000;
 
 pub struct Generator {
     qr_conf: QrConfig,
     out_conf: OutputConfig,
+    in_conf: InputConfig,
     proc_conf: ProcessingConfig,
+    report_conf: ReportConfig,
+    batch_conf: BatchConfig,
     files: Vec<PathBuf>,
+    manifest: Mutex<Vec<manifest::ManifestEntry>>,
+    stats: Mutex<stats::Stats>,
+    next_row_name: AtomicU64,
+    deduper: dedupe::Deduper,
+    permits: Option<budget::Permits>,
+    timings: timings::Timings,
+    failures: exit::Failures,
+    rejects: rejects::Rejects,
+    checksums: checksums::Checksums,
+    batch: batch::Batch,
+    // The first output written with each image by its SHA-256, to link identical images to.
+    identical: Mutex<HashMap<String, PathBuf>>,
+    cache: Option<cache::Cache>,
+    settings: String,
+    seen: Mutex<HashSet<String>>,
+    used_names: record::UsedNames,
+    input_root: OnceLock<Option<PathBuf>>,
 }
 
 impl Generator {
@@ -17,249 +85,3573 @@ impl Generator {
         files: Vec<PathBuf>,
         qr_conf: QrConfig,
         out_conf: OutputConfig,
+        in_conf: InputConfig,
         proc_conf: ProcessingConfig,
+        report_conf: ReportConfig,
+        batch_conf: BatchConfig,
     ) -> Self {
+        let permits = proc_conf.limits.max_memory.map(|budget| {
+            let usual = image_bytes(&qr_conf, &out_conf, &Default::default());
+            budget::Permits::new(budget.images(usual), usual)
+        });
+        let cache = out_conf
+            .incremental
+            .cache
+            .then(|| cache::Cache::load(&out_conf.output));
+        let settings = cache_settings(&qr_conf, &out_conf);
+
         Generator {
             files,
             qr_conf,
             out_conf,
+            in_conf,
             proc_conf,
+            report_conf,
+            batch_conf,
+            manifest: Mutex::new(Vec::new()),
+            stats: Mutex::new(Default::default()),
+            next_row_name: AtomicU64::new(1),
+            deduper: Default::default(),
+            permits,
+            timings: Default::default(),
+            failures: exit::Failures::default(),
+            rejects: Default::default(),
+            checksums: Default::default(),
+            batch: Default::default(),
+            identical: Default::default(),
+            cache,
+            settings,
+            seen: Default::default(),
+            used_names: Default::default(),
+            input_root: OnceLock::new(),
         }
     }
 
     pub fn generate(&self) {
-        for file_path in &self.files {
-            match &self.process_file(file_path) {
-                Ok(_) => trace!("complete file {}", file_path.display()),
-                Err(e) => warn!("{:?}", e),
-            }
+        if let Some(progress) = &self.report_conf.progress {
+            progress.started(self.files.len());
         }
-    }
 
-    fn process_file(&self, file_path: &PathBuf) -> Result<(), Box<dyn Error>> {
-        trace!("process file {}", file_path.display());
-        let file = File::open(file_path)?;
-        let reader = self.csv_reader(file);
-        let chunks = chunker::Chunker::new(reader, self.proc_conf.chunk_size);
-
-        for chunk in chunks {
-            chunk
-                .par_iter()
-                .filter(|record| record.len() >= 2)
-                .for_each(|record| {
-                    if let Some(qr) = self.encode(record) {
-                        let mut exp = exporter::Exporter::new(
-                            qr,
-                            self.out_conf.output.clone(),
-                            self.out_conf.border,
-                            self.out_conf.format,
-                            record[0].to_string(),
-                            self.out_conf.scale,
-                            self.out_conf.no_rect,
-                            self.out_conf.foreground,
-                            self.out_conf.background,
-                        );
-                        let res = exp.export();
-                        if res.is_err() {
-                            warn!(
-                                "error generating for {} {:?}",
-                                record[0].to_string(),
-                                res.err()
-                            );
+        // Each worker takes the next file when it finishes one, so many small files keep every core busy.
+        let next = AtomicUsize::new(0);
+        let workers = self
+            .proc_conf
+            .limits
+            .parallel_files
+            .clamp(1, self.files.len().max(1));
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(file_path) = self.files.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if self.stopped() {
+                            break;
+                        }
+
+                        match &self.process_file(file_path) {
+                            Ok(_) => trace!("complete file {}", file_path.display()),
+                            Err(e) => {
+                                warn!(
+                                    file:% = file_path.display();
+                                    "{} {:?}",
+                                    file_path.display(),
+                                    e
+                                );
+                                self.fail(exit::Failure::Input);
+                            }
                         }
                     }
                 });
-        }
+            }
+        });
 
-        Ok(())
-    }
+        self.link_duplicates();
 
-    fn csv_reader<R: io::Read>(&self, reader: R) -> csv::Reader<R> {
-        csv::ReaderBuilder::new()
-            .has_headers(self.proc_conf.has_headers)
-            .trim(csv::Trim::All)
-            .flexible(true)
-            .from_reader(reader)
+        self.prune();
+        self.finish_archive();
+        self.write_reports();
+
+        if let Some(progress) = &self.report_conf.progress {
+            progress.finished(self.failures.total());
+        }
     }
 
-    fn encode(&self, record: &csv::StringRecord) -> Option<qrcodegen::QrCode> {
-        let segment = qrcodegen::QrSegment::make_segments(&record[1]);
+    /// Generate the given records rather than reading them from the input files, the source is only used to
+    /// describe where they came from in warnings and reports.
+    pub fn generate_records<I>(&self, source: &Path, records: I)
+    where
+        I: Iterator<Item = record::Record>,
+    {
+        if let Some(progress) = &self.report_conf.progress {
+            progress.started(1);
+        }
 
-        for s in segment.iter() {
-            trace!(
-                "encoding mode = {:?},  character count = {:?}",
-                match s.mode() {
-                    qrcodegen::QrSegmentMode::Alphanumeric => "Alphanumeric",
-                    qrcodegen::QrSegmentMode::Byte => "Byte",
-                    qrcodegen::QrSegmentMode::Eci => "Eci",
-                    qrcodegen::QrSegmentMode::Kanji => "Kanji",
-                    qrcodegen::QrSegmentMode::Numeric => "Numeric",
-                },
-                s.num_chars()
-            );
+        if let Err(e) = self.process_records(source, records) {
+            warn!(file:% = source.display(); "{} {:?}", source.display(), e);
+            self.fail(exit::Failure::Input);
         }
 
-        match qrcodegen::QrCode::encode_segments_advanced(
-            &segment,
-            self.qr_conf.error_correction,
-            self.qr_conf.qr_version_min,
-            self.qr_conf.qr_version_max,
-            self.qr_conf.mask,
-            true,
-        ) {
-            Ok(qr) => Some(qr),
+        self.link_duplicates();
+
+        self.prune();
+        self.finish_archive();
+        self.write_reports();
+
+        if let Some(progress) = &self.report_conf.progress {
+            progress.finished(self.failures.total());
+        }
+    }
+
+    /// Generate a single QR Code for the given value rather than reading records from the input files.
+    pub fn generate_value(&self, name: &str, data: &str) {
+        let segments = qrcodegen::QrSegment::make_segments(data);
+        self.process_record(
+            Path::new(""),
+            0,
+            name,
+            segments,
+            data.as_bytes(),
+            &Default::default(),
+            None,
+            None,
+        );
+        self.finish_archive();
+        self.write_reports();
+    }
+
+    /// Generate a QR Code for a record entered interactively or received from a queue, returning where it was
+    /// written in each format.  The reports are left to be written by write_reports.
+    pub fn generate_entry(&self, source: &Path, record: &record::Record) -> Vec<PathBuf> {
+        let record = match self.check_file_name(source, record.clone()) {
+            Ok(Some(record)) => record,
+            Ok(None) => return Vec::new(),
             Err(e) => {
-                warn!("error generating for {} {:?}", record[0].to_string(), e);
-                None
+                warn!(file:% = source.display(), row = record.row; "{}", e);
+                self.reject(source, record.row, &record.name, exit::Failure::Record, e);
+                return Vec::new();
             }
+        };
+
+        self.process_record(
+            source,
+            record.row,
+            &record.name,
+            qrcodegen::QrSegment::make_segments(&record.data),
+            record.data.as_bytes(),
+            &record.overrides,
+            None,
+            record.renamed_from.as_deref(),
+        )
+        .unwrap_or_default()
+    }
+
+    /// Generate the given records like generate_records, returning where each was written in each format, e.g. to
+    /// list the files of the records.  A record that failed or was skipped has no outputs.
+    pub fn generate_listed(&self, source: &Path, records: &[record::Record]) -> Vec<Vec<PathBuf>> {
+        if let Some(progress) = &self.report_conf.progress {
+            progress.started(1);
+        }
+
+        let outputs = records
+            .par_iter()
+            .map(|record| {
+                if self.stopped() {
+                    return Vec::new();
+                }
+
+                let _permit = self.permits.as_ref().map(|permits| {
+                    permits.acquire(image_bytes(
+                        &self.qr_conf,
+                        &self.out_conf,
+                        &record.overrides,
+                    ))
+                });
+                self.generate_entry(source, &self.sign(self.wrap_url(record.clone())))
+            })
+            .collect();
+
+        self.link_duplicates();
+        self.finish_archive();
+        self.write_reports();
+
+        if let Some(progress) = &self.report_conf.progress {
+            progress.finished(self.failures.total());
         }
+        outputs
     }
-}
 
-impl fmt::Display for Generator {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "qr_conf = [QR Version Min:{}, QR Version Max:{}, Error Correction: {}, Mask:{}], \
-             proc_conf = [Chunk Size:{}, Has CSV Header:{}], \
-             out_conf: [Border:{}, Format: {:?}, Exclude <rect />: {:?}, Foreground: {:?}, Fackgound: {:?}, Output: {}], \
-             input: Files: {:?}:",
-            self.qr_conf.qr_version_min.value(),
-            self.qr_conf.qr_version_max.value(),
-            match self.qr_conf.error_correction {
-                qrcodegen::QrCodeEcc::High => "High",
-                qrcodegen::QrCodeEcc::Low => "Low",
-                qrcodegen::QrCodeEcc::Quartile => "Quartile",
-                qrcodegen::QrCodeEcc::Medium => "Medium",
-            },
-            match self.qr_conf.mask {
-                Some(m) => m.value().to_string(),
-                _ => String::from("<Not Set>"),
-            },
-            self.proc_conf.chunk_size,
-            self.proc_conf.has_headers,
+    /// Draw a single QR Code for the given value with text characters instead of writing a file.
+    pub fn draw_value(&self, data: &str) -> Option<String> {
+        let segments = qrcodegen::QrSegment::make_segments(data);
+        self.encode(Path::new(""), 0, data, segments, &Default::default())
+            .map(|(qr, _)| terminal::render(&qr, self.out_conf.border))
+    }
+
+    /// Draw a single QR Code for the given value in the first output format instead of writing a file, e.g. to
+    /// write it to standard output.
+    pub fn render_value(&self, data: &str) -> Option<Vec<u8>> {
+        self.render(qrcodegen::QrSegment::make_segments(data))
+    }
+
+    /// Draw a single QR Code holding the given bytes in byte mode in the first output format.
+    pub fn render_bytes(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.render(vec![qrcodegen::QrSegment::make_bytes(data)])
+    }
+
+    fn render(&self, segments: Vec<qrcodegen::QrSegment>) -> Option<Vec<u8>> {
+        let (qr, _) = self.encode(Path::new(""), 0, "-", segments, &Default::default())?;
+        let exp = exporter::Exporter::new(
+            qr,
+            self.out_conf.output.clone(),
             self.out_conf.border,
-            self.out_conf.format,
+            self.out_conf.formats.first(),
+            String::new(),
+            self.out_conf.scale,
             self.out_conf.no_rect,
             self.out_conf.foreground,
             self.out_conf.background,
-            self.out_conf.output.display(),
-            self.files,
-        )
+        );
+
+        match exp.render() {
+            Ok(image) => Some(image),
+            Err(e) => {
+                warn!("error generating for - {:?}", e);
+                self.fail(exit::Failure::Record);
+                None
+            }
+        }
     }
-}
 
-pub struct QrConfig {
-    qr_version_min: qrcodegen::Version,
-    qr_version_max: qrcodegen::Version,
-    mask: Option<qrcodegen::Mask>,
-    error_correction: qrcodegen::QrCodeEcc,
-}
+    /// Generate a single QR Code holding the given bytes in byte mode.
+    pub fn generate_bytes(&self, name: &str, data: &[u8]) {
+        let segments = vec![qrcodegen::QrSegment::make_bytes(data)];
+        self.process_record(
+            Path::new(""),
+            0,
+            name,
+            segments,
+            data,
+            &Default::default(),
+            None,
+            None,
+        );
+        self.finish_archive();
+        self.write_reports();
+    }
 
-impl QrConfig {
-    pub fn new(
-        qr_version_min: qrcodegen::Version,
-        qr_version_max: qrcodegen::Version,
-        error_correction: qrcodegen::QrCodeEcc,
-        mask: Option<qrcodegen::Mask>,
-    ) -> Self {
-        QrConfig {
-            qr_version_min,
-            qr_version_max,
-            mask,
-            error_correction,
+    fn link_duplicates(&self) {
+        if self.proc_conf.dedupe != Some(dedupe::Dedupe::Link) {
+            return;
+        }
+
+        for (target, original) in self.deduper.link() {
+            self.checksums.link(
+                self.relative_output(&target),
+                self.relative_output(&original),
+            );
         }
     }
-}
 
-#[derive(Clone, Default)]
-pub struct OutputConfig {
-    output: PathBuf,
-    border: u8,
-    format: exporter::ExportFormat,
-    scale: u8,
-    no_rect: bool,
-    foreground: (u8, u8, u8),
-    background: (u8, u8, u8),
-}
+    // The path of an output within the output directory, which is how archives and checksums refer to it.
+    fn relative_output<'a>(&self, output: &'a Path) -> &'a Path {
+        output.strip_prefix(&self.out_conf.output).unwrap_or(output)
+    }
 
-impl OutputConfig {
-    pub fn new(
-        output: PathBuf,
-        border: u8,
-        format: exporter::ExportFormat,
-        scale: u8,
-        no_rect: bool,
-        foreground: (u8, u8, u8),
-        background: (u8, u8, u8),
-    ) -> Self {
-        OutputConfig {
-            output,
-            border,
-            format,
-            scale,
-            no_rect,
-            foreground,
-            background,
+    // Write the outputs that hold every QR Code of the run together.
+    fn write_batch(&self) {
+        if !self.batch_conf.needs_codes() {
+            return;
+        }
+
+        let codes = self.batch.codes();
+        let style = batch::Style {
+            border: self.out_conf.border,
+            scale: self.out_conf.scale,
+            no_rect: self.out_conf.no_rect,
+            foreground: self.out_conf.foreground,
+            background: self.out_conf.background,
+        };
+
+        if let Some(path) = &self.batch_conf.pdf {
+            self.write_batch_output("pdf", path, &codes, || {
+                pdf::write(
+                    path,
+                    &codes,
+                    &self.batch_conf.document,
+                    &style,
+                    self.batch_conf.captions,
+                )
+            });
+        }
+
+        if let Some(path) = &self.batch_conf.sheet {
+            self.write_batch_output("contact sheet", path, &codes, || {
+                sheet::write(path, &codes, self.batch_conf.grid, &style).map(|sheets| {
+                    for sheet in sheets {
+                        info!("wrote contact sheet {}", sheet.display());
+                    }
+                })
+            });
+        }
+
+        if let Some(path) = &self.batch_conf.labels {
+            self.write_batch_output("labels", path, &codes, || {
+                labels::write(
+                    path,
+                    &codes,
+                    &self.batch_conf.template,
+                    &self.batch_conf.document,
+                    &style,
+                    self.batch_conf.captions,
+                )
+                .map(|sheets| {
+                    for sheet in sheets {
+                        info!("wrote labels {}", sheet.display());
+                    }
+                })
+            });
+        }
+
+        if let Some(path) = &self.batch_conf.poster {
+            self.write_batch_output("poster", path, &codes, || {
+                poster::write(path, &codes, &self.batch_conf.layout, &style)
+            });
+        }
+
+        if let Some(path) = &self.batch_conf.html {
+            self.write_batch_output("html", path, &codes, || html::write(path, &codes, &style));
         }
     }
-}
 
-#[derive(Default, Clone, Copy, Debug)]
-pub struct ProcessingConfig {
-    chunk_size: usize,
-    has_headers: bool,
-}
+    fn write_batch_output<F>(&self, kind: &str, path: &Path, codes: &[batch::Code], write: F)
+    where
+        F: FnOnce() -> io::Result<()>,
+    {
+        if codes.is_empty() {
+            warn!("no QR Codes were generated for {}", path.display());
+            return;
+        }
 
-impl ProcessingConfig {
-    pub fn new(chunk_size: usize, has_headers: bool) -> Self {
-        ProcessingConfig {
-            chunk_size,
-            has_headers,
+        match write() {
+            Ok(_) => trace!("wrote {} {}", kind, path.display()),
+            Err(e) => {
+                warn!("error writing {} {} {:?}", kind, path.display(), e);
+                self.fail(exit::Failure::Output);
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+    // Close the archive the outputs went into, if any, once nothing more will be added to it.
+    fn finish_archive(&self) {
+        if let Some(archive) = &self.out_conf.destination.archive {
+            if let Err(e) = archive.finish() {
+                warn!("error finishing archive {:?}", e);
+                self.fail(exit::Failure::Output);
+            }
+        }
+    }
 
-    fn default_generator() -> Generator {
-        Generator::new(
-            Vec::new(),
-            QrConfig::new(
-                qrcodegen::Version::new(1),
-                qrcodegen::Version::new(2),
-                qrcodegen::QrCodeEcc::High,
-                None,
-            ),
-            Default::default(),
-            Default::default(),
-        )
+    pub fn write_reports(&self) {
+        self.write_batch();
+
+        if let Some(path) = &self.report_conf.checksums {
+            match self.checksums.write(path) {
+                Ok(_) => trace!("wrote checksums {}", path.display()),
+                Err(e) => {
+                    warn!("error writing checksums {} {:?}", path.display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        if let Some(config) = &self.report_conf.manifest {
+            let path = &config.path;
+            let mut entries = self.manifest.lock().unwrap();
+            let written = manifest::write(path, &mut entries, config.append).and_then(|_| {
+                match &config.key {
+                    Some(key) => manifest::sign(path, key),
+                    None => Ok(()),
+                }
+            });
+            match written {
+                Ok(_) => {
+                    trace!("wrote manifest {}", path.display());
+                    // The entries are in the file now, so writing the reports again, e.g. after each message from a
+                    // queue, only appends the entries added since.
+                    if config.append {
+                        entries.clear();
+                    }
+                }
+                Err(e) => {
+                    warn!("error writing manifest {} {:?}", path.display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        if self.deduper.count() > 0 {
+            warn!(
+                "{} records repeated the data of an earlier record",
+                self.deduper.count()
+            );
+        }
+
+        if let Some(path) = &self.report_conf.duplicates {
+            match self.deduper.write(path) {
+                Ok(_) => trace!("wrote duplicates {}", path.display()),
+                Err(e) => {
+                    warn!("error writing duplicates {} {:?}", path.display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        if let Some(path) = &self.report_conf.rejects {
+            match self.rejects.write(path) {
+                Ok(_) => trace!("wrote rejects {}", path.display()),
+                Err(e) => {
+                    warn!("error writing rejects {} {:?}", path.display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        if let Some(cache) = &self.cache {
+            match cache.save() {
+                Ok(_) => trace!("wrote cache {}", cache.path().display()),
+                Err(e) => {
+                    warn!("error writing cache {} {:?}", cache.path().display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        let stats = self.stats.lock().unwrap();
+
+        if self.report_conf.stats {
+            print!("{}", stats);
+        }
+
+        if let Some(path) = &self.report_conf.stats_file {
+            match stats.write(path) {
+                Ok(_) => trace!("wrote stats {}", path.display()),
+                Err(e) => {
+                    warn!("error writing stats {} {:?}", path.display(), e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+
+        if self.report_conf.timings {
+            print!("{}", self.timings);
+        }
     }
 
-    #[test]
-    fn ensure_csv_is_flexible_and_reads_header() {
-        let gen = default_generator();
-        let buff = Cursor::new("file_name,qr_data\nfile_name,qr_data,extra");
+    /// An estimate of the number of records in the input files from the lines of the CSV and NDJSON files.  Other
+    /// inputs, such as standard input, URLs and compressed files, aren't counted.
+    pub fn count_records(&self) -> u64 {
+        self.files
+            .iter()
+            .filter(|path| !input::is_stdin(path) && !input::is_url(path) && !input::is_gzip(path))
+            .map(|path| {
+                let lines = match self.in_conf.input_format.for_path(path) {
+                    input::InputFormat::Csv | input::InputFormat::Ndjson => {
+                        space::estimate_lines(path).unwrap_or(0)
+                    }
+                    _ => 0,
+                };
+                let header = u64::from(self.in_conf.header_row == input::HeaderRow::Present);
+                let records = lines.saturating_sub(header + self.proc_conf.skip_rows as u64);
 
-        let reader = gen.csv_reader(buff);
-        let all_ok = reader.into_records().all(|r| r.is_ok());
+                match self.proc_conf.take {
+                    Some(take) => records.min(take as u64),
+                    None => records,
+                }
+            })
+            .sum()
+    }
 
-        assert!(all_ok);
+    /// Check there is room in the output directory for the QR Codes of the records, unless the check is turned off
+    /// or they go into an archive, so a run that can't finish stops before it starts.  Each QR Code is taken to be at
+    /// least the size of the smallest one the run can make.
+    pub fn check_disk_space(&self, records: impl FnOnce() -> u64) -> Result<(), String> {
+        if !self.proc_conf.limits.space_check || self.out_conf.destination.archive.is_some() {
+            return Ok(());
+        }
+
+        let records = records();
+        let output = &self.out_conf.output;
+        let needed = space::estimate(records, self.smallest_output());
+        let available = space::available(output).map_err(|e| {
+            format!(
+                "Unable to check the free space in {}: {}",
+                output.display(),
+                e
+            )
+        })?;
+        debug!(
+            "{} records need at least {} of {} free",
+            records,
+            space::describe(needed),
+            space::describe(available)
+        );
+
+        if needed > available {
+            return Err(format!(
+                "The QR Codes for {} records need at least {} but only {} is free in {}.",
+                records,
+                space::describe(needed),
+                space::describe(available),
+                output.display()
+            ));
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn ensure_csv_skips_header() {
-        let mut gen = default_generator();
-        gen.proc_conf.has_headers = true;
-        let buff = Cursor::new("file_name,qr_data\nfile_name,qr_data,extra");
+    // The size of an empty QR Code of the minimum version in each of the output formats together.
+    fn smallest_output(&self) -> u64 {
+        let version = self.qr_conf.qr_version_min;
+        let qr = match qrcodegen::QrCode::encode_segments_advanced(
+            &[],
+            self.qr_conf.error_correction,
+            version,
+            version,
+            self.qr_conf.mask,
+            false,
+        ) {
+            Ok(qr) => qr,
+            Err(_) => return 0,
+        };
 
-        let reader = gen.csv_reader(buff);
-        let count = reader.into_records().count();
+        let mut exp = exporter::Exporter::new(
+            qr,
+            self.out_conf.output.clone(),
+            self.out_conf.border,
+            self.out_conf.formats.first(),
+            String::new(),
+            self.out_conf.scale,
+            self.out_conf.no_rect,
+            self.out_conf.foreground,
+            self.out_conf.background,
+        );
+        self.out_conf
+            .formats
+            .iter()
+            .map(|format| {
+                exp.set_format(format);
+                exp.render().map_or(0, |image| image.len() as u64)
+            })
+            .sum()
+    }
 
-        assert_eq!(1, count);
+    /// The directory the named QR Code from the file is written to in the format.  That is a subdirectory of the
+    /// output named for the format when keeping formats apart, then the same subdirectory as the file is of the input
+    /// when mirroring the input directories and then a hashed subdirectory when sharding.
+    fn output_dir(&self, file_path: &Path, name: &str, format: exporter::ExportFormat) -> PathBuf {
+        let mut dir = self.out_conf.output.clone();
+        if self.out_conf.names.format_dirs {
+            dir.push(format.extension());
+        }
+        if self.out_conf.names.mirror {
+            if let Some(relative) = self.input_dir(file_path) {
+                dir.push(relative);
+            }
+        }
+        if self.out_conf.names.shard > 0 {
+            dir.push(exporter::shard_dir(name, self.out_conf.names.shard));
+        }
+        dir
+    }
+
+    // The directory of a local input file relative to the directory holding all of them.
+    fn input_dir(&self, file_path: &Path) -> Option<PathBuf> {
+        let root = self
+            .input_root
+            .get_or_init(|| input::common_dir(&self.files))
+            .as_ref()?;
+        let absolute = std::path::absolute(file_path).ok()?;
+        Some(absolute.parent()?.strip_prefix(root).ok()?.to_path_buf())
+    }
+
+    /// The formats the record's QR Code is written in, its own when it overrides them.
+    fn formats<'a>(&'a self, overrides: &'a record::Overrides) -> &'a exporter::Formats {
+        overrides.formats.as_ref().unwrap_or(&self.out_conf.formats)
+    }
+
+    /// The paths the named QR Code from the file is written to, one for each of the formats.
+    fn output_paths(
+        &self,
+        file_path: &Path,
+        name: &str,
+        formats: &exporter::Formats,
+    ) -> Vec<PathBuf> {
+        formats
+            .iter()
+            .map(|format| {
+                exporter::output_path(&self.output_dir(file_path, name, format), name, format)
+            })
+            .collect()
+    }
+
+    /// True if an earlier run wrote the named QR Code in every one of the formats, leaving files that aren't empty.
+    fn was_written(&self, file_path: &Path, name: &str, formats: &exporter::Formats) -> bool {
+        self.output_paths(file_path, name, formats)
+            .iter()
+            .all(|path| {
+                exporter::long_path(path)
+                    .metadata()
+                    .is_ok_and(|metadata| metadata.len() > 0)
+            })
+    }
+
+    /// The records, files and reports that failed or were skipped with a warning during the run, by kind.
+    pub fn failures(&self) -> &exit::Failures {
+        &self.failures
+    }
+
+    /// True once a failure has stopped the run because of --fail-fast.
+    pub fn stopped(&self) -> bool {
+        self.proc_conf.errors.fail_fast && self.failures.total() > 0
+    }
+
+    fn fail(&self, failure: exit::Failure) {
+        self.failures.add(failure);
+    }
+
+    /// Count a record that couldn't be generated as a failure and add it to the rejects report.
+    fn reject(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        failure: exit::Failure,
+        reason: String,
+    ) {
+        self.fail(failure);
+        self.rejects.add(file_path, row, name, reason);
+    }
+
+    /// Reject the record if it has taken longer than the record timeout.
+    fn past_deadline(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        deadline: Option<Instant>,
+    ) -> bool {
+        match deadline {
+            Some(deadline) if Instant::now() > deadline => {
+                self.time_out(file_path, row, name);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn time_out(&self, file_path: &Path, row: u64, name: &str) {
+        let reason = format!(
+            "timed out after {:?}",
+            self.proc_conf.limits.record_timeout.unwrap_or_default()
+        );
+        warn!(
+            file:% = file_path.display(), row = row;
+            "error generating for {} {}",
+            describe(file_path, row, name),
+            reason
+        );
+        self.reject(file_path, row, name, exit::Failure::Record, reason);
+    }
+
+    /// Write the image, retrying transient errors, on another thread when there is a deadline so that a hung output
+    /// gives up at the deadline rather than stalling the worker.  A write that is given up on carries on in the
+    /// background.  Returns None when the file already exists and is skipped.  When writing an archive the image is
+    /// added to it instead, under its path relative to the output directory, which is returned.  With
+    /// --link-identical an image the same as one already written is hard linked to it instead.
+    fn write(
+        &self,
+        exp: &exporter::Exporter,
+        image: Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<PathBuf>> {
+        let link_identical = self.out_conf.destination.link_identical;
+        if self.report_conf.checksums.is_none() && !link_identical {
+            return self.write_output(exp, image, deadline);
+        }
+
+        let sum = checksums::sha256(&image);
+        let original = match link_identical {
+            true => self.identical.lock().unwrap().get(&sum).cloned(),
+            false => None,
+        };
+        let written = match original {
+            Some(original) => self.link_output(exp, &original, image, deadline)?,
+            None => {
+                // Replace rather than overwrite a file that may be linked to another output.
+                if link_identical
+                    && self.out_conf.destination.on_exists == exporter::OnExists::Overwrite
+                {
+                    exporter::remove_file(&exp.path())?;
+                }
+                self.write_output(exp, image, deadline)?
+            }
+        };
+
+        if let Some(output) = &written {
+            if link_identical {
+                self.identical
+                    .lock()
+                    .unwrap()
+                    .entry(sum.clone())
+                    .or_insert_with(|| output.clone());
+            }
+            if self.report_conf.checksums.is_some() {
+                self.checksums.add(self.relative_output(output), sum);
+            }
+        }
+        Ok(written)
+    }
+
+    // Hard link the output to an identical one written earlier, writing it in full instead if the file system can't.
+    fn link_output(
+        &self,
+        exp: &exporter::Exporter,
+        original: &Path,
+        image: Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<PathBuf>> {
+        let path = exp.path();
+        if let Some(dir) = path.parent().filter(|dir| *dir != self.out_conf.output) {
+            fs::create_dir_all(exporter::long_path(dir))?;
+        }
+
+        match exporter::link_file(&path, original, self.out_conf.destination.on_exists) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {
+                debug!(
+                    "unable to link {} to {}, writing it instead {:?}",
+                    path.display(),
+                    original.display(),
+                    e
+                );
+                self.write_output(exp, image, deadline)
+            }
+            result => result,
+        }
+    }
+
+    fn write_output(
+        &self,
+        exp: &exporter::Exporter,
+        image: Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<PathBuf>> {
+        let path = exp.path();
+        if let Some(archive) = &self.out_conf.destination.archive {
+            let entry = self.relative_output(&path);
+            archive.append(entry, &image)?;
+            return Ok(Some(entry.to_path_buf()));
+        }
+
+        let retry = self.proc_conf.errors.retry;
+        let on_exists = self.out_conf.destination.on_exists;
+        if let Some(dir) = path.parent().filter(|dir| *dir != self.out_conf.output) {
+            fs::create_dir_all(exporter::long_path(dir))?;
+        }
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return retry.run(|| exp.write(&image, on_exists)),
+        };
+
+        // The write carries on in the background after the deadline, so it goes to a hidden file that is only moved
+        // into place if the record hasn't been given up on by then.  The lock is held from that check until the result
+        // is sent, so the record can't time out after its output appears.
+        let given_up = Arc::new(Mutex::new(false));
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn({
+            let given_up = Arc::clone(&given_up);
+            move || {
+                let temp = retry.run(|| exporter::write_temp_file(&path, &image));
+                let given_up = given_up.lock().unwrap();
+                let _ = sender.send(match temp {
+                    Ok(temp) if *given_up => exporter::remove_file(&temp).map(|_| None),
+                    Ok(temp) => exporter::move_file(&temp, &path, on_exists),
+                    Err(e) => Err(e),
+                });
+            }
+        });
+
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(written) => written,
+            Err(_) => {
+                let mut given_up = given_up.lock().unwrap();
+                receiver.try_recv().unwrap_or_else(|_| {
+                    *given_up = true;
+                    Err(io::ErrorKind::TimedOut.into())
+                })
+            }
+        }
+    }
+
+    fn process_file(&self, file_path: &Path) -> Result<(), Box<dyn Error>> {
+        trace!("process file {}", file_path.display());
+        let format = self.in_conf.input_format.for_path(file_path);
+
+        if format == input::InputFormat::Excel {
+            if input::is_stdin(file_path) || input::is_url(file_path) {
+                return Err("Excel input must be read from a local file.".into());
+            }
+
+            let rows = excel::read_rows(file_path, self.in_conf.sheet.as_deref())?;
+            let (headers, rows) = self.in_conf.header_row.split(rows);
+
+            return self.process_records(
+                file_path,
+                self.map_records(file_path, headers.as_ref(), rows)?,
+            );
+        }
+
+        let file = input::open(file_path, &self.in_conf.headers)?;
+        let file = match self.in_conf.encoding {
+            Some(encoding) => input::decode(file, encoding),
+            None => input::strip_bom(file)?,
+        };
+
+        let is_json = matches!(
+            format,
+            input::InputFormat::Json | input::InputFormat::Ndjson
+        );
+        if is_json && self.in_conf.filter.is_some() {
+            return Err("A filter can only be used with CSV and Excel input.".into());
+        }
+
+        match format {
+            input::InputFormat::Json => self.process_records(
+                file_path,
+                self.json_records(file_path, json::read_array(file)?),
+            ),
+            input::InputFormat::Ndjson => self.process_records(
+                file_path,
+                self.json_records(file_path, json::read_lines(file)),
+            ),
+            _ => {
+                let (file, delimiter) = match self.delimiter(file_path) {
+                    Some(delimiter) => (file, delimiter),
+                    None => input::sniff_delimiter(file)?,
+                };
+                self.process_records(file_path, self.csv_records(file_path, file, delimiter)?)
+            }
+        }
+    }
+
+    fn process_records<I>(&self, file_path: &Path, records: I) -> Result<(), Box<dyn Error>>
+    where
+        I: Iterator<Item = record::Record>,
+    {
+        let mut record_error = None;
+        let records = records
+            .map_while(|record| match self.check_record(file_path, record) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    record_error = Some(e);
+                    None
+                }
+            })
+            .flatten()
+            .map(|record| self.wrap_url(record))
+            .map(|record| self.sign(record));
+
+        let records = self.slice_records(records).filter(|record| {
+            self.proc_conf.dedupe.is_none() || self.deduper.is_first(file_path, record)
+        });
+
+        // Records are read on this thread and queued for the encoders, which take the next record as soon as
+        // they finish one.  Reading waits while the queue is full, so at most the queue plus one record per worker
+        // are held in memory however large the file.
+        let (sender, receiver) = mpsc::sync_channel(self.queue_length());
+        let processed = AtomicU64::new(0);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                receiver
+                    .into_iter()
+                    .par_bridge()
+                    .for_each(|record: record::Record| {
+                        // Records already being generated finish, but queued ones are dropped.
+                        if self.stopped() {
+                            return;
+                        }
+
+                        let _permit = self.permits.as_ref().map(|permits| {
+                            permits.acquire(image_bytes(
+                                &self.qr_conf,
+                                &self.out_conf,
+                                &record.overrides,
+                            ))
+                        });
+                        self.process_changed(file_path, &record);
+
+                        let processed = processed.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(progress) = &self.report_conf.progress {
+                            if processed.is_multiple_of(self.proc_conf.chunk_size as u64) {
+                                progress.chunk_done(file_path, processed);
+                            }
+                        }
+                    });
+            });
+
+            let mut records = records;
+            while let Some(record) = self
+                .timings
+                .time(timings::Stage::Parsing, || records.next())
+            {
+                if self.stopped() || sender.send(record).is_err() {
+                    break;
+                }
+            }
+            drop(sender);
+        });
+
+        if let Some(progress) = &self.report_conf.progress {
+            progress.file_done(file_path, processed.into_inner());
+        }
+
+        match record_error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Leave out JSON records that couldn't be read, with a warning, and those with overrides that can't be read,
+    /// which are rejected the same as CSV rows.
+    fn json_records<'a, I>(
+        &'a self,
+        file_path: &'a Path,
+        entries: I,
+    ) -> impl Iterator<Item = record::Record> + 'a
+    where
+        I: Iterator<Item = Result<json::Entry, String>> + 'a,
+    {
+        entries.filter_map(move |entry| match entry {
+            Ok((mut record, overrides)) => {
+                record.overrides = self.overrides(file_path, &record, overrides)?;
+                Some(record)
+            }
+            Err(e) => {
+                warn!(file:% = file_path.display(); "{} {}", file_path.display(), e);
+                self.fail(exit::Failure::Record);
+                None
+            }
+        })
+    }
+
+    /// The record if it should be generated, after checking it isn't blank and making its name safe, or an error if
+    /// the policies say processing the file should stop.
+    fn check_record(
+        &self,
+        file_path: &Path,
+        record: record::Record,
+    ) -> Result<Option<record::Record>, String> {
+        if !self.check_blank(file_path, &record)? {
+            return Ok(None);
+        }
+
+        self.check_file_name(file_path, record)
+    }
+
+    /// Make the name of the record a safe file name that no earlier record in the run has used, in ASCII and the case
+    /// asked for, e.g. Müller becomes muller, and short enough for the file system.  Names that don't come from the
+    /// input are always safe and can't repeat by accident, so are left alone.
+    fn check_file_name(
+        &self,
+        file_path: &Path,
+        mut record: record::Record,
+    ) -> Result<Option<record::Record>, String> {
+        if self.proc_conf.naming != record::Naming::Column {
+            return Ok(Some(record));
+        }
+
+        if self.out_conf.names.ascii && !record.name.is_ascii() {
+            record.rename(deunicode::deunicode_with_tofu(&record.name, "_"));
+        }
+
+        let name = self.out_conf.names.case.apply(&record.name);
+        if name != record.name {
+            record.rename(name);
+        }
+
+        let record = match self.check_name(file_path, record)? {
+            Some(record) => self.unique_name(file_path, record)?,
+            None => None,
+        };
+
+        Ok(record.map(|record| self.short_name(file_path, record)))
+    }
+
+    /// Cut the name short when the file name would be longer than the file system allows.
+    fn short_name(&self, file_path: &Path, mut record: record::Record) -> record::Record {
+        let name = record::shorten_name(
+            &record.name,
+            self.formats(&record.overrides).longest_extension(),
+            self.out_conf.names.max_length,
+        );
+
+        if name != record.name {
+            info!(
+                "{} is too long for a file name, using {}",
+                describe(file_path, record.row, &record.name),
+                name
+            );
+            record.rename(name);
+        }
+
+        record
+    }
+
+    /// Follow the duplicate name policy when an earlier record in the run already used the record's name.
+    fn unique_name(
+        &self,
+        file_path: &Path,
+        mut record: record::Record,
+    ) -> Result<Option<record::Record>, String> {
+        if self.used_names.claim(&record.name) {
+            return Ok(Some(record));
+        }
+
+        match self.proc_conf.errors.on_duplicate_name {
+            record::DuplicateNamePolicy::Suffix => {
+                let name = self.used_names.claim_numbered(&record.name);
+                info!(
+                    "{} was already used, using {}",
+                    describe(file_path, record.row, &record.name),
+                    name
+                );
+                record.rename(name);
+                Ok(Some(record))
+            }
+            record::DuplicateNamePolicy::Overwrite => Ok(Some(record)),
+            record::DuplicateNamePolicy::Error => Err(format!(
+                "{} has the same name as an earlier record",
+                describe(file_path, record.row, &record.name)
+            )),
+        }
+    }
+
+    /// Make the name of the record safe to use as a file name, following the unsafe name policy when it wasn't.
+    fn check_name(
+        &self,
+        file_path: &Path,
+        mut record: record::Record,
+    ) -> Result<Option<record::Record>, String> {
+        let safe_name = record::safe_name(&record.name);
+        if safe_name == record.name {
+            return Ok(Some(record));
+        }
+
+        let message = format!(
+            "{} isn't a safe file name",
+            describe(file_path, record.row, &record.name)
+        );
+
+        match self.proc_conf.errors.on_unsafe_name {
+            record::UnsafeNamePolicy::Replace => {
+                info!("{}, using {}", message, safe_name);
+                record.rename(safe_name);
+                Ok(Some(record))
+            }
+            record::UnsafeNamePolicy::Skip => {
+                warn!(file:% = file_path.display(), row = record.row; "{}", message);
+                self.reject(
+                    file_path,
+                    record.row,
+                    &record.name,
+                    exit::Failure::Record,
+                    String::from("unsafe file name"),
+                );
+                Ok(None)
+            }
+            record::UnsafeNamePolicy::Error => Err(message),
+        }
+    }
+
+    fn check_blank(&self, file_path: &Path, record: &record::Record) -> Result<bool, String> {
+        let missing = if record.data.is_empty() {
+            "data"
+        } else if record.name.is_empty() && self.proc_conf.naming == record::Naming::Column {
+            "name"
+        } else {
+            return Ok(true);
+        };
+
+        let message = format!(
+            "{} row {} has no {}",
+            file_path.display(),
+            record.row,
+            missing
+        );
+
+        match self.proc_conf.errors.on_blank {
+            record::BlankPolicy::Skip => Ok(false),
+            record::BlankPolicy::Warn => {
+                warn!(file:% = file_path.display(), row = record.row; "{}", message);
+                self.fail(exit::Failure::Record);
+                Ok(false)
+            }
+            record::BlankPolicy::Error => Err(message),
+        }
+    }
+
+    /// Turn the data into a link when a URL prefix or suffix was given.
+    fn wrap_url(&self, mut record: record::Record) -> record::Record {
+        if self.proc_conf.url_prefix.is_some() || self.proc_conf.url_suffix.is_some() {
+            record.data = record::wrap_url(
+                &record.data,
+                self.proc_conf.url_prefix.as_deref().unwrap_or_default(),
+                self.proc_conf.url_suffix.as_deref().unwrap_or_default(),
+            );
+        }
+
+        record
+    }
+
+    /// Append the HMAC signature of the data when a signing key was given, unless the key already signed it as a JWT.
+    fn sign(&self, mut record: record::Record) -> record::Record {
+        if let (Some(signer), false) = (
+            &self.proc_conf.signer,
+            matches!(self.in_conf.data, payload::DataSource::Jwt(_)),
+        ) {
+            record.data = signer.sign(&record.data);
+        }
+
+        record
+    }
+
+    /// The number of records that can wait for an encoder, capped because the queue is allocated up front, and
+    /// sized to fit the memory budget when there is one.
+    fn queue_length(&self) -> usize {
+        let length = self.proc_conf.chunk_size.min(MAX_QUEUED_RECORDS);
+        match self.proc_conf.limits.max_memory {
+            Some(budget) => length.min(budget.records(self.proc_conf.limits.parallel_files)),
+            None => length,
+        }
+    }
+
+    /// Name the records then keep the range selected by --skip-rows and --take, naming first so that row names
+    /// stay the same however the input is sliced.
+    fn slice_records<'a, I>(&'a self, records: I) -> impl Iterator<Item = record::Record> + 'a
+    where
+        I: Iterator<Item = record::Record> + 'a,
+    {
+        records
+            .map(move |record| self.name_record(record))
+            .skip(self.proc_conf.skip_rows)
+            .take(self.proc_conf.take.unwrap_or(usize::MAX))
+    }
+
+    /// Apply the naming mode to a record, row names keep counting across input files so they never repeat within a
+    /// run.
+    fn name_record(&self, mut record: record::Record) -> record::Record {
+        match self.proc_conf.naming {
+            record::Naming::Column => (),
+            record::Naming::Row => {
+                let row = self.next_row_name.fetch_add(1, Ordering::Relaxed);
+                record.name = format!("{:06}", row);
+            }
+            record::Naming::Hash => record.name = record::hash_name(&record.data),
+        }
+
+        record
+    }
+
+    /// Generate the record unless the cache or the previous run's manifest shows its output was already generated
+    /// from the same data and settings.
+    fn process_changed(&self, file_path: &Path, record: &record::Record) {
+        let hash = cache::hash(
+            &self.settings,
+            &format!("{:?}", record.overrides),
+            &record.data,
+        );
+        if self.out_conf.incremental.prune {
+            self.seen.lock().unwrap().insert(record.name.clone());
+        }
+
+        let outputs = match self.unchanged_outputs(file_path, record, &hash) {
+            Some(outputs) => outputs,
+            None => match self.process_record(
+                file_path,
+                record.row,
+                &record.name,
+                qrcodegen::QrSegment::make_segments(&record.data),
+                record.data.as_bytes(),
+                &record.overrides,
+                Some(&hash),
+                record.renamed_from.as_deref(),
+            ) {
+                Some(outputs) => {
+                    if let Some(cache) = &self.cache {
+                        for output in &outputs {
+                            cache.insert(output, hash.clone());
+                        }
+                    }
+                    outputs
+                }
+                None => return,
+            },
+        };
+
+        if let Some(mtime) = record.mtime {
+            for output in &outputs {
+                self.set_mtime(file_path, record, output, mtime);
+            }
+        }
+    }
+
+    /// The outputs of the record if the cache or the previous manifest shows they were already generated from the
+    /// hash.
+    fn unchanged_outputs(
+        &self,
+        file_path: &Path,
+        record: &record::Record,
+        hash: &str,
+    ) -> Option<Vec<PathBuf>> {
+        let outputs = self.output_paths(file_path, &record.name, self.formats(&record.overrides));
+        if let Some(cache) = self
+            .cache
+            .as_ref()
+            .filter(|cache| outputs.iter().all(|output| cache.is_current(output, hash)))
+        {
+            info!(
+                "skipping {}, unchanged since {} was written",
+                describe(file_path, record.row, &record.name),
+                cache.path().display()
+            );
+            return Some(outputs);
+        }
+
+        let entries = self.unchanged_entries(&record.name, hash)?;
+        info!(
+            "skipping {}, unchanged since the previous manifest",
+            describe(file_path, record.row, &record.name)
+        );
+        // Keep the record in this run's manifest so the next run can be compared with it in turn, unless the run is
+        // appended to a manifest that already has it.
+        if self
+            .report_conf
+            .manifest
+            .as_ref()
+            .is_some_and(|manifest| !manifest.append)
+        {
+            self.manifest
+                .lock()
+                .unwrap()
+                .extend(entries.iter().map(|entry| manifest::ManifestEntry {
+                    input: file_path.display().to_string(),
+                    row: record.row,
+                    ..entry.clone()
+                }));
+        }
+        Some(
+            entries
+                .iter()
+                .map(|entry| PathBuf::from(&entry.output))
+                .collect(),
+        )
+    }
+
+    /// Give the output the modification time from the record, so tools that sync by time see the date it is for.
+    fn set_mtime(
+        &self,
+        file_path: &Path,
+        record: &record::Record,
+        output: &Path,
+        mtime: SystemTime,
+    ) {
+        let result = fs::File::options()
+            .write(true)
+            .open(exporter::long_path(output))
+            .and_then(|file| file.set_modified(mtime));
+
+        if let Err(e) = result {
+            warn!(
+                file:% = file_path.display(), row = record.row;
+                "error setting the modification time of {} {:?}",
+                output.display(),
+                e
+            );
+            self.fail(exit::Failure::Output);
+        }
+    }
+
+    /// The previous manifest's entries for the record if its outputs were generated from the same hash and are still
+    /// there.
+    fn unchanged_entries(&self, name: &str, hash: &str) -> Option<&[manifest::ManifestEntry]> {
+        let entries = self.out_conf.incremental.previous.as_ref()?.get(name);
+        let unchanged = !entries.is_empty()
+            && entries.iter().all(|entry| {
+                entry.hash.as_deref() == Some(hash)
+                    && exporter::long_path(Path::new(&entry.output))
+                        .metadata()
+                        .is_ok_and(|metadata| metadata.len() > 0)
+            });
+        unchanged.then_some(entries)
+    }
+
+    /// Remove the outputs of the records in the previous manifest that are no longer in the input.  Nothing is
+    /// removed unless all of the input was read, so a missing file or a partial run can't empty the output.
+    fn prune(&self) {
+        let previous = match &self.out_conf.incremental.previous {
+            Some(previous) if self.out_conf.incremental.prune => previous,
+            _ => return,
+        };
+
+        if self.stopped()
+            || self.failures.get(exit::Failure::Input) > 0
+            || self.proc_conf.skip_rows > 0
+            || self.proc_conf.take.is_some()
+            || self.in_conf.filter.is_some()
+        {
+            warn!("not pruning outputs as only part of the input was read");
+            return;
+        }
+
+        let seen = self.seen.lock().unwrap();
+        for entry in previous
+            .entries()
+            .filter(|entry| !seen.contains(&entry.name))
+        {
+            match fs::remove_file(exporter::long_path(Path::new(&entry.output))) {
+                Ok(_) => info!(
+                    "removed {}, {} is no longer in the input",
+                    entry.output, entry.name
+                ),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    warn!("error removing {} {:?}", entry.output, e);
+                    self.fail(exit::Failure::Output);
+                }
+            }
+        }
+    }
+
+    fn process_record(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        segments: Vec<qrcodegen::QrSegment>,
+        payload: &[u8],
+        overrides: &record::Overrides,
+        hash: Option<&str>,
+        renamed_from: Option<&str>,
+    ) -> Option<Vec<PathBuf>> {
+        let formats = self.formats(overrides);
+        if self.out_conf.incremental.resume && self.was_written(file_path, name, formats) {
+            info!(
+                "skipping {}, already written",
+                describe(file_path, row, name)
+            );
+            return None;
+        }
+
+        let deadline = self
+            .proc_conf
+            .limits
+            .record_timeout
+            .map(|timeout| Instant::now() + timeout);
+        let parts = self.timings.time(timings::Stage::Encoding, || {
+            self.encode_parts(file_path, row, name, segments, payload, overrides)
+        })?;
+        if self.past_deadline(file_path, row, name, deadline) {
+            return None;
+        }
+
+        if self.report_conf.analyze_masks {
+            for (part, (qr, segments)) in parts.iter().enumerate() {
+                println!(
+                    "{}: {}",
+                    part_name(name, part, parts.len()),
+                    analysis::MaskAnalysis::new(qr, segments)
+                );
+            }
+        }
+
+        // The QR Codes are only encoded once and then drawn in each format.
+        let mut outputs = Vec::new();
+        for (part, exp) in formats
+            .iter()
+            .flat_map(|format| self.exporters(file_path, name, &parts, format, overrides))
+        {
+            let image = match self
+                .timings
+                .time(timings::Stage::Rendering, || exp.render())
+            {
+                Ok(image) => image,
+                Err(e) => {
+                    warn!(
+                        file:% = file_path.display(), row = row;
+                        "error generating for {} {:?}",
+                        describe(file_path, row, name),
+                        e
+                    );
+                    self.reject(
+                        file_path,
+                        row,
+                        name,
+                        exit::Failure::Record,
+                        format!("{:?}", e),
+                    );
+                    return None;
+                }
+            };
+            if self.past_deadline(file_path, row, name, deadline) {
+                return None;
+            }
+
+            match self.timings.time(timings::Stage::Writing, || {
+                self.write(&exp, image, deadline)
+            }) {
+                Ok(Some(output)) => outputs.push((output, part)),
+                Ok(None) => {
+                    info!("skipping {}, {} already exists", name, exp.path().display());
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    self.time_out(file_path, row, name);
+                    return None;
+                }
+                Err(e) => {
+                    warn!(
+                        file:% = file_path.display(), row = row;
+                        "error writing {} {:?}",
+                        describe(file_path, row, name),
+                        e
+                    );
+                    self.reject(
+                        file_path,
+                        row,
+                        name,
+                        exit::Failure::Output,
+                        format!("{:?}", e),
+                    );
+                    return None;
+                }
+            }
+        }
+        if outputs.is_empty() {
+            return None;
+        }
+
+        // A structured append sequence counts as one record, described by its first QR Code.
+        let (qr, segments) = &parts[0];
+        if let Some(limit) = self.report_conf.capacity_warn {
+            let utilization = capacity::Utilization::new(qr, segments);
+            if utilization.percent() > limit {
+                warn!(
+                    file:% = file_path.display(), row = row;
+                    "{} uses {:.1}% of the data capacity of version {}",
+                    describe(file_path, row, name),
+                    utilization.percent(),
+                    qr.version().value()
+                );
+            }
+        }
+
+        self.stats.lock().unwrap().add(qr, segments, payload.len());
+
+        if self.batch_conf.needs_codes() {
+            self.batch.add(file_path, row, name, qr);
+        }
+
+        if self.proc_conf.dedupe == Some(dedupe::Dedupe::Link) {
+            for (output, _) in &outputs {
+                self.deduper.add_output(name, output);
+            }
+        }
+
+        if let Some(config) = &self.report_conf.manifest {
+            let entries = outputs.iter().map(|(output, part)| {
+                let (qr, segments) = &parts[*part];
+                manifest::ManifestEntry::new(
+                    file_path,
+                    row,
+                    name,
+                    output,
+                    qr,
+                    segments,
+                    hash,
+                    renamed_from,
+                    Some(&config.run_id),
+                )
+            });
+            self.manifest.lock().unwrap().extend(entries);
+        }
+
+        Some(outputs.into_iter().map(|(output, _)| output).collect())
+    }
+
+    // The exporters of a record's QR Codes in a format with the part each draws, one for each part of a structured
+    // append sequence, or a single one drawing every part as the frames of a GIF.
+    fn exporters(
+        &self,
+        file_path: &Path,
+        name: &str,
+        parts: &[(qrcodegen::QrCode, Vec<qrcodegen::QrSegment>)],
+        format: exporter::ExportFormat,
+        overrides: &record::Overrides,
+    ) -> Vec<(usize, exporter::Exporter)> {
+        let exporter = |part: usize, file_name: String| {
+            exporter::Exporter::new(
+                parts[part].0.clone(),
+                self.output_dir(file_path, name, format),
+                overrides.border.unwrap_or(self.out_conf.border),
+                format,
+                file_name,
+                overrides.scale.unwrap_or(self.out_conf.scale),
+                self.out_conf.no_rect,
+                self.out_conf.foreground,
+                self.out_conf.background,
+            )
+        };
+
+        if format == exporter::ExportFormat::GIF {
+            let mut exp = exporter(0, name.to_string());
+            exp.set_frames(
+                parts[1..].iter().map(|(qr, _)| qr.clone()).collect(),
+                self.qr_conf.structured_append.interval,
+            );
+            return vec![(0, exp)];
+        }
+
+        (0..parts.len())
+            .map(|part| (part, exporter(part, part_name(name, part, parts.len()))))
+            .collect()
+    }
+
+    /// The delimiter to use for a file, tab separated files default to tabs when none was specified.  Returns
+    /// None if the delimiter should be detected from the file contents.
+    fn delimiter(&self, file_path: &Path) -> Option<u8> {
+        match self.in_conf.delimiter {
+            Some(input::Delimiter::Byte(delimiter)) => Some(delimiter),
+            Some(input::Delimiter::Auto) => None,
+            None if input::is_tsv(file_path) => Some(b'\t'),
+            None => Some(b','),
+        }
+    }
+
+    fn csv_records<'a, R: io::Read + 'a>(
+        &'a self,
+        file_path: &'a Path,
+        reader: R,
+        delimiter: u8,
+    ) -> Result<impl Iterator<Item = record::Record> + 'a, Box<dyn Error>> {
+        let mut reader = self.csv_reader(reader, delimiter);
+        let headers = match self.in_conf.header_row {
+            input::HeaderRow::Present => Some(reader.headers()?.clone()),
+            _ => None,
+        };
+
+        if self.in_conf.data == payload::DataSource::Column
+            && self.in_conf.filter.is_none()
+            && self.in_conf.header_row != input::HeaderRow::Auto
+        {
+            let rows = reader
+                .into_byte_records()
+                .filter_map(move |result| match result {
+                    Ok(r) => Some(r),
+                    Err(e) => {
+                        warn!(file:% = file_path.display(); "{} {:?}", file_path.display(), e);
+                        self.fail(exit::Failure::Record);
+                        None
+                    }
+                });
+            return Ok(Either::Left(self.map_byte_records(
+                file_path,
+                headers.as_ref(),
+                rows,
+            )?));
+        }
+
+        let rows = reader
+            .into_records()
+            .filter_map(move |result| match result {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    warn!(file:% = file_path.display(); "{} {:?}", file_path.display(), e);
+                    self.fail(exit::Failure::Record);
+                    None
+                }
+            });
+
+        // The csv reader has already taken a header row that is known to be there.
+        let (detected, rows) = match self.in_conf.header_row {
+            input::HeaderRow::Auto => input::HeaderRow::Auto,
+            _ => input::HeaderRow::Absent,
+        }
+        .split(rows);
+
+        Ok(Either::Right(self.map_records(
+            file_path,
+            headers.or(detected).as_ref(),
+            rows,
+        )?))
+    }
+
+    /// Turn rows into records when the name and data are taken straight from their columns, the usual case for
+    /// large files.  Rows are left as bytes and only the two fields used are checked to be UTF-8, rows where they
+    /// aren't are skipped.
+    fn map_byte_records<'a, I>(
+        &'a self,
+        file_path: &'a Path,
+        headers: Option<&csv::StringRecord>,
+        rows: I,
+    ) -> Result<impl Iterator<Item = record::Record> + 'a, Box<dyn Error>>
+    where
+        I: Iterator<Item = csv::ByteRecord> + 'a,
+    {
+        let name = match self.proc_conf.naming {
+            record::Naming::Column => Some(self.in_conf.columns.name.resolve(headers)?),
+            _ => None,
+        };
+        let data = self.in_conf.columns.data.resolve(headers)?;
+        let mtime = self.mtime_column(headers)?;
+        let overrides = self.in_conf.columns.overrides.resolve(headers)?;
+
+        Ok(rows.filter_map(move |r| {
+            let row = r.position().map_or(0, |p| p.line());
+            let field = |index: Option<usize>| {
+                let bytes = index.and_then(|i| r.get(i)).unwrap_or_default();
+                std::str::from_utf8(bytes).map(str::to_string)
+            };
+
+            match (field(name), field(Some(data))) {
+                (Ok(name), Ok(data)) => self.fill_record(
+                    file_path,
+                    record::Record::new(row, name, data),
+                    mtime,
+                    &overrides,
+                    |i| {
+                        r.get(i)
+                            .map(std::str::from_utf8)
+                            .transpose()
+                            .map(|f| f.map(Cow::from))
+                            .map_err(|_| String::from("Fields must be valid UTF-8."))
+                    },
+                ),
+                _ => {
+                    warn!(
+                        file:% = file_path.display(), row = row;
+                        "{} row {} is not valid UTF-8",
+                        file_path.display(),
+                        row
+                    );
+                    self.fail(exit::Failure::Record);
+                    None
+                }
+            }
+        }))
+    }
+
+    /// Turn rows of fields into records using the configured name and data columns, the data template or the payload
+    /// type, which are looked up in the header row when given by name.  Rows that don't match the filter, or can't
+    /// be made into the payload type, are skipped, and columns missing from short rows are left empty, as is the name
+    /// unless it is taken from a column.
+    fn map_records<'a, I>(
+        &'a self,
+        file_path: &'a Path,
+        headers: Option<&csv::StringRecord>,
+        rows: I,
+    ) -> Result<impl Iterator<Item = record::Record> + 'a, Box<dyn Error>>
+    where
+        I: Iterator<Item = csv::StringRecord> + 'a,
+    {
+        let name = match self.proc_conf.naming {
+            record::Naming::Column => Some(self.in_conf.columns.name.resolve(headers)?),
+            _ => None,
+        };
+        let payload = match &self.in_conf.data {
+            payload::DataSource::Column => {
+                Payload::Column(self.in_conf.columns.data.resolve(headers)?)
+            }
+            payload::DataSource::Template(template) => {
+                Payload::Template(template.resolve(headers)?)
+            }
+            payload::DataSource::Build(payload) => Payload::Build(
+                payload.resolve(headers, self.in_conf.columns.data.resolve(headers)?)?,
+            ),
+            payload::DataSource::Jwt(claims) => Payload::Jwt(
+                claims.resolve(headers)?,
+                self.proc_conf
+                    .signer
+                    .clone()
+                    .ok_or("A JWT needs a key from --hmac-key-env or --hmac-key-file.")?,
+            ),
+        };
+        let filter = self
+            .in_conf
+            .filter
+            .as_ref()
+            .map(|f| f.resolve(headers))
+            .transpose()?;
+        let mtime = self.mtime_column(headers)?;
+        let overrides = self.in_conf.columns.overrides.resolve(headers)?;
+
+        let rows = rows.filter(move |r| filter.as_ref().is_none_or(|f| f.matches(r)));
+
+        Ok(rows.filter_map(move |r| {
+            let row = r.position().map_or(0, |p| p.line());
+            let data = match payload.render(&r) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!(
+                        file:% = file_path.display(), row = row;
+                        "{} row {} {}",
+                        file_path.display(),
+                        row,
+                        e
+                    );
+                    self.fail(exit::Failure::Record);
+                    return None;
+                }
+            };
+
+            let record = record::Record::new(
+                row,
+                name.and_then(|name| r.get(name))
+                    .unwrap_or_default()
+                    .to_string(),
+                data,
+            );
+            self.fill_record(file_path, record, mtime, &overrides, |i| {
+                Ok(r.get(i).map(Cow::from))
+            })
+        }))
+    }
+
+    /// Set the modified time and the QR settings a record overrides from the rest of its row, the same whether the
+    /// row was read as text or as bytes.  An override that can't be read, e.g. isn't valid UTF-8, rejects the record.
+    fn fill_record<'r, F>(
+        &self,
+        file_path: &Path,
+        mut record: record::Record,
+        mtime: Option<usize>,
+        overrides: &columns::OverrideFields,
+        field: F,
+    ) -> Option<record::Record>
+    where
+        F: Fn(usize) -> Result<Option<Cow<'r, str>>, String>,
+    {
+        let mtime = mtime
+            .map(&field)
+            .transpose()
+            .map(|f| f.flatten().unwrap_or_default());
+        record.mtime = self.mtime(file_path, record.row, mtime);
+        record.overrides = self.overrides(file_path, &record, overrides.read(field))?;
+        Some(record)
+    }
+
+    fn mtime_column(&self, headers: Option<&csv::StringRecord>) -> Result<Option<usize>, String> {
+        self.in_conf
+            .columns
+            .mtime
+            .as_ref()
+            .map(|column| column.resolve(headers))
+            .transpose()
+    }
+
+    /// The modification time in the field, if there is one.  A time that can't be read is left out with a warning,
+    /// the QR Code is still generated.
+    fn mtime(
+        &self,
+        file_path: &Path,
+        row: u64,
+        field: Result<Cow<str>, String>,
+    ) -> Option<SystemTime> {
+        field
+            .and_then(|field| match field.as_ref() {
+                "" => Ok(None),
+                field => record::parse_mtime(field).map(Some),
+            })
+            .map_err(|e| {
+                warn!(
+                    file:% = file_path.display(), row = row;
+                    "{} row {} {}",
+                    file_path.display(),
+                    row,
+                    e
+                )
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// The QR settings the record overrides, or None with a warning when one can't be read, as its QR Code wouldn't be
+    /// the one asked for.  The record is added to the rejects report.
+    pub fn overrides(
+        &self,
+        file_path: &Path,
+        record: &record::Record,
+        overrides: Result<record::Overrides, String>,
+    ) -> Option<record::Overrides> {
+        overrides
+            .map_err(|e| {
+                warn!(
+                    file:% = file_path.display(), row = record.row;
+                    "{} {}",
+                    describe(file_path, record.row, &record.name),
+                    e
+                );
+                self.reject(
+                    file_path,
+                    record.row,
+                    &record.name,
+                    exit::Failure::Record,
+                    e,
+                );
+            })
+            .ok()
+    }
+
+    fn csv_reader<R: io::Read>(&self, reader: R, delimiter: u8) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .has_headers(self.in_conf.header_row == input::HeaderRow::Present)
+            .delimiter(delimiter)
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .quote(self.in_conf.csv.quote)
+            .escape(self.in_conf.csv.escape)
+            .double_quote(self.in_conf.csv.double_quote)
+            .comment(self.in_conf.csv.comment)
+            .from_reader(reader)
+    }
+
+    /// Encode the record as a single QR Code or, with structured append, split it across several when it is too long
+    /// for one of the maximum version.
+    fn encode_parts(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        segments: Vec<qrcodegen::QrSegment>,
+        payload: &[u8],
+        overrides: &record::Overrides,
+    ) -> Option<Vec<(qrcodegen::QrCode, Vec<qrcodegen::QrSegment>)>> {
+        if !self.qr_conf.structured_append.split {
+            return self
+                .encode(file_path, row, name, segments, overrides)
+                .map(|part| vec![part]);
+        }
+
+        let (qr_version_min, qr_version_max) =
+            self.version_range(file_path, row, name, overrides)?;
+        let error_correction = overrides
+            .error_correction
+            .unwrap_or(self.qr_conf.error_correction);
+        let mask = overrides.mask.or(self.qr_conf.mask);
+        if let Ok(qr) = qrcodegen::QrCode::encode_segments_advanced(
+            &segments,
+            error_correction,
+            qr_version_min,
+            qr_version_max,
+            mask,
+            true,
+        ) {
+            return Some(vec![(qr, segments)]);
+        }
+
+        match structured::encode(
+            payload,
+            error_correction,
+            qr_version_min,
+            qr_version_max,
+            mask,
+        ) {
+            Ok(parts) => {
+                info!(
+                    "splitting {} across {} QR Codes",
+                    describe(file_path, row, name),
+                    parts.len()
+                );
+                Some(parts)
+            }
+            Err(e) => {
+                warn!(
+                    file:% = file_path.display(), row = row;
+                    "error generating for {} {}",
+                    describe(file_path, row, name),
+                    e
+                );
+                self.reject(file_path, row, name, exit::Failure::Record, e);
+                None
+            }
+        }
+    }
+
+    fn encode(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        segment: Vec<qrcodegen::QrSegment>,
+        overrides: &record::Overrides,
+    ) -> Option<(qrcodegen::QrCode, Vec<qrcodegen::QrSegment>)> {
+        let (qr_version_min, qr_version_max) =
+            self.version_range(file_path, row, name, overrides)?;
+
+        for s in segment.iter() {
+            trace!(
+                "encoding mode = {:?},  character count = {:?}",
+                manifest::mode_name(s.mode()),
+                s.num_chars()
+            );
+        }
+
+        match qrcodegen::QrCode::encode_segments_advanced(
+            &segment,
+            overrides
+                .error_correction
+                .unwrap_or(self.qr_conf.error_correction),
+            qr_version_min,
+            qr_version_max,
+            overrides.mask.or(self.qr_conf.mask),
+            true,
+        ) {
+            Ok(qr) => Some((qr, segment)),
+            Err(e) => {
+                warn!(
+                    file:% = file_path.display(), row = row;
+                    "error generating for {} {:?}",
+                    describe(file_path, row, name),
+                    e
+                );
+                self.reject(
+                    file_path,
+                    row,
+                    name,
+                    exit::Failure::Record,
+                    format!("{:?}", e),
+                );
+                None
+            }
+        }
+    }
+
+    // The record's smallest and largest versions, rejecting it if the smallest is greater.
+    fn version_range(
+        &self,
+        file_path: &Path,
+        row: u64,
+        name: &str,
+        overrides: &record::Overrides,
+    ) -> Option<(qrcodegen::Version, qrcodegen::Version)> {
+        let qr_version_min = overrides
+            .qr_version_min
+            .unwrap_or(self.qr_conf.qr_version_min);
+        let qr_version_max = overrides
+            .qr_version_max
+            .unwrap_or(self.qr_conf.qr_version_max);
+
+        if qr_version_min > qr_version_max {
+            warn!(
+                file:% = file_path.display(), row = row;
+                "error generating for {} minimum version {} is greater than maximum version {}",
+                describe(file_path, row, name),
+                qr_version_min.value(),
+                qr_version_max.value()
+            );
+            self.reject(
+                file_path,
+                row,
+                name,
+                exit::Failure::Record,
+                format!(
+                    "minimum version {} is greater than maximum version {}",
+                    qr_version_min.value(),
+                    qr_version_max.value()
+                ),
+            );
+            return None;
+        }
+
+        Some((qr_version_min, qr_version_max))
+    }
+}
+
+/// Where a record came from for warnings, e.g. orders.csv row 12 (A-1), or just the name for a single value.
+fn describe(file_path: &Path, row: u64, name: &str) -> String {
+    if file_path.as_os_str().is_empty() {
+        return name.to_string();
+    }
+
+    format!("{} row {} ({})", file_path.display(), row, name)
+}
+
+/// The file name of a part of a structured append sequence, e.g. A-1-2of3, or just the name for a single QR Code.
+fn part_name(name: &str, part: usize, parts: usize) -> String {
+    if parts == 1 {
+        return name.to_string();
+    }
+
+    format!("{}-{}of{}", name, part + 1, parts)
+}
+
+// The most memory drawing and writing one of the record's QR Codes can take, in the largest of its formats.
+fn image_bytes(qr_conf: &QrConfig, out_conf: &OutputConfig, overrides: &record::Overrides) -> u64 {
+    overrides
+        .formats
+        .as_ref()
+        .unwrap_or(&out_conf.formats)
+        .iter()
+        .map(|format| {
+            budget::image_bytes(
+                overrides
+                    .qr_version_max
+                    .unwrap_or(qr_conf.qr_version_max)
+                    .value(),
+                overrides.border.unwrap_or(out_conf.border),
+                overrides.scale.unwrap_or(out_conf.scale),
+                format,
+            )
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+// Everything besides a record's data and overrides that changes its QR Code, so changing any of it invalidates the
+// cache.
+fn cache_settings(qr_conf: &QrConfig, out_conf: &OutputConfig) -> String {
+    format!(
+        "{} {} {:?} {:?} {:?} {} {:?} {} {} {:?} {:?}",
+        qr_conf.qr_version_min.value(),
+        qr_conf.qr_version_max.value(),
+        qr_conf.error_correction,
+        qr_conf.mask.map(|mask| mask.value()),
+        qr_conf.structured_append,
+        out_conf.border,
+        out_conf.formats,
+        out_conf.scale,
+        out_conf.no_rect,
+        out_conf.foreground,
+        out_conf.background
+    )
+}
+
+/// Where the data of each record comes from once the columns have been found in a file.
+enum Payload {
+    Column(usize),
+    Template(template::ResolvedTemplate),
+    Build(payload::ResolvedPayload),
+    Jwt(jwt::ResolvedClaims, sign::Signer),
+}
+
+impl Payload {
+    fn render(&self, row: &csv::StringRecord) -> Result<String, String> {
+        match self {
+            Payload::Column(index) => Ok(row.get(*index).unwrap_or_default().to_string()),
+            Payload::Template(template) => Ok(template.render(row)),
+            Payload::Build(payload) => payload.render(row),
+            Payload::Jwt(claims, signer) => claims.render(row, signer),
+        }
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "qr_conf = [QR Version Min:{}, QR Version Max:{}, Error Correction: {}, Mask:{}, Structured Append: {:?}], \
+             in_conf = [Header Row:{:?}, Input Format: {:?}, Delimiter: {:?}, Sheet: {:?}, Encoding: {:?}, Name Column: {}, Data Column: {}, Mtime Column: {:?}, Data: {}, Filter: {:?}, CSV: {:?}], \
+             proc_conf = [Chunk Size:{}, Naming: {:?}, Skip Rows: {}, Take: {:?}, Dedupe: {:?}, Errors: {:?}, URL Prefix: {:?}, URL Suffix: {:?}, Signer: {:?}, Limits: {:?}], \
+             out_conf: [Border:{}, Formats: {:?}, Exclude <rect />: {:?}, Foreground: {:?}, Fackgound: {:?}, Output: {}, On Exists: {:?}, Archive: {}, Link Identical: {}, Resume: {}, Cache: {}, Previous Manifest: {}, Prune: {}, File Names: {:?}], \
+             report_conf: [Manifest: {:?}, Signed Manifest: {}, Stats: {}, Stats File: {:?}, Capacity Warning: {:?}, Analyze Masks: {}, Duplicates: {:?}, Rejects: {:?}, Timings: {}, Progress Events: {}], \
+             batch_conf: [PDF: {:?}, Document: {:?}, Captions: {}, Contact Sheet: {:?}, Grid: {:?}, Labels: {:?}, Label Template: {:?}, Poster: {:?}, Poster Layout: {:?}, HTML: {:?}], \
+             input: Files: {:?}:",
+            self.qr_conf.qr_version_min.value(),
+            self.qr_conf.qr_version_max.value(),
+            match self.qr_conf.error_correction {
+                qrcodegen::QrCodeEcc::High => "High",
+                qrcodegen::QrCodeEcc::Low => "Low",
+                qrcodegen::QrCodeEcc::Quartile => "Quartile",
+                qrcodegen::QrCodeEcc::Medium => "Medium",
+            },
+            match self.qr_conf.mask {
+                Some(m) => m.value().to_string(),
+                _ => String::from("<Not Set>"),
+            },
+            self.qr_conf.structured_append,
+            self.in_conf.header_row,
+            self.in_conf.input_format,
+            self.in_conf.delimiter,
+            self.in_conf.sheet,
+            self.in_conf.encoding.map(encoding_rs::Encoding::name),
+            self.in_conf.columns.name,
+            self.in_conf.columns.data,
+            self.in_conf.columns.mtime.as_ref().map(ToString::to_string),
+            self.in_conf.data,
+            self.in_conf.filter.as_ref().map(ToString::to_string),
+            self.in_conf.csv,
+            self.proc_conf.chunk_size,
+            self.proc_conf.naming,
+            self.proc_conf.skip_rows,
+            self.proc_conf.take,
+            self.proc_conf.dedupe,
+            self.proc_conf.errors,
+            self.proc_conf.url_prefix,
+            self.proc_conf.url_suffix,
+            self.proc_conf.signer,
+            self.proc_conf.limits,
+            self.out_conf.border,
+            self.out_conf.formats,
+            self.out_conf.no_rect,
+            self.out_conf.foreground,
+            self.out_conf.background,
+            self.out_conf.output.display(),
+            self.out_conf.destination.on_exists,
+            self.out_conf.destination.archive.is_some(),
+            self.out_conf.destination.link_identical,
+            self.out_conf.incremental.resume,
+            self.out_conf.incremental.cache,
+            self.out_conf.incremental.previous.is_some(),
+            self.out_conf.incremental.prune,
+            self.out_conf.names,
+            self.report_conf.manifest.as_ref().map(|manifest| &manifest.path),
+            self.report_conf
+                .manifest
+                .as_ref()
+                .is_some_and(|manifest| manifest.key.is_some()),
+            self.report_conf.stats,
+            self.report_conf.stats_file,
+            self.report_conf.capacity_warn,
+            self.report_conf.analyze_masks,
+            self.report_conf.duplicates,
+            self.report_conf.rejects,
+            self.report_conf.timings,
+            self.report_conf.progress.is_some(),
+            self.batch_conf.pdf,
+            self.batch_conf.document,
+            self.batch_conf.captions,
+            self.batch_conf.sheet,
+            self.batch_conf.grid,
+            self.batch_conf.labels,
+            self.batch_conf.template,
+            self.batch_conf.poster,
+            self.batch_conf.layout,
+            self.batch_conf.html,
+            self.files,
+        )
+    }
+}
+
+pub struct QrConfig {
+    qr_version_min: qrcodegen::Version,
+    qr_version_max: qrcodegen::Version,
+    mask: Option<qrcodegen::Mask>,
+    error_correction: qrcodegen::QrCodeEcc,
+    structured_append: structured::StructuredAppend,
+}
+
+impl QrConfig {
+    pub fn new(
+        qr_version_min: qrcodegen::Version,
+        qr_version_max: qrcodegen::Version,
+        error_correction: qrcodegen::QrCodeEcc,
+        mask: Option<qrcodegen::Mask>,
+        structured_append: structured::StructuredAppend,
+    ) -> Self {
+        QrConfig {
+            qr_version_min,
+            qr_version_max,
+            mask,
+            error_correction,
+            structured_append,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct OutputConfig {
+    output: PathBuf,
+    border: u8,
+    formats: exporter::Formats,
+    scale: u32,
+    no_rect: bool,
+    foreground: (u8, u8, u8),
+    background: (u8, u8, u8),
+    destination: Destination,
+    incremental: Incremental,
+    names: FileNames,
+}
+
+impl OutputConfig {
+    pub fn new(
+        output: PathBuf,
+        border: u8,
+        formats: exporter::Formats,
+        scale: u32,
+        no_rect: bool,
+        foreground: (u8, u8, u8),
+        background: (u8, u8, u8),
+        destination: Destination,
+        incremental: Incremental,
+        names: FileNames,
+    ) -> Self {
+        OutputConfig {
+            output,
+            border,
+            formats,
+            scale,
+            no_rect,
+            foreground,
+            background,
+            destination,
+            incremental,
+            names,
+        }
+    }
+}
+
+/// Where the outputs are written, the output directory unless they go into an archive, what happens when one is
+/// already there and whether identical outputs are hard linked together.
+#[derive(Default, Debug)]
+pub struct Destination {
+    on_exists: exporter::OnExists,
+    archive: Option<archive::Archive>,
+    link_identical: bool,
+}
+
+impl Destination {
+    pub fn new(
+        on_exists: exporter::OnExists,
+        archive: Option<archive::Archive>,
+        link_identical: bool,
+    ) -> Self {
+        Destination {
+            on_exists,
+            archive,
+            link_identical,
+        }
+    }
+}
+
+/// How names from the input are turned into file names and which directory under the output they go in.
+#[derive(Clone, Debug)]
+pub struct FileNames {
+    ascii: bool,
+    case: record::NameCase,
+    max_length: usize,
+    shard: u8,
+    mirror: bool,
+    format_dirs: bool,
+}
+
+impl FileNames {
+    pub fn new(
+        ascii: bool,
+        case: record::NameCase,
+        max_length: usize,
+        shard: u8,
+        mirror: bool,
+        format_dirs: bool,
+    ) -> Self {
+        FileNames {
+            ascii,
+            case,
+            max_length,
+            shard,
+            mirror,
+            format_dirs,
+        }
+    }
+}
+
+impl Default for FileNames {
+    fn default() -> Self {
+        // The limit of most file systems, in bytes.
+        FileNames::new(false, Default::default(), 255, 0, false, false)
+    }
+}
+
+/// How a run builds on the outputs of earlier runs instead of generating everything again.
+#[derive(Default, Clone, Debug)]
+pub struct Incremental {
+    resume: bool,
+    cache: bool,
+    previous: Option<manifest::Previous>,
+    prune: bool,
+}
+
+impl Incremental {
+    pub fn new(
+        resume: bool,
+        cache: bool,
+        previous: Option<manifest::Previous>,
+        prune: bool,
+    ) -> Self {
+        Incremental {
+            resume,
+            cache,
+            previous,
+            prune,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct InputConfig {
+    header_row: input::HeaderRow,
+    input_format: input::InputFormat,
+    delimiter: Option<input::Delimiter>,
+    sheet: Option<String>,
+    headers: Vec<(String, String)>,
+    encoding: Option<&'static encoding_rs::Encoding>,
+    columns: columns::ColumnMap,
+    data: payload::DataSource,
+    filter: Option<filter::Filter>,
+    csv: input::CsvOptions,
+}
+
+impl InputConfig {
+    pub fn new(
+        header_row: input::HeaderRow,
+        input_format: input::InputFormat,
+        delimiter: Option<input::Delimiter>,
+        sheet: Option<String>,
+        headers: Vec<(String, String)>,
+        encoding: Option<&'static encoding_rs::Encoding>,
+        columns: columns::ColumnMap,
+        data: payload::DataSource,
+        filter: Option<filter::Filter>,
+        csv: input::CsvOptions,
+    ) -> Self {
+        InputConfig {
+            header_row,
+            input_format,
+            delimiter,
+            sheet,
+            headers,
+            encoding,
+            columns,
+            data,
+            filter,
+            csv,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct ProcessingConfig {
+    chunk_size: usize,
+    naming: record::Naming,
+    skip_rows: usize,
+    take: Option<usize>,
+    dedupe: Option<dedupe::Dedupe>,
+    errors: ErrorPolicy,
+    url_prefix: Option<String>,
+    url_suffix: Option<String>,
+    signer: Option<sign::Signer>,
+    limits: Limits,
+}
+
+impl ProcessingConfig {
+    pub fn new(
+        chunk_size: usize,
+        naming: record::Naming,
+        skip_rows: usize,
+        take: Option<usize>,
+        dedupe: Option<dedupe::Dedupe>,
+        errors: ErrorPolicy,
+        url_prefix: Option<String>,
+        url_suffix: Option<String>,
+        signer: Option<sign::Signer>,
+        limits: Limits,
+    ) -> Self {
+        ProcessingConfig {
+            chunk_size,
+            naming,
+            skip_rows,
+            take,
+            dedupe,
+            errors,
+            url_prefix,
+            url_suffix,
+            signer,
+            limits,
+        }
+    }
+}
+
+/// What happens to blank records, unsafe names and names used twice, whether the run stops at the first failure and
+/// how failed writes are retried.
+#[derive(Default, Clone, Debug)]
+pub struct ErrorPolicy {
+    on_blank: record::BlankPolicy,
+    on_unsafe_name: record::UnsafeNamePolicy,
+    on_duplicate_name: record::DuplicateNamePolicy,
+    fail_fast: bool,
+    retry: retry::Retry,
+}
+
+impl ErrorPolicy {
+    pub fn new(
+        on_blank: record::BlankPolicy,
+        on_unsafe_name: record::UnsafeNamePolicy,
+        on_duplicate_name: record::DuplicateNamePolicy,
+        fail_fast: bool,
+        retry: retry::Retry,
+    ) -> Self {
+        ErrorPolicy {
+            on_blank,
+            on_unsafe_name,
+            on_duplicate_name,
+            fail_fast,
+            retry,
+        }
+    }
+}
+
+/// How much of the machine a run can use.
+#[derive(Default, Clone, Debug)]
+pub struct Limits {
+    parallel_files: usize,
+    max_memory: Option<budget::MemoryBudget>,
+    record_timeout: Option<Duration>,
+    space_check: bool,
+}
+
+impl Limits {
+    pub fn new(
+        parallel_files: usize,
+        max_memory: Option<budget::MemoryBudget>,
+        record_timeout: Option<Duration>,
+        space_check: bool,
+    ) -> Self {
+        Limits {
+            parallel_files,
+            max_memory,
+            record_timeout,
+            space_check,
+        }
+    }
+}
+
+/// Where the manifest is written, the key it is signed with, if any, whether this run is added to the runs already in
+/// it and the id the run's entries are given.
+#[derive(Clone, Debug)]
+pub struct ManifestConfig {
+    path: PathBuf,
+    key: Option<minisign::SecretKey>,
+    append: bool,
+    run_id: String,
+}
+
+impl ManifestConfig {
+    pub fn new(
+        path: PathBuf,
+        key: Option<minisign::SecretKey>,
+        append: bool,
+        run_id: String,
+    ) -> Self {
+        ManifestConfig {
+            path,
+            key,
+            append,
+            run_id,
+        }
+    }
+}
+
+/// The outputs that hold every QR Code of the run together rather than one for each record.
+#[derive(Default, Clone, Debug)]
+pub struct BatchConfig {
+    pdf: Option<PathBuf>,
+    document: pdf::Document,
+    captions: bool,
+    sheet: Option<PathBuf>,
+    grid: sheet::Grid,
+    labels: Option<PathBuf>,
+    template: labels::Template,
+    poster: Option<PathBuf>,
+    layout: poster::Layout,
+    html: Option<PathBuf>,
+}
+
+impl BatchConfig {
+    pub fn new(
+        pdf: Option<PathBuf>,
+        document: pdf::Document,
+        captions: bool,
+        sheet: Option<PathBuf>,
+        grid: sheet::Grid,
+        labels: Option<PathBuf>,
+        template: labels::Template,
+        poster: Option<PathBuf>,
+        layout: poster::Layout,
+        html: Option<PathBuf>,
+    ) -> Self {
+        BatchConfig {
+            pdf,
+            document,
+            captions,
+            sheet,
+            grid,
+            labels,
+            template,
+            poster,
+            layout,
+            html,
+        }
+    }
+
+    // Whether the QR Codes need keeping until the end of the run.
+    fn needs_codes(&self) -> bool {
+        self.pdf.is_some()
+            || self.sheet.is_some()
+            || self.labels.is_some()
+            || self.poster.is_some()
+            || self.html.is_some()
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct ReportConfig {
+    manifest: Option<ManifestConfig>,
+    stats: bool,
+    stats_file: Option<PathBuf>,
+    capacity_warn: Option<f64>,
+    analyze_masks: bool,
+    duplicates: Option<PathBuf>,
+    rejects: Option<PathBuf>,
+    checksums: Option<PathBuf>,
+    timings: bool,
+    progress: Option<progress::Progress>,
+}
+
+impl ReportConfig {
+    pub fn new(
+        manifest: Option<ManifestConfig>,
+        stats: bool,
+        stats_file: Option<PathBuf>,
+        capacity_warn: Option<f64>,
+        analyze_masks: bool,
+        duplicates: Option<PathBuf>,
+        rejects: Option<PathBuf>,
+        checksums: Option<PathBuf>,
+        timings: bool,
+        progress: Option<progress::Progress>,
+    ) -> Self {
+        ReportConfig {
+            manifest,
+            stats,
+            stats_file,
+            capacity_warn,
+            analyze_masks,
+            duplicates,
+            rejects,
+            checksums,
+            timings,
+            progress,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Cursor};
+
+    fn default_generator() -> Generator {
+        Generator::new(
+            Vec::new(),
+            QrConfig::new(
+                qrcodegen::Version::new(1),
+                qrcodegen::Version::new(2),
+                qrcodegen::QrCodeEcc::High,
+                None,
+                Default::default(),
+            ),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    // An empty directory for a test's outputs, named for the test in the temp directory.
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("qrgen_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn generator_into(output: &Path) -> Generator {
+        let mut gen = default_generator();
+        gen.out_conf.output = output.to_path_buf();
+        gen
+    }
+
+    // Generate records of a name and data, numbering their rows from 1, as if read from a file named test.
+    fn generate(gen: &Generator, records: &[(&str, &str)]) {
+        gen.generate_records(
+            Path::new("test"),
+            records.iter().enumerate().map(|(i, (name, data))| {
+                record::Record::new(i as u64 + 1, (*name).into(), (*data).into())
+            }),
+        );
+    }
+
+    #[test]
+    fn ensure_csv_is_flexible_and_reads_header() {
+        let gen = default_generator();
+        let buff = Cursor::new("file_name,qr_data\nfile_name,qr_data,extra");
+
+        let reader = gen.csv_reader(buff, b',');
+        let all_ok = reader.into_records().all(|r| r.is_ok());
+
+        assert!(all_ok);
+    }
+
+    #[test]
+    fn ensure_csv_skips_header() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        let buff = Cursor::new("file_name,qr_data\nfile_name,qr_data,extra");
+
+        let reader = gen.csv_reader(buff, b',');
+        let count = reader.into_records().count();
+
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn ensure_csv_records_detect_header_row() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Auto;
+
+        let buff = Cursor::new("filename,data\na,1\nb,2");
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!(record::Record::new(2, "a".into(), "1".into()), records[0]);
+
+        let buff = Cursor::new("a,1\nb,2");
+        assert_eq!(
+            2,
+            gen.csv_records(Path::new("test.csv"), buff, b',')
+                .unwrap()
+                .count()
+        );
+    }
+
+    #[test]
+    fn ensure_csv_records_only_check_used_fields_are_utf8() {
+        let gen = default_generator();
+        let buff = Cursor::new(&b"a,1,\xff\nb,\xff\nc,3\n"[..]);
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(
+            vec![
+                record::Record::new(1, "a".into(), "1".into()),
+                record::Record::new(3, "c".into(), "3".into()),
+            ],
+            records
+        );
+        assert_eq!(1, gen.failures().total());
+    }
+
+    #[test]
+    fn ensure_csv_records_leave_missing_fields_empty() {
+        let gen = default_generator();
+        let buff = Cursor::new("a,1\nb\nc,3,extra");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(3, records.len());
+        assert_eq!(record::Record::new(1, "a".into(), "1".into()), records[0]);
+        assert_eq!(record::Record::new(2, "b".into(), "".into()), records[1]);
+        assert_eq!(record::Record::new(3, "c".into(), "3".into()), records[2]);
+    }
+
+    #[test]
+    fn ensure_blank_policy_applies() {
+        let mut gen = default_generator();
+        let path = Path::new("in.csv");
+        let full = record::Record::new(1, "a".into(), "1".into());
+        let no_data = record::Record::new(2, "b".into(), "".into());
+        let no_name = record::Record::new(3, "".into(), "3".into());
+
+        assert_eq!(Ok(true), gen.check_blank(path, &full));
+        assert_eq!(Ok(false), gen.check_blank(path, &no_data));
+        assert_eq!(Ok(false), gen.check_blank(path, &no_name));
+
+        gen.proc_conf.errors.on_blank = record::BlankPolicy::Error;
+        assert_eq!(
+            Err("in.csv row 2 has no data".to_string()),
+            gen.check_blank(path, &no_data)
+        );
+
+        gen.proc_conf.naming = record::Naming::Hash;
+        assert_eq!(Ok(true), gen.check_blank(path, &no_name));
+    }
+
+    #[test]
+    fn ensure_fail_fast_stops_generating() {
+        let dir = output_dir("ensure_fail_fast_stops_generating");
+        let mut gen = generator_into(&dir);
+        gen.proc_conf.errors.fail_fast = true;
+        assert!(!gen.stopped());
+
+        gen.fail(exit::Failure::Record);
+        assert!(gen.stopped());
+        generate(&gen, &[("a", "1")]);
+
+        assert!(!dir.join("a.svg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_timed_out_records_are_rejected() {
+        let dir = output_dir("ensure_timed_out_records_are_rejected");
+        let mut gen = generator_into(&dir);
+        gen.proc_conf.limits = Limits::new(1, None, Some(Duration::from_nanos(1)), false);
+        gen.report_conf.rejects = Some(dir.join("rejects.csv"));
+
+        generate(&gen, &[("a", "1")]);
+
+        assert!(!dir.join("a.svg").exists());
+        assert_eq!(1, gen.failures().get(exit::Failure::Record));
+        assert_eq!(
+            "input,row,name,reason\ntest,1,a,timed out after 1ns\n",
+            fs::read_to_string(dir.join("rejects.csv")).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_timed_out_writes_leave_no_output() {
+        let dir = output_dir("ensure_timed_out_writes_leave_no_output");
+        let gen = generator_into(&dir);
+        let qr_code = qrcodegen::QrCode::encode_text("1", qrcodegen::QrCodeEcc::Low).unwrap();
+        let exp = exporter::Exporter::new(
+            qr_code,
+            dir.clone(),
+            0,
+            exporter::ExportFormat::SVG,
+            "a".into(),
+            1,
+            false,
+            (0, 0, 0),
+            (255, 255, 255),
+        );
+
+        let written = gen.write_output(&exp, b"<svg/>".to_vec(), Some(Instant::now()));
+        thread::sleep(Duration::from_millis(200));
+
+        // Either the write beat the deadline or it was given up on, and then nothing is left behind.
+        let files = fs::read_dir(&dir).unwrap().count();
+        match written {
+            Ok(Some(path)) => assert_eq!((1, dir.join("a.svg")), (files, path)),
+            other => assert_eq!(
+                (0, Some(io::ErrorKind::TimedOut)),
+                (files, other.err().map(|e| e.kind()))
+            ),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_disk_space_is_checked() {
+        let file = std::env::temp_dir().join("qrgen_ensure_disk_space_is_checked.csv");
+        fs::write(&file, "name,data\na,1\nb,2\nc,3\n").unwrap();
+        let mut gen = generator_into(&std::env::temp_dir());
+        gen.files = vec![file.clone()];
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.proc_conf.limits = Limits::new(1, None, None, true);
+
+        assert_eq!(3, gen.count_records());
+        assert!(gen.check_disk_space(|| gen.count_records()).is_ok());
+        assert!(gen.check_disk_space(|| u64::MAX / 2).is_err());
+
+        // The newlines in compressed data say nothing about the number of records.
+        let gzip = file.with_extension("csv.gz");
+        fs::write(&gzip, "\n\n\n").unwrap();
+        gen.files.push(gzip.clone());
+        assert_eq!(3, gen.count_records());
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(&gzip).unwrap();
+    }
+
+    #[test]
+    fn ensure_resume_skips_written_records() {
+        let dir = output_dir("ensure_resume_skips_written_records");
+        fs::write(dir.join("a.svg"), "<svg/>").unwrap();
+        fs::write(dir.join("b.svg"), "").unwrap();
+        let mut gen = generator_into(&dir);
+        gen.out_conf.incremental.resume = true;
+
+        generate(&gen, &[("a", "1"), ("b", "2")]);
+
+        assert_eq!("<svg/>", fs::read_to_string(dir.join("a.svg")).unwrap());
+        assert!(fs::metadata(dir.join("b.svg")).unwrap().len() > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_cache_skips_unchanged_records() {
+        let dir = output_dir("ensure_cache_skips_unchanged_records");
+        let run = |records: &[(&str, &str)]| {
+            let mut gen = generator_into(&dir);
+            gen.cache = Some(cache::Cache::load(&dir));
+            generate(&gen, records);
+            gen.write_reports();
+        };
+
+        run(&[("a", "1"), ("b", "2")]);
+        fs::write(dir.join("a.svg"), "<svg/>").unwrap();
+        fs::write(dir.join("b.svg"), "<svg/>").unwrap();
+        run(&[("a", "1"), ("b", "changed")]);
+
+        assert_eq!("<svg/>", fs::read_to_string(dir.join("a.svg")).unwrap());
+        assert_ne!("<svg/>", fs::read_to_string(dir.join("b.svg")).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_previous_manifest_only_generates_changes() {
+        let dir = output_dir("ensure_previous_manifest_only_generates_changes");
+        let manifest = dir.join("manifest.csv");
+        let generator = || {
+            let mut gen = generator_into(&dir);
+            gen.report_conf.manifest = Some(ManifestConfig::new(
+                manifest.clone(),
+                None,
+                false,
+                "1".into(),
+            ));
+            gen
+        };
+        generate(&generator(), &[("a", "1"), ("b", "2"), ("c", "3")]);
+        fs::write(dir.join("a.svg"), "<svg/>").unwrap();
+        fs::write(dir.join("b.svg"), "<svg/>").unwrap();
+
+        let mut gen = generator();
+        gen.out_conf.incremental = Incremental::new(
+            false,
+            false,
+            Some(manifest::Previous::read(&manifest).unwrap()),
+            true,
+        );
+        generate(&gen, &[("a", "1"), ("b", "changed")]);
+
+        assert_eq!("<svg/>", fs::read_to_string(dir.join("a.svg")).unwrap());
+        assert_ne!("<svg/>", fs::read_to_string(dir.join("b.svg")).unwrap());
+        assert!(!dir.join("c.svg").exists());
+        let previous = manifest::Previous::read(&manifest).unwrap();
+        assert!(!previous.get("a").is_empty() && previous.get("c").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_appended_manifest_entries_are_written_once() {
+        let dir = output_dir("ensure_appended_manifest_entries_are_written_once");
+        let manifest = dir.join("manifest.csv");
+        let mut gen = generator_into(&dir);
+        gen.report_conf.manifest = Some(ManifestConfig::new(
+            manifest.clone(),
+            None,
+            true,
+            "1".into(),
+        ));
+
+        for name in ["a", "b", "c"] {
+            gen.generate_entry(
+                Path::new("queue"),
+                &record::Record::new(1, name.into(), name.into()),
+            );
+            gen.write_reports();
+        }
+
+        let rows = csv::Reader::from_path(&manifest).unwrap().records().count();
+        assert_eq!(3, rows);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_filtered_runs_do_not_prune() {
+        let dir = output_dir("ensure_filtered_runs_do_not_prune");
+        let manifest = dir.join("manifest.csv");
+        let mut gen = generator_into(&dir);
+        gen.report_conf.manifest = Some(ManifestConfig::new(
+            manifest.clone(),
+            None,
+            false,
+            "1".into(),
+        ));
+        generate(&gen, &[("a", "1"), ("b", "2")]);
+
+        gen.in_conf.filter = Some(filter::Filter::parse("country == \"DE\"").unwrap());
+        gen.out_conf.incremental = Incremental::new(
+            false,
+            false,
+            Some(manifest::Previous::read(&manifest).unwrap()),
+            true,
+        );
+        generate(&gen, &[("a", "1")]);
+
+        assert!(dir.join("a.svg").is_file() && dir.join("b.svg").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_unsafe_names_are_replaced_or_skipped() {
+        let dir = output_dir("ensure_unsafe_names_are_replaced_or_skipped");
+        fs::create_dir(dir.join("sub")).unwrap();
+        let mut gen = generator_into(&dir.join("sub"));
+
+        generate(&gen, &[("../a", "1")]);
+        assert!(dir.join("sub").join("_a.svg").exists());
+        assert!(!dir.join("a.svg").exists());
+
+        gen.proc_conf.errors.on_unsafe_name = record::UnsafeNamePolicy::Skip;
+        fs::remove_file(dir.join("sub").join("_a.svg")).unwrap();
+        generate(&gen, &[("../a", "1")]);
+        assert!(!dir.join("sub").join("_a.svg").exists());
+        assert_eq!(1, gen.failures().get(exit::Failure::Record));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_duplicate_names_are_suffixed() {
+        let dir = output_dir("ensure_duplicate_names_are_suffixed");
+        let gen = generator_into(&dir);
+
+        generate(&gen, &[("a", "1"), ("a", "2")]);
+
+        assert!(dir.join("a.svg").exists());
+        assert!(dir.join("a-2.svg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_names_can_be_ascii() {
+        let dir = output_dir("ensure_names_can_be_ascii");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.names = FileNames::new(true, Default::default(), 255, 0, false, false);
+
+        generate(&gen, &[("Müller", "1")]);
+
+        assert!(dir.join("Muller.svg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_each_format_is_written() {
+        let dir = output_dir("ensure_each_format_is_written");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.formats = exporter::Formats::new(vec![
+            exporter::ExportFormat::SVG,
+            exporter::ExportFormat::PNG,
+        ])
+        .unwrap();
+        gen.out_conf.scale = 2;
+        gen.report_conf.manifest = Some(ManifestConfig::new(
+            dir.join("manifest.csv"),
+            None,
+            false,
+            "1".into(),
+        ));
+
+        generate(&gen, &[("a", "1")]);
+
+        assert!(dir.join("a.svg").is_file() && dir.join("a.png").is_file());
+        let previous = manifest::Previous::read(&dir.join("manifest.csv")).unwrap();
+        assert_eq!(2, previous.get("a").len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_long_records_are_split_with_structured_append() {
+        let dir = output_dir("ensure_long_records_are_split_with_structured_append");
+        let mut gen = generator_into(&dir);
+        gen.qr_conf.structured_append = structured::StructuredAppend::new(true, Duration::ZERO);
+        gen.out_conf.formats = exporter::Formats::new(vec![
+            exporter::ExportFormat::SVG,
+            exporter::ExportFormat::GIF,
+        ])
+        .unwrap();
+        gen.out_conf.scale = 1;
+
+        // Version 2 at High holds 12 bytes after the structured append header.
+        generate(&gen, &[("a", &"x".repeat(60)), ("b", "short")]);
+
+        for part in 1..=5 {
+            assert!(dir.join(format!("a-{}of5.svg", part)).is_file());
+        }
+        assert!(dir.join("a.gif").is_file() && !dir.join("a.svg").exists());
+        assert!(dir.join("b.svg").is_file() && dir.join("b.gif").is_file());
+        assert_eq!(0, gen.failures().total());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_checksums_cover_outputs_and_links() {
+        let dir = output_dir("ensure_checksums_cover_outputs_and_links");
+        let mut gen = generator_into(&dir);
+        gen.proc_conf.dedupe = Some(dedupe::Dedupe::Link);
+        gen.report_conf.checksums = Some(dir.join("SHA256SUMS"));
+
+        generate(&gen, &[("a", "1"), ("b", "1")]);
+
+        let sum = checksums::sha256(&fs::read(dir.join("a.svg")).unwrap());
+        assert_eq!(
+            format!("{0}  a.svg\n{0}  b.svg\n", sum),
+            fs::read_to_string(dir.join("SHA256SUMS")).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_identical_outputs_are_linked() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = output_dir("ensure_identical_outputs_are_linked");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.destination.link_identical = true;
+
+        // Records are generated in parallel, so write the first before the ones that may repeat it.
+        generate(&gen, &[("a", "1")]);
+        generate(&gen, &[("b", "2"), ("c", "1")]);
+
+        let inode = |name: &str| fs::metadata(dir.join(name)).unwrap().ino();
+        assert_eq!(inode("a.svg"), inode("c.svg"));
+        assert_ne!(inode("a.svg"), inode("b.svg"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_formats_can_have_their_own_dirs() {
+        let dir = output_dir("ensure_formats_can_have_their_own_dirs");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.formats = exporter::Formats::new(vec![
+            exporter::ExportFormat::SVG,
+            exporter::ExportFormat::PNG,
+        ])
+        .unwrap();
+        gen.out_conf.scale = 2;
+        gen.out_conf.names = FileNames::new(false, Default::default(), 255, 0, false, true);
+
+        generate(&gen, &[("a", "1")]);
+
+        assert!(dir.join("svg").join("a.svg").is_file());
+        assert!(dir.join("png").join("a.png").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_listed_records_return_their_outputs() {
+        let dir = output_dir("ensure_listed_records_return_their_outputs");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.formats = exporter::Formats::new(vec![
+            exporter::ExportFormat::SVG,
+            exporter::ExportFormat::PNG,
+        ])
+        .unwrap();
+        gen.out_conf.scale = 2;
+        gen.out_conf.names = FileNames::new(false, Default::default(), 255, 0, false, true);
+        let records = vec![
+            record::Record::new(1, "a".into(), "1".into()),
+            record::Record::new(2, "b/".into(), "2".into()),
+            record::Record::new(3, "c".into(), "x".repeat(100)),
+        ];
+
+        let outputs = gen.generate_listed(Path::new("uuid"), &records);
+
+        assert_eq!(
+            vec![
+                vec![dir.join("svg").join("a.svg"), dir.join("png").join("a.png")],
+                vec![
+                    dir.join("svg").join("b_.svg"),
+                    dir.join("png").join("b_.png")
+                ],
+                Vec::new()
+            ],
+            outputs
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_records_can_override_formats() {
+        let dir = output_dir("ensure_records_can_override_formats");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.scale = 2;
+        let mut png = record::Record::new(1, "a".into(), "1".into());
+        png.overrides.formats = Some(exporter::ExportFormat::PNG.into());
+
+        gen.generate_records(
+            Path::new("test"),
+            vec![png, record::Record::new(2, "b".into(), "2".into())].into_iter(),
+        );
+
+        assert!(dir.join("a.png").is_file());
+        assert!(!dir.join("a.svg").exists());
+        assert!(dir.join("b.svg").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_outputs_are_sharded() {
+        let dir = output_dir("ensure_outputs_are_sharded");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.names = FileNames::new(false, Default::default(), 255, 2, false, false);
+
+        generate(&gen, &[("hello", "1")]);
+
+        assert!(dir.join("2c").join("f2").join("hello.svg").is_file());
+        assert!(!dir.join("hello.svg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_input_dirs_are_mirrored() {
+        let dir = output_dir("ensure_input_dirs_are_mirrored");
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        for sub in &["a", "b"] {
+            fs::create_dir_all(input.join(sub)).unwrap();
+            fs::write(input.join(sub).join("in.csv"), format!("{},1\n", sub)).unwrap();
+        }
+        fs::create_dir(&output).unwrap();
+        let mut gen = generator_into(&output);
+        gen.out_conf.names = FileNames::new(false, Default::default(), 255, 0, true, false);
+        gen.files = vec![
+            input.join("a").join("in.csv"),
+            input.join("b").join("in.csv"),
+        ];
+
+        gen.generate();
+
+        assert!(output.join("a").join("a.svg").is_file());
+        assert!(output.join("b").join("b.svg").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_names_follow_name_case() {
+        let dir = output_dir("ensure_names_follow_name_case");
+        let mut gen = generator_into(&dir);
+        gen.out_conf.names = FileNames::new(false, record::NameCase::Lower, 255, 0, false, false);
+        gen.report_conf.manifest = Some(ManifestConfig::new(
+            dir.join("manifest.csv"),
+            None,
+            false,
+            "1".into(),
+        ));
+
+        generate(&gen, &[("SKU-1A", "1")]);
+
+        let written: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(written.iter().any(|name| name == "sku-1a.svg"));
+        assert!(!written.iter().any(|name| name == "SKU-1A.svg"));
+        let previous = manifest::Previous::read(&dir.join("manifest.csv")).unwrap();
+        assert_eq!(
+            Some("SKU-1A"),
+            previous.get("sku-1a")[0].renamed_from.as_deref()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_mtime_is_set_from_column() {
+        let dir = output_dir("ensure_mtime_is_set_from_column");
+        let input = dir.join("in.csv");
+        fs::write(&input, "a,1,2024-05-01\nb,2,\n").unwrap();
+        let mut gen = generator_into(&dir);
+        gen.files = vec![input];
+        gen.in_conf.columns.mtime = Some(columns::Column::Index(2));
+
+        gen.generate();
+
+        let mtime = |name: &str| fs::metadata(dir.join(name)).unwrap().modified().unwrap();
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_521_600),
+            mtime("a.svg")
+        );
+        assert!(mtime("b.svg") > SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_521_600));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_failures_are_counted_by_kind() {
+        let file = std::env::temp_dir().join("qrgen_ensure_failures_are_counted_by_kind");
+        fs::write(&file, "not a directory").unwrap();
+        let mut gen = generator_into(&file);
+        gen.files = vec![file.with_extension("missing")];
+
+        gen.generate();
+        generate(&gen, &[("a", "1")]);
+
+        assert_eq!(1, gen.failures().get(exit::Failure::Input));
+        assert_eq!(1, gen.failures().get(exit::Failure::Output));
+        assert_eq!(Some(exit::Failure::Output), gen.failures().worst());
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn ensure_csv_records_use_mapped_columns() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.columns = columns::ColumnMap::new(
+            columns::Column::Name("sku".into()),
+            columns::Column::Index(4),
+            None,
+            Default::default(),
+        );
+        let buff = Cursor::new("id,sku,a,b,url\n1,x1,,,https://a\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(
+            vec![record::Record::new(2, "x1".into(), "https://a".into())],
+            records
+        );
+    }
+
+    #[test]
+    fn ensure_csv_records_read_overrides() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.columns.overrides.ecc = Some(columns::Column::Name("ecc".into()));
+        let buff = Cursor::new("name,url,ecc\na,https://a,low\nb,https://b,\nc,https://c,none\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(
+            vec![Some(qrcodegen::QrCodeEcc::Low), None],
+            records
+                .iter()
+                .map(|r| r.overrides.error_correction)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, gen.failures().get(exit::Failure::Record));
+    }
+
+    #[test]
+    fn ensure_csv_overrides_that_are_not_utf8_are_rejected() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.columns.overrides.ecc = Some(columns::Column::Name("ecc".into()));
+        let buff = Cursor::new(&b"name,url,ecc\na,https://a,low\nb,https://b,l\xffw\n"[..]);
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(1, records.len());
+        assert_eq!("a", records[0].name);
+        assert_eq!(1, gen.failures().get(exit::Failure::Record));
+        let dir = output_dir("ensure_csv_overrides_that_are_not_utf8_are_rejected");
+        gen.rejects.write(&dir.join("rejects.csv")).unwrap();
+        let rejects = fs::read_to_string(dir.join("rejects.csv")).unwrap();
+        assert!(rejects.contains("test.csv,3,b,Fields must be valid UTF-8."));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_invalid_overrides_are_rejected() {
+        let dir = output_dir("ensure_invalid_overrides_are_rejected");
+        let input = dir.join("in.csv");
+        fs::write(
+            &input,
+            "name,data,format,scale
+a,1,svg|png,
+b,2,,0
+c,3,png,2
+",
+        )
+        .unwrap();
+        let mut gen = generator_into(&dir);
+        gen.files = vec![input.clone()];
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.columns.overrides.format = Some(columns::Column::Name("format".into()));
+        gen.in_conf.columns.overrides.scale = Some(columns::Column::Name("scale".into()));
+        gen.report_conf.rejects = Some(dir.join("rejects.csv"));
+
+        gen.generate();
+
+        assert!(dir.join("c.png").is_file());
+        assert!(!dir.join("a.svg").exists() && !dir.join("b.svg").exists());
+        let rejects = fs::read_to_string(dir.join("rejects.csv")).unwrap();
+        let rows: Vec<_> = rejects.lines().skip(1).collect();
+        assert_eq!(2, rows.len());
+        assert!(rows[0].starts_with(&format!("{},2,a,", input.display())));
+        assert!(rows[1].starts_with(&format!("{},3,b,", input.display())));
+        assert_eq!(2, gen.failures().get(exit::Failure::Record));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_invalid_json_overrides_are_rejected() {
+        let dir = output_dir("ensure_invalid_json_overrides_are_rejected");
+        let input = dir.join("in.ndjson");
+        fs::write(
+            &input,
+            "{\"data\": \"1\", \"format\": \"svg|png\"}\n{\"data\": \"2\", \"format\": \"png\", \"scale\": 2}\n",
+        )
+        .unwrap();
+        let mut gen = generator_into(&dir);
+        gen.files = vec![input.clone()];
+        gen.proc_conf.naming = record::Naming::Row;
+        gen.report_conf.rejects = Some(dir.join("rejects.csv"));
+
+        gen.generate();
+
+        assert!(dir.join("000001.png").is_file());
+        let rejects = fs::read_to_string(dir.join("rejects.csv")).unwrap();
+        let rows: Vec<_> = rejects.lines().skip(1).collect();
+        assert_eq!(1, rows.len());
+        assert!(rows[0].starts_with(&format!("{},1,,", input.display())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_csv_records_use_data_template() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.data = payload::DataSource::Template(
+            template::Template::parse("https://t.example/{sku}?batch={batch}").unwrap(),
+        );
+        let buff = Cursor::new("sku,batch\nA-1,42\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(
+            vec![record::Record::new(
+                2,
+                "A-1".into(),
+                "https://t.example/A-1?batch=42".into()
+            )],
+            records
+        );
+    }
+
+    #[test]
+    fn ensure_csv_records_are_filtered() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.filter = Some(filter::Filter::parse("country == \"DE\"").unwrap());
+        let buff = Cursor::new("name,data,country\na,1,DE\nb,2,FR\nc,3,DE\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(2, records.len());
+        assert_eq!("a", records[0].name);
+        assert_eq!("c", records[1].name);
+    }
+
+    #[test]
+    fn ensure_row_naming_counts_across_files() {
+        let mut gen = default_generator();
+        gen.proc_conf.naming = record::Naming::Row;
+        gen.in_conf.columns.data = columns::Column::Index(0);
+
+        let first: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), Cursor::new("one\ntwo\n"), b',')
+            .unwrap()
+            .map(|r| gen.name_record(r))
+            .collect();
+        let second = gen.name_record(record::Record::new(1, "three".into(), "3".into()));
+
+        assert_eq!("000001", first[0].name);
+        assert_eq!("two", first[1].data);
+        assert_eq!("000002", first[1].name);
+        assert_eq!("000003", second.name);
+    }
+
+    #[test]
+    fn ensure_records_are_described_by_file_and_row() {
+        assert_eq!(
+            "orders.csv row 12 (A-1)",
+            describe(Path::new("orders.csv"), 12, "A-1")
+        );
+        assert_eq!("qrcode", describe(Path::new(""), 0, "qrcode"));
+    }
+
+    #[test]
+    fn ensure_queue_is_bounded() {
+        let mut gen = default_generator();
+        gen.proc_conf.chunk_size = 100;
+        assert_eq!(100, gen.queue_length());
+
+        gen.proc_conf.chunk_size = 1_000_000;
+        assert_eq!(MAX_QUEUED_RECORDS, gen.queue_length());
+
+        gen.proc_conf.limits = Limits::new(1, budget::MemoryBudget::parse("1M").ok(), None, false);
+        assert_eq!(512, gen.queue_length());
+    }
+
+    #[test]
+    fn ensure_rows_can_be_sliced() {
+        let mut gen = default_generator();
+        gen.proc_conf.naming = record::Naming::Row;
+        gen.proc_conf.skip_rows = 1;
+        gen.proc_conf.take = Some(2);
+        let records = (1..=5).map(|i| record::Record::new(i, String::new(), i.to_string()));
+
+        let sliced: Vec<record::Record> = gen.slice_records(records).collect();
+
+        assert_eq!(2, sliced.len());
+        assert_eq!("2", sliced[0].data);
+        assert_eq!("000002", sliced[0].name);
+        assert_eq!("3", sliced[1].data);
+    }
+
+    #[test]
+    fn ensure_data_is_wrapped_in_url() {
+        let mut gen = default_generator();
+        let record = record::Record::new(1, "a".into(), "A 1".into());
+        assert_eq!("A 1", gen.wrap_url(record.clone()).data);
+
+        gen.proc_conf.url_prefix = Some("https://example.com/p/".into());
+        assert_eq!("https://example.com/p/A%201", gen.wrap_url(record).data);
+    }
+
+    #[test]
+    fn ensure_csv_records_reject_unknown_column() {
+        let mut gen = default_generator();
+        gen.in_conf.header_row = input::HeaderRow::Present;
+        gen.in_conf.columns.data = columns::Column::Name("missing".into());
+        let buff = Cursor::new("name,data\na,1\n");
+
+        assert!(gen.csv_records(Path::new("test.csv"), buff, b',').is_err());
+    }
+
+    #[test]
+    fn ensure_overrides_apply() {
+        let gen = default_generator();
+        let overrides = record::Overrides {
+            error_correction: Some(qrcodegen::QrCodeEcc::Low),
+            qr_version_max: Some(qrcodegen::Version::new(10)),
+            mask: Some(qrcodegen::Mask::new(2)),
+            ..Default::default()
+        };
+
+        // Too long for the generator's maximum version of 2, but fits the override.
+        let segments = qrcodegen::QrSegment::make_segments(&"x".repeat(100));
+        let (qr, _) = gen
+            .encode(Path::new("test.csv"), 1, "name", segments, &overrides)
+            .unwrap();
+
+        assert_eq!(2, qr.mask().value());
+        assert_eq!(5, qr.version().value());
+    }
+
+    #[test]
+    fn ensure_min_version_above_max_is_rejected() {
+        let gen = default_generator();
+        let overrides = record::Overrides {
+            qr_version_min: Some(qrcodegen::Version::new(5)),
+            ..Default::default()
+        };
+
+        let segments = qrcodegen::QrSegment::make_segments("x");
+
+        assert!(gen
+            .encode(Path::new("test.csv"), 1, "name", segments, &overrides)
+            .is_none());
+    }
+
+    #[test]
+    fn ensure_csv_uses_delimiter() {
+        let gen = default_generator();
+        let buff = Cursor::new("a;1,2\nb;3");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b';')
+            .unwrap()
+            .collect();
+
+        assert_eq!("1,2", records[0].data);
+        assert_eq!("3", records[1].data);
+    }
+
+    #[test]
+    fn ensure_tsv_defaults_to_tab() {
+        let mut gen = default_generator();
+        assert_eq!(Some(b'\t'), gen.delimiter(Path::new("data.tsv")));
+        assert_eq!(Some(b','), gen.delimiter(Path::new("data.csv")));
+
+        gen.in_conf.delimiter = Some(input::Delimiter::Byte(b'|'));
+        assert_eq!(Some(b'|'), gen.delimiter(Path::new("data.tsv")));
+
+        gen.in_conf.delimiter = Some(input::Delimiter::Auto);
+        assert_eq!(None, gen.delimiter(Path::new("data.csv")));
+    }
+
+    #[test]
+    fn ensure_csv_skips_comment_lines() {
+        let mut gen = default_generator();
+        gen.in_conf.csv.comment = Some(b'#');
+        let buff = Cursor::new("# generated nightly\na,1\n#b,2\nc,#3\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!(2, records.len());
+        assert_eq!("#3", records[1].data);
+    }
+
+    #[test]
+    fn ensure_csv_uses_escape_character() {
+        let mut gen = default_generator();
+        gen.in_conf.csv.escape = Some(b'\\');
+        gen.in_conf.csv.double_quote = false;
+        let buff = Cursor::new("a,\"say \\\"hi\\\", ok\"\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!("say \"hi\", ok", records[0].data);
+    }
+
+    #[test]
+    fn ensure_csv_uses_quote_character() {
+        let mut gen = default_generator();
+        gen.in_conf.csv.quote = b'\'';
+        let buff = Cursor::new("a,'1,2'\n");
+
+        let records: Vec<record::Record> = gen
+            .csv_records(Path::new("test.csv"), buff, b',')
+            .unwrap()
+            .collect();
+
+        assert_eq!("1,2", records[0].data);
     }
 
     #[test]
@@ -267,7 +3659,7 @@ mod tests {
         let gen = default_generator();
         let buff = Cursor::new("  file_name, qr_data ");
 
-        let mut reader = gen.csv_reader(buff);
+        let mut reader = gen.csv_reader(buff, b',');
         let record = reader.records().next().unwrap().unwrap();
 
         assert_eq!("file_name", record[0].to_string());
@@ -0,0 +1,100 @@
+use crate::batch::{Code, Style};
+use crate::exporter::{ExportFormat, Exporter};
+use crate::svg;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Write the QR Codes as one HTML page that needs no other files, each a PNG inlined as a base64 data URI with the
+/// record's name under it, so a batch can be emailed as a single attachment and viewed offline.
+pub fn write(path: &Path, codes: &[Code], style: &Style) -> io::Result<()> {
+    let title = path
+        .file_stem()
+        .map(|stem| svg::escape(&stem.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         \t<meta charset=\"utf-8\">\n\
+         \t<title>{}</title>\n\
+         \t<style>\n\
+         \t\tbody {{ font-family: Helvetica, Arial, sans-serif; margin: 2em; }}\n\
+         \t\tmain {{ display: flex; flex-wrap: wrap; gap: 2em; }}\n\
+         \t\tfigure {{ margin: 0; text-align: center; }}\n\
+         \t\tfigcaption {{ margin-top: 0.5em; overflow-wrap: anywhere; }}\n\
+         \t</style>\n\
+         </head>\n\
+         <body>\n\
+         <main>\n",
+        title
+    );
+
+    for code in codes {
+        let exporter = Exporter::new(
+            code.qr_code.clone(),
+            PathBuf::new(),
+            style.border,
+            ExportFormat::PNG,
+            code.name.clone(),
+            style.scale,
+            style.no_rect,
+            style.foreground,
+            style.background,
+        );
+        let png = exporter
+            .render()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let side = u64::from(style.modules(&code.qr_code) as u32) * u64::from(style.scale);
+        let name = svg::escape(&code.name);
+
+        html += &format!(
+            "\t<figure>\n\
+             \t\t<img src=\"data:image/png;base64,{}\" width=\"{2}\" height=\"{2}\" alt=\"{1}\">\n\
+             \t\t<figcaption>{1}</figcaption>\n\
+             \t</figure>\n",
+            STANDARD.encode(png),
+            name,
+            side
+        );
+    }
+
+    html += "</main>\n</body>\n</html>\n";
+    fs::write(path, html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Batch;
+
+    #[test]
+    fn should_inline_each_code_as_a_data_uri() {
+        let path = std::env::temp_dir().join("qrgen_should_inline_each_code_as_a_data_uri.html");
+        let batch = Batch::default();
+        for (row, name) in ["Alice", "Bob & Carol"].iter().enumerate() {
+            let qr_code = qrcodegen::QrCode::encode_text(name, qrcodegen::QrCodeEcc::Low).unwrap();
+            batch.add(Path::new("guests.csv"), row as u64, name, &qr_code);
+        }
+        let style = Style {
+            scale: 2,
+            ..Default::default()
+        };
+
+        write(&path, &batch.codes(), &style).unwrap();
+
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.contains("<title>qrgen_should_inline_each_code_as_a_data_uri</title>"));
+        assert_eq!(
+            2,
+            html.matches("<img src=\"data:image/png;base64,iVBORw0KGgo")
+                .count()
+        );
+        assert!(html.contains("<figcaption>Bob &amp; Carol</figcaption>"));
+        assert!(html.contains("alt=\"Alice\""));
+        fs::remove_file(&path).unwrap();
+    }
+}
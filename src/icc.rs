@@ -0,0 +1,144 @@
+use std::convert::TryFrom;
+
+// The sRGB primaries adapted to the D50 white of the profile connection space, as in IEC 61966-2-1.
+const RED: [f64; 3] = [0.436_074_7, 0.222_504_5, 0.013_932_2];
+const GREEN: [f64; 3] = [0.385_064_9, 0.716_878_6, 0.097_104_5];
+const BLUE: [f64; 3] = [0.143_080_4, 0.060_616_9, 0.714_173_3];
+const D50: [f64; 3] = [0.964_2, 1.0, 0.824_9];
+// Enough points that the curve is within a level of the sRGB transfer function everywhere.
+const CURVE_POINTS: usize = 1024;
+const HEADER: usize = 128;
+
+/// A version 2 ICC profile for sRGB, which PDF/A needs embedded as the output intent of documents drawn in RGB.  It's
+/// built here rather than shipped as a file, as it's only a handful of numbers and a curve.
+pub fn srgb() -> Vec<u8> {
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", description("sRGB IEC61966-2.1")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"rXYZ", xyz(RED)),
+        (b"gXYZ", xyz(GREEN)),
+        (b"bXYZ", xyz(BLUE)),
+        (b"rTRC", curve()),
+        (b"gTRC", curve()),
+        (b"bTRC", curve()),
+    ];
+
+    let mut table = u32_bytes(tags.len()).to_vec();
+    let mut data = Vec::new();
+    let start = HEADER + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&u32_bytes(start + data.len()));
+        table.extend_from_slice(&u32_bytes(tag.len()));
+        data.extend_from_slice(tag);
+        // Tags start on four byte boundaries.
+        data.resize(data.len().div_ceil(4) * 4, 0);
+    }
+
+    let size = start + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&u32_bytes(size));
+    profile.extend_from_slice(&[0; 4]);
+    profile.extend_from_slice(&[2, 0x10, 0, 0]);
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    // 1 January 2000, the date is only informational.
+    for part in [2000u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&part.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]);
+    // Perceptual rendering intent.
+    profile.extend_from_slice(&[0; 4]);
+    profile.extend_from_slice(&xyz(D50)[8..]);
+    profile.resize(HEADER, 0);
+
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn description(text: &str) -> Vec<u8> {
+    let mut tag = b"desc".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&u32_bytes(text.len() + 1));
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    // No Unicode or Macintosh ScriptCode descriptions.
+    tag.extend_from_slice(&[0; 8 + 2 + 1 + 67]);
+    tag
+}
+
+fn text(text: &str) -> Vec<u8> {
+    let mut tag = b"text".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag
+}
+
+fn xyz(values: [f64; 3]) -> Vec<u8> {
+    let mut tag = b"XYZ ".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    for value in values {
+        tag.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+    }
+    tag
+}
+
+// The sRGB transfer function, linear near black then a 2.4 power curve.
+fn curve() -> Vec<u8> {
+    let mut tag = b"curv".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&u32_bytes(CURVE_POINTS));
+    for i in 0..CURVE_POINTS {
+        let encoded = i as f64 / (CURVE_POINTS - 1) as f64;
+        let linear = if encoded <= 0.040_45 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        tag.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    tag
+}
+
+fn u32_bytes(value: usize) -> [u8; 4] {
+    u32::try_from(value).unwrap_or(u32::MAX).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(profile: &[u8], at: usize) -> usize {
+        u32::from_be_bytes([
+            profile[at],
+            profile[at + 1],
+            profile[at + 2],
+            profile[at + 3],
+        ]) as usize
+    }
+
+    #[test]
+    fn should_build_an_srgb_profile() {
+        let profile = srgb();
+
+        assert_eq!(profile.len(), u32_at(&profile, 0));
+        assert_eq!(b"mntrRGB XYZ ", &profile[12..24]);
+        assert_eq!(b"acsp", &profile[36..40]);
+
+        let tags = u32_at(&profile, HEADER);
+        assert_eq!(9, tags);
+        for tag in 0..tags {
+            let entry = HEADER + 4 + 12 * tag;
+            let (offset, size) = (u32_at(&profile, entry + 4), u32_at(&profile, entry + 8));
+            assert_eq!(0, offset % 4);
+            assert!(offset + size <= profile.len());
+        }
+
+        let curve = curve();
+        assert_eq!([0, 0], curve[12..14]);
+        assert_eq!([0xFF, 0xFF], curve[curve.len() - 2..]);
+    }
+}
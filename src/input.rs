@@ -0,0 +1,670 @@
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+// Delimiters considered when sniffing, in order of preference when they score equally.
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+// The number of bytes read from the start of a file when sniffing the delimiter.
+const SNIFF_LENGTH: usize = 8192;
+
+// The byte order mark some editors, notably Excel, write at the start of UTF-8 files.
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+// Column names commonly used in header rows, lower case with spaces, underscores and dashes removed.
+const HEADER_WORDS: [&str; 20] = [
+    "name",
+    "filename",
+    "file",
+    "id",
+    "key",
+    "label",
+    "title",
+    "data",
+    "qrdata",
+    "qr",
+    "content",
+    "contents",
+    "payload",
+    "url",
+    "link",
+    "text",
+    "value",
+    "code",
+    "sku",
+    "description",
+];
+
+// Fields longer than this are taken to be data rather than a column name.
+const MAX_HEADER_LENGTH: usize = 32;
+
+/// How the field delimiter of a CSV file is chosen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Delimiter {
+    /// Detect the delimiter from the first lines of the file.
+    Auto,
+    Byte(u8),
+}
+
+/// How fields are quoted and commented in CSV input, passed through to the csv crate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    pub quote: u8,
+    pub escape: Option<u8>,
+    pub double_quote: bool,
+    pub comment: Option<u8>,
+}
+
+impl CsvOptions {
+    pub fn new(quote: u8, escape: Option<u8>, double_quote: bool, comment: Option<u8>) -> Self {
+        CsvOptions {
+            quote,
+            escape,
+            double_quote,
+            comment,
+        }
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions::new(b'"', None, true, None)
+    }
+}
+
+/// Whether the first row of a CSV or Excel file is a header row.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum HeaderRow {
+    #[default]
+    Absent,
+    Present,
+    /// Decide from the contents of the first two rows of each file.
+    Auto,
+}
+
+impl HeaderRow {
+    /// Take the header row, if there is one, from the start of the rows, returning it and the remaining rows.
+    pub fn split<I>(
+        self,
+        mut rows: I,
+    ) -> (
+        Option<csv::StringRecord>,
+        impl Iterator<Item = csv::StringRecord>,
+    )
+    where
+        I: Iterator<Item = csv::StringRecord>,
+    {
+        let first = match self {
+            HeaderRow::Absent => None,
+            _ => rows.next(),
+        };
+        let mut rows = rows.peekable();
+
+        let is_header = match (self, &first) {
+            (HeaderRow::Auto, Some(first)) => looks_like_header(first, rows.peek()),
+            _ => true,
+        };
+
+        if is_header {
+            (first, None.into_iter().chain(rows))
+        } else {
+            info!("the first row does not look like a header row");
+            (None, first.into_iter().chain(rows))
+        }
+    }
+}
+
+/// The layout of an input file.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum InputFormat {
+    /// Choose the format from the file extension, falling back to CSV.
+    #[default]
+    Auto,
+    Csv,
+    Json,
+    Ndjson,
+    Excel,
+}
+
+impl InputFormat {
+    /// Resolve the automatic format using the extension of the given path.
+    pub fn for_path(self, path: &Path) -> InputFormat {
+        if self != InputFormat::Auto {
+            return self;
+        }
+
+        match extension(path).unwrap_or_default().as_ref() {
+            "json" => InputFormat::Json,
+            "ndjson" | "jsonl" => InputFormat::Ndjson,
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => InputFormat::Excel,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// Expand any glob patterns, e.g. data/*.csv, among the input paths, for shells that don't expand them.  Paths that
+/// exist, standard input and URLs are kept as they are, as is a pattern that matches nothing so that it is reported
+/// as missing.
+pub fn expand_globs(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut expanded = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        if is_stdin(&path) || is_url(&path) || path.exists() || !pattern.contains(['*', '?', '[']) {
+            expanded.push(path);
+            continue;
+        }
+
+        let matches: Vec<PathBuf> = match glob::glob(&pattern) {
+            Ok(paths) => paths
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .collect(),
+            Err(e) => {
+                warn!("invalid pattern {} {:?}", pattern, e);
+                Vec::new()
+            }
+        };
+
+        if matches.is_empty() {
+            expanded.push(path);
+        } else {
+            info!("{} matched {} files", pattern, matches.len());
+            expanded.extend(matches);
+        }
+    }
+
+    expanded
+}
+
+/// The deepest directory holding every local input file, as an absolute path, that the input directories are mirrored
+/// from.  None when there are no local files.
+pub fn common_dir(paths: &[PathBuf]) -> Option<PathBuf> {
+    paths
+        .iter()
+        .filter(|path| !is_stdin(path) && !is_url(path))
+        .filter_map(|path| Some(std::path::absolute(path).ok()?.parent()?.to_path_buf()))
+        .reduce(|common, dir| {
+            common
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        })
+}
+
+/// The path without any query string or fragment when it is a URL.
+fn strip_query(path: &Path) -> PathBuf {
+    if !is_url(path) {
+        return path.to_path_buf();
+    }
+
+    let url = path.to_string_lossy();
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    PathBuf::from(&url[..end])
+}
+
+/// The lower case extension of a path, looking through a .gz extension to the one before it.
+fn extension(path: &Path) -> Option<String> {
+    let path = strip_query(path);
+    let lower = |e: &OsStr| e.to_string_lossy().to_lowercase();
+
+    match path.extension().map(lower) {
+        Some(ref e) if e == "gz" => path.file_stem().map(Path::new)?.extension().map(lower),
+        extension => extension,
+    }
+}
+
+/// Returns true if the path has a gzip extension.
+pub fn is_gzip(path: &Path) -> bool {
+    strip_query(path)
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("gz"))
+        .unwrap_or(false)
+}
+
+/// Returns true if the path has a tab separated values extension.
+pub fn is_tsv(path: &Path) -> bool {
+    matches!(extension(path).as_deref(), Some("tsv") | Some("tab"))
+}
+
+/// Returns true if the path refers to standard input rather than a file.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Returns true if the path is an HTTP or HTTPS URL rather than a file.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .map(|p| {
+            let p = p.to_ascii_lowercase();
+            p.starts_with("https://") || p.starts_with("http://")
+        })
+        .unwrap_or(false)
+}
+
+/// Open an input for reading, treating - as standard input and fetching HTTP(S) URLs with the given request
+/// headers.  Inputs with a .gz extension are decompressed as they are read.
+pub fn open(path: &Path, headers: &[(String, String)]) -> io::Result<Box<dyn Read>> {
+    let reader: Box<dyn Read> = if is_stdin(path) {
+        Box::new(io::stdin())
+    } else if is_url(path) {
+        fetch(&path.to_string_lossy(), headers)?
+    } else {
+        Box::new(File::open(path)?)
+    };
+
+    if is_gzip(path) {
+        return Ok(Box::new(MultiGzDecoder::new(reader)));
+    }
+
+    Ok(reader)
+}
+
+/// Skip a UTF-8 byte order mark at the start of the reader, returning a reader that yields everything else.
+pub fn strip_bom(mut reader: Box<dyn Read>) -> io::Result<Box<dyn Read>> {
+    let mut start = Vec::with_capacity(UTF8_BOM.len());
+    reader
+        .by_ref()
+        .take(UTF8_BOM.len() as u64)
+        .read_to_end(&mut start)?;
+
+    if start == UTF8_BOM {
+        info!("skipped UTF-8 byte order mark");
+        return Ok(reader);
+    }
+
+    Ok(Box::new(Cursor::new(start).chain(reader)))
+}
+
+/// Transcode a reader from the given character encoding to UTF-8 as it is read.  A byte order mark at the start
+/// is removed and takes precedence over the given encoding.
+pub fn decode(reader: Box<dyn Read>, encoding: &'static Encoding) -> Box<dyn Read> {
+    Box::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding))
+            .bom_override(true)
+            .build(reader),
+    )
+}
+
+/// Start a GET request for the URL, returning a reader that streams the response body.
+fn fetch(url: &str, headers: &[(String, String)]) -> io::Result<Box<dyn Read>> {
+    info!("fetching {}", url);
+    let request = headers
+        .iter()
+        .fold(ureq::get(url), |request, (name, value)| {
+            request.set(name, value)
+        });
+
+    let response = request.call().map_err(|e| match e {
+        ureq::Error::Status(code, response) => io::Error::other(format!(
+            "{} returned {} {}",
+            url,
+            code,
+            response.status_text()
+        )),
+        ureq::Error::Transport(transport) => io::Error::other(transport),
+    })?;
+
+    Ok(Box::new(response.into_reader()))
+}
+
+/// Detect the delimiter from the start of the reader, returning a reader that still yields the whole input.
+pub fn sniff_delimiter(mut reader: Box<dyn Read>) -> io::Result<(Box<dyn Read>, u8)> {
+    let mut sample = Vec::with_capacity(SNIFF_LENGTH);
+    reader
+        .by_ref()
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut sample)?;
+
+    let delimiter = detect_delimiter(&sample, sample.len() < SNIFF_LENGTH);
+    info!("detected delimiter {:?}", char::from(delimiter));
+
+    Ok((Box::new(Cursor::new(sample).chain(reader)), delimiter))
+}
+
+/// Pick the candidate delimiter that appears the same, non-zero, number of times on the most lines, preferring
+/// the one with more fields when tied.  Falls back to a comma.
+fn detect_delimiter(sample: &[u8], complete: bool) -> u8 {
+    let mut lines: Vec<&[u8]> = sample
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.iter().all(u8::is_ascii_whitespace))
+        .take(10)
+        .collect();
+
+    // The last line may have been cut off by the sample length.
+    if !complete && lines.len() > 1 {
+        lines.pop();
+    }
+
+    let mut best = (0, 0, b',');
+
+    for &candidate in &CANDIDATE_DELIMITERS {
+        let counts: Vec<usize> = lines
+            .iter()
+            .map(|line| count_unquoted(line, candidate))
+            .collect();
+
+        let first = match counts.first() {
+            Some(&first) if first > 0 => first,
+            _ => continue,
+        };
+
+        let consistent = counts.iter().filter(|&&c| c == first).count();
+        if (consistent, first) > (best.0, best.1) {
+            best = (consistent, first, candidate);
+        }
+    }
+
+    best.2
+}
+
+/// Guess if a row is a header row, every field must look like a column name, and either at least two of them are
+/// commonly used column names or a column holds a number in the next row but not in this one.
+fn looks_like_header(row: &csv::StringRecord, next: Option<&csv::StringRecord>) -> bool {
+    if row.is_empty() || !row.iter().all(is_column_name) {
+        return false;
+    }
+
+    let known = row
+        .iter()
+        .map(|field| {
+            field
+                .chars()
+                .filter(|c| !matches!(c, ' ' | '_' | '-'))
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|field| HEADER_WORDS.contains(&field.as_str()))
+        .count();
+
+    let numbers_below = next.is_some_and(|next| {
+        row.iter()
+            .zip(next.iter())
+            .any(|(field, value)| field.parse::<f64>().is_err() && value.parse::<f64>().is_ok())
+    });
+
+    known >= 2 || numbers_below
+}
+
+fn is_column_name(field: &str) -> bool {
+    !field.is_empty()
+        && field.len() <= MAX_HEADER_LENGTH
+        && field.chars().any(char::is_alphabetic)
+        && field
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-'))
+}
+
+fn count_unquoted(line: &[u8], delimiter: u8) -> usize {
+    let mut quoted = false;
+    let mut count = 0;
+
+    for &b in line {
+        if b == b'"' {
+            quoted = !quoted;
+        } else if b == delimiter && !quoted {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_treat_dash_as_stdin() {
+        assert!(is_stdin(Path::new("-")));
+        assert!(!is_stdin(Path::new("-.csv")));
+        assert!(!is_stdin(Path::new("data.csv")));
+    }
+
+    #[test]
+    fn should_detect_format_from_extension() {
+        assert_eq!(
+            InputFormat::Json,
+            InputFormat::Auto.for_path(Path::new("a.JSON"))
+        );
+        assert_eq!(
+            InputFormat::Ndjson,
+            InputFormat::Auto.for_path(Path::new("a.jsonl"))
+        );
+        assert_eq!(
+            InputFormat::Excel,
+            InputFormat::Auto.for_path(Path::new("a.xlsx"))
+        );
+        assert_eq!(InputFormat::Csv, InputFormat::Auto.for_path(Path::new("-")));
+        assert_eq!(
+            InputFormat::Json,
+            InputFormat::Auto.for_path(Path::new("https://example.com/records.json?key=1"))
+        );
+        assert_eq!(
+            InputFormat::Ndjson,
+            InputFormat::Ndjson.for_path(Path::new("a.csv"))
+        );
+    }
+
+    #[test]
+    fn should_detect_delimiters() {
+        assert_eq!(b',', detect_delimiter(b"a,1\nb,2\n", true));
+        assert_eq!(b';', detect_delimiter(b"a;1,5\nb;2,0\nc;3\n", true));
+        assert_eq!(b'\t', detect_delimiter(b"a\t1\nb\t2", true));
+        assert_eq!(b'|', detect_delimiter(b"a|x;y\nb|z\n", true));
+    }
+
+    #[test]
+    fn should_ignore_quoted_delimiters() {
+        assert_eq!(b';', detect_delimiter(b"a;\"x,y,z\"\nb;\"1,2,3\"\n", true));
+    }
+
+    #[test]
+    fn should_detect_header_rows() {
+        let row = |fields: &[&str]| csv::StringRecord::from(fields.to_vec());
+
+        assert!(looks_like_header(
+            &row(&["filename", "data"]),
+            Some(&row(&["a", "https://example.com"]))
+        ));
+        assert!(looks_like_header(&row(&["File Name", "QR_Data"]), None));
+        assert!(looks_like_header(
+            &row(&["product", "batch"]),
+            Some(&row(&["a", "42"]))
+        ));
+        assert!(!looks_like_header(
+            &row(&["product", "batch"]),
+            Some(&row(&["a", "b"]))
+        ));
+        assert!(!looks_like_header(
+            &row(&["label", "https://example.com"]),
+            Some(&row(&["a", "b"]))
+        ));
+        assert!(!looks_like_header(
+            &row(&["a1", "42"]),
+            Some(&row(&["a2", "43"]))
+        ));
+    }
+
+    #[test]
+    fn should_split_header_row() {
+        let rows = || {
+            vec![
+                csv::StringRecord::from(vec!["name", "data"]),
+                csv::StringRecord::from(vec!["a", "1"]),
+            ]
+            .into_iter()
+        };
+
+        let (headers, rest) = HeaderRow::Auto.split(rows());
+        assert_eq!(Some(csv::StringRecord::from(vec!["name", "data"])), headers);
+        assert_eq!(1, rest.count());
+
+        let (headers, rest) = HeaderRow::Absent.split(rows());
+        assert_eq!(None, headers);
+        assert_eq!(2, rest.count());
+    }
+
+    #[test]
+    fn should_default_to_comma() {
+        assert_eq!(b',', detect_delimiter(b"", true));
+        assert_eq!(b',', detect_delimiter(b"abc\ndef\n", true));
+    }
+
+    #[test]
+    fn should_keep_sniffed_bytes() {
+        let reader: Box<dyn Read> = Box::new(Cursor::new(b"a;1\nb;2\n".to_vec()));
+        let (mut reader, delimiter) = sniff_delimiter(reader).unwrap();
+
+        let mut all = String::new();
+        reader.read_to_string(&mut all).unwrap();
+
+        assert_eq!(b';', delimiter);
+        assert_eq!("a;1\nb;2\n", all);
+    }
+
+    #[test]
+    fn should_expand_globs() {
+        let dir = std::env::temp_dir().join("qrgen_should_expand_globs");
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["b.csv", "a.csv", "c.txt"] {
+            fs::write(dir.join(name), "a,1\n").unwrap();
+        }
+
+        let expanded = expand_globs(vec![
+            dir.join("*.csv"),
+            dir.join("none*.csv"),
+            PathBuf::from("-"),
+        ]);
+
+        assert_eq!(
+            vec![
+                dir.join("a.csv"),
+                dir.join("b.csv"),
+                dir.join("none*.csv"),
+                PathBuf::from("-")
+            ],
+            expanded
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_find_common_dir() {
+        let root = std::env::temp_dir().join("qrgen_should_find_common_dir");
+
+        assert_eq!(
+            Some(root.clone()),
+            common_dir(&[
+                root.join("a").join("x.csv"),
+                PathBuf::from("-"),
+                root.join("b").join("c").join("y.csv"),
+            ])
+        );
+        assert_eq!(
+            Some(root.join("a")),
+            common_dir(&[root.join("a").join("x.csv")])
+        );
+        assert_eq!(
+            None,
+            common_dir(&[PathBuf::from("https://example.com/a.csv")])
+        );
+    }
+
+    #[test]
+    fn should_error_for_missing_file() {
+        assert!(open(Path::new("does/not/exist.csv"), &[]).is_err());
+    }
+
+    #[test]
+    fn should_recognise_urls() {
+        assert!(is_url(Path::new("https://example.com/export?format=csv")));
+        assert!(is_url(Path::new("HTTP://example.com/a.csv")));
+        assert!(!is_url(Path::new("https.csv")));
+        assert!(!is_url(Path::new("data/a.csv")));
+    }
+
+    #[test]
+    fn should_use_url_path_extension() {
+        assert!(is_tsv(Path::new("https://example.com/a.tsv#top")));
+        assert!(!is_tsv(Path::new("https://example.com/a.csv?sep=.tsv")));
+    }
+
+    #[test]
+    fn should_look_through_gzip_extension() {
+        assert!(is_gzip(Path::new("nightly.csv.GZ")));
+        assert!(!is_gzip(Path::new("nightly.csv")));
+        assert!(is_tsv(Path::new("nightly.tsv.gz")));
+        assert_eq!(
+            InputFormat::Ndjson,
+            InputFormat::Auto.for_path(Path::new("https://example.com/a.ndjson.gz?x=1"))
+        );
+        assert_eq!(
+            InputFormat::Csv,
+            InputFormat::Auto.for_path(Path::new("nightly.gz"))
+        );
+    }
+
+    #[test]
+    fn should_strip_bom() {
+        for (input, expected) in &[
+            (&b"\xef\xbb\xbfa,1\n"[..], "a,1\n"),
+            (&b"a,1\n"[..], "a,1\n"),
+            (&b"a"[..], "a"),
+            (&b""[..], ""),
+        ] {
+            let reader: Box<dyn Read> = Box::new(Cursor::new(input.to_vec()));
+            let mut all = String::new();
+            strip_bom(reader).unwrap().read_to_string(&mut all).unwrap();
+
+            assert_eq!(*expected, all);
+        }
+    }
+
+    #[test]
+    fn should_transcode_to_utf8() {
+        let reader: Box<dyn Read> = Box::new(Cursor::new(b"caf\xe9,1\n".to_vec()));
+        let mut all = String::new();
+        decode(reader, encoding_rs::WINDOWS_1252)
+            .read_to_string(&mut all)
+            .unwrap();
+
+        assert_eq!("café,1\n", all);
+
+        let reader: Box<dyn Read> = Box::new(Cursor::new(b"\xef\xbb\xbfcaf\xc3\xa9,1\n".to_vec()));
+        let mut all = String::new();
+        decode(reader, encoding_rs::WINDOWS_1252)
+            .read_to_string(&mut all)
+            .unwrap();
+
+        assert_eq!("café,1\n", all);
+    }
+
+    #[test]
+    fn should_decompress_gzip_file() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join("qrgen_should_decompress_gzip_file.csv.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(b"a,1\nb,2\n").unwrap();
+        encoder.finish().unwrap();
+
+        let mut all = String::new();
+        open(&path, &[]).unwrap().read_to_string(&mut all).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("a,1\nb,2\n", all);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::record::{OverrideValues, Overrides, Record};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read};
+
+/// A record read from JSON and the overrides it gives, kept apart so a record with an override that can't be read
+/// can still be reported by name.
+pub type Entry = (Record, Result<Overrides, String>);
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    /// Left out when the records are named by row or hash.
+    #[serde(default)]
+    name: Option<Value>,
+    data: Value,
+    #[serde(default)]
+    ecc: Option<Value>,
+    #[serde(default)]
+    min_version: Option<Value>,
+    #[serde(default)]
+    max_version: Option<Value>,
+    #[serde(default)]
+    mask: Option<Value>,
+    #[serde(default)]
+    format: Option<Value>,
+    #[serde(default)]
+    scale: Option<Value>,
+    #[serde(default)]
+    border: Option<Value>,
+}
+
+impl JsonRecord {
+    fn into_entry(self, row: u64) -> Result<Entry, String> {
+        let overrides = self.overrides();
+        let name = match &self.name {
+            Some(name) => scalar_to_string("name", name)?,
+            None => String::new(),
+        };
+        let record = Record::new(row, name, scalar_to_string("data", &self.data)?);
+
+        Ok((record, overrides))
+    }
+
+    fn overrides(&self) -> Result<Overrides, String> {
+        let value = |field: &str, value: &Option<Value>| {
+            value
+                .as_ref()
+                .map(|value| scalar_to_string(field, value).map(Into::into))
+                .transpose()
+        };
+
+        OverrideValues {
+            ecc: value("ecc", &self.ecc)?,
+            min: value("min_version", &self.min_version)?,
+            max: value("max_version", &self.max_version)?,
+            mask: value("mask", &self.mask)?,
+            format: value("format", &self.format)?,
+            scale: value("scale", &self.scale)?,
+            border: value("border", &self.border)?,
+        }
+        .parse()
+    }
+}
+
+/// Allow numbers and booleans as well as strings, so ids don't need quoting in the source JSON.
+fn scalar_to_string(field: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("{} must be a string, number or boolean", field)),
+    }
+}
+
+fn parse(row: u64, record: Result<JsonRecord, serde_json::Error>) -> Result<Entry, String> {
+    record
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.into_entry(row))
+        .map_err(|e| format!("skipping JSON record {} {}", row, e))
+}
+
+/// Read a JSON array of objects, each having a name and data field, giving an error for each object that isn't a
+/// valid record.
+pub fn read_array<R: Read>(
+    reader: R,
+) -> Result<impl Iterator<Item = Result<Entry, String>>, serde_json::Error> {
+    let values: Vec<Value> = serde_json::from_reader(reader)?;
+
+    Ok(values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| parse(i as u64 + 1, serde_json::from_value(value))))
+}
+
+/// Read newline delimited JSON, one object per line, giving an error for each line that isn't a valid record.
+/// Blank lines are ignored.
+pub fn read_lines<R: Read>(reader: R) -> impl Iterator<Item = Result<Entry, String>> {
+    BufReader::new(reader)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let row = i as u64 + 1;
+            match line {
+                Ok(line) if line.trim().is_empty() => None,
+                Ok(line) => Some(parse(row, serde_json::from_str(&line))),
+                Err(e) => Some(Err(format!("error reading line {} {:?}", row, e))),
+            }
+        })
+}
+
+/// Read a single JSON object, such as a message taken from a queue.
+pub fn read_object(row: u64, text: &str) -> Option<Entry> {
+    match parse(row, serde_json::from_str(text)) {
+        Ok(record) => Some(record),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exporter::ExportFormat;
+
+    #[test]
+    fn should_read_array_with_overrides() {
+        let input = r#"[
+            {"name": "a", "data": "one"},
+            {"name": "b", "data": 9998819191919191, "ecc": "low", "min_version": 2, "max_version": "5", "mask": 3,
+             "format": "png, svg", "scale": 4, "border": 0}
+        ]"#;
+
+        let records: Vec<Record> = read_array(input.as_bytes())
+            .unwrap()
+            .map(|entry| {
+                let (mut record, overrides) = entry.unwrap();
+                record.overrides = overrides.unwrap();
+                record
+            })
+            .collect();
+
+        assert_eq!(2, records.len());
+        assert_eq!(Record::new(1, "a".into(), "one".into()), records[0]);
+        assert_eq!("9998819191919191", records[1].data);
+        assert_eq!(
+            Some(qrcodegen::QrCodeEcc::Low),
+            records[1].overrides.error_correction
+        );
+        assert_eq!(
+            Some(2),
+            records[1].overrides.qr_version_min.map(|v| v.value())
+        );
+        assert_eq!(
+            Some(5),
+            records[1].overrides.qr_version_max.map(|v| v.value())
+        );
+        assert_eq!(Some(3), records[1].overrides.mask.map(|m| m.value()));
+        assert_eq!(
+            Some(vec![ExportFormat::PNG, ExportFormat::SVG]),
+            records[1]
+                .overrides
+                .formats
+                .as_ref()
+                .map(|formats| formats.iter().collect())
+        );
+        assert_eq!(Some(4), records[1].overrides.scale);
+        assert_eq!(Some(0), records[1].overrides.border);
+    }
+
+    #[test]
+    fn should_read_lines_and_skip_invalid() {
+        let input = "{\"name\": \"a\", \"data\": \"one\"}\n\n{\"name\": \"b\"}\n{\"name\": \"c\", \"data\": \"3\", \"mask\": 9}\n{\"name\": \"d\", \"data\": \"four\"}\n";
+
+        let (entries, errors): (Vec<_>, Vec<_>) =
+            read_lines(input.as_bytes()).partition(Result::is_ok);
+        let entries: Vec<Entry> = entries.into_iter().filter_map(Result::ok).collect();
+
+        assert_eq!(1, errors.len());
+        assert_eq!(3, entries.len());
+        assert_eq!(1, entries[0].0.row);
+        // The record with an invalid override is still read, so it can be reported by name.
+        assert_eq!(("c", 4), (entries[1].0.name.as_str(), entries[1].0.row));
+        assert!(entries[1].1.is_err());
+        assert_eq!("d", entries[2].0.name);
+        assert_eq!(5, entries[2].0.row);
+    }
+
+    #[test]
+    fn should_read_records_without_a_name() {
+        let (record, overrides) = read_object(1, "{\"data\": \"https://t.example/1\"}").unwrap();
+
+        assert_eq!("", record.name);
+        assert_eq!(Ok(Overrides::default()), overrides);
+        assert!(read_object(2, "{\"name\": [1], \"data\": \"x\"}").is_none());
+    }
+
+    #[test]
+    fn should_read_object() {
+        let (record, _) = read_object(
+            7,
+            "{\"name\": \"order-1\", \"data\": \"https://t.example/1\"}",
+        )
+        .unwrap();
+
+        assert_eq!(7, record.row);
+        assert_eq!("order-1", record.name);
+        assert!(read_object(8, "https://t.example/1").is_none());
+    }
+
+    #[test]
+    fn should_error_for_invalid_array() {
+        assert!(read_array("{\"name\": \"a\"}".as_bytes()).is_err());
+    }
+}
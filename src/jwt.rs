@@ -0,0 +1,134 @@
+use crate::{columns::Column, sign::Signer};
+use serde_json::{Map, Value};
+use std::fmt;
+
+// Registered claims that hold a NumericDate, seconds since the epoch, rather than a string.
+const NUMERIC_CLAIMS: [&str; 3] = ["exp", "nbf", "iat"];
+
+/// The claims of a JWT and the columns they are taken from, e.g. sub=id,role,exp=expires.  A column given on its own
+/// is used as the claim name, columns are given by number starting at 1 or by header name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Claims {
+    source: String,
+    claims: Vec<(String, Column)>,
+}
+
+impl Claims {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let claims = source
+            .split(',')
+            .map(|claim| {
+                let (name, column) = claim.split_once('=').unwrap_or((claim, claim));
+                let name = name.trim();
+                if name.is_empty() || name.parse::<usize>().is_ok() {
+                    return Err(format!(
+                        "The claim {} needs a name, e.g. sub={}.",
+                        claim, column
+                    ));
+                }
+
+                Ok((name.to_string(), crate::parse_column(column)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Claims {
+            source: source.to_string(),
+            claims,
+        })
+    }
+
+    /// Find the position of every claim column, looking names up in the header row of the file.
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<ResolvedClaims, String> {
+        let claims = self
+            .claims
+            .iter()
+            .map(|(name, column)| Ok((name.clone(), column.resolve(headers)?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(ResolvedClaims { claims })
+    }
+}
+
+impl fmt::Display for Claims {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Claims whose columns have been found in a particular file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedClaims {
+    claims: Vec<(String, usize)>,
+}
+
+impl ResolvedClaims {
+    /// Sign the claims of the row into an HS256 token, leaving out claims whose column is empty or missing.
+    pub fn render(&self, row: &csv::StringRecord, signer: &Signer) -> Result<String, String> {
+        let mut claims = Map::new();
+
+        for (name, index) in &self.claims {
+            let value = match row.get(*index).filter(|v| !v.is_empty()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let value = if NUMERIC_CLAIMS.contains(&name.as_str()) {
+                value.parse::<i64>().map(Value::from).map_err(|_| {
+                    format!("the {} claim {} is not a number of seconds", name, value)
+                })?
+            } else {
+                Value::from(value)
+            };
+            claims.insert(name.clone(), value);
+        }
+
+        Ok(signer.token(&Value::Object(claims).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_claims() {
+        let claims = Claims::parse("sub=id,role,exp=3").unwrap();
+
+        assert_eq!(
+            vec![
+                ("sub".to_string(), Column::Name("id".into())),
+                ("role".to_string(), Column::Name("role".into())),
+                ("exp".to_string(), Column::Index(2)),
+            ],
+            claims.claims
+        );
+        assert!(Claims::parse("sub,2").is_err());
+        assert!(Claims::parse("sub=").is_err());
+    }
+
+    #[test]
+    fn should_sign_row_claims() {
+        let headers = csv::StringRecord::from(vec!["id", "role", "expires"]);
+        let claims = Claims::parse("sub=id,role,exp=expires")
+            .unwrap()
+            .resolve(Some(&headers))
+            .unwrap();
+        let signer = Signer::new(b"secret".to_vec(), ".".into());
+
+        let token = claims
+            .render(
+                &csv::StringRecord::from(vec!["u1", "", "1700000000"]),
+                &signer,
+            )
+            .unwrap();
+
+        assert_eq!(
+            "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJleHAiOjE3MDAwMDAwMDAsInN1YiI6InUxIn0.\
+             3_Y76Oy9sAc2IwsJdbHp6Me5D1qy785yj84a4gvpwNs",
+            token
+        );
+        assert!(claims
+            .render(&csv::StringRecord::from(vec!["u1", "", "soon"]), &signer)
+            .is_err());
+    }
+}
@@ -0,0 +1,450 @@
+use crate::batch::{Code, Style};
+use crate::pdf::{self, Paper, POINTS_PER_MM};
+use crate::sheet;
+use crate::svg;
+use pdf_writer::Content;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Room left clear inside the edge of each label, as printers don't place labels exactly.
+const PADDING: f32 = 2.0 * POINTS_PER_MM;
+const CAPTION_SIZE: f32 = 10.0;
+// Labels this much wider than they are tall have the caption beside the QR Code rather than under it.
+const WIDE: f32 = 1.5;
+// Labels are counted as fitting the page when they overrun it by less than this, to allow for rounding.
+const TOLERANCE: f32 = 0.01 * POINTS_PER_MM;
+
+// An Avery sheet by its product code, with the page, label size, left and top margins and pitch in millimetres.
+struct Preset {
+    name: &'static str,
+    page: &'static str,
+    label: (f32, f32),
+    margins: (f32, f32),
+    pitch: (f32, f32),
+}
+
+const PRESETS: [Preset; 6] = [
+    Preset {
+        name: "L7160",
+        page: "a4",
+        label: (63.5, 38.1),
+        margins: (7.2, 15.15),
+        pitch: (66.04, 38.1),
+    },
+    Preset {
+        name: "L7163",
+        page: "a4",
+        label: (99.1, 38.1),
+        margins: (4.65, 15.15),
+        pitch: (101.6, 38.1),
+    },
+    Preset {
+        name: "L7165",
+        page: "a4",
+        label: (99.1, 67.7),
+        margins: (4.65, 13.1),
+        pitch: (101.6, 67.7),
+    },
+    Preset {
+        name: "L7651",
+        page: "a4",
+        label: (38.1, 21.2),
+        margins: (4.75, 10.7),
+        pitch: (40.64, 21.2),
+    },
+    Preset {
+        name: "5160",
+        page: "letter",
+        label: (66.675, 25.4),
+        margins: (4.7625, 12.7),
+        pitch: (69.85, 25.4),
+    },
+    Preset {
+        name: "5163",
+        page: "letter",
+        label: (101.6, 50.8),
+        margins: (3.96875, 12.7),
+        pitch: (106.3625, 50.8),
+    },
+];
+
+/// The layout of a sheet of labels, in points: the page, the size of each label, the margins to the top left label
+/// and the pitch from one label to the next, across and down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Template {
+    pub page: Paper,
+    pub label: (f32, f32),
+    pub margins: (f32, f32),
+    pub pitch: (f32, f32),
+    columns: usize,
+    rows: usize,
+}
+
+impl Template {
+    /// A template for labels of the size laid out from the margins at the pitch, with as many columns and rows as fit
+    /// the page.
+    pub fn new(
+        page: Paper,
+        label: (f32, f32),
+        margins: (f32, f32),
+        pitch: (f32, f32),
+    ) -> Result<Self, String> {
+        if label.0 <= 0.0 || label.1 <= 0.0 {
+            return Err("Labels must have a width and height greater than 0.".into());
+        }
+        if pitch.0 < label.0 || pitch.1 < label.1 {
+            return Err(
+                "The label pitch must be at least the size of a label, or they would overlap."
+                    .into(),
+            );
+        }
+
+        let fit = |page: f32, margin: f32, label: f32, pitch: f32| {
+            let room = page - margin - label + TOLERANCE;
+            if room < 0.0 {
+                0
+            } else {
+                (room / pitch) as usize + 1
+            }
+        };
+        let columns = fit(page.width, margins.0, label.0, pitch.0);
+        let rows = fit(page.height, margins.1, label.1, pitch.1);
+        if columns == 0 || rows == 0 {
+            return Err("No labels of that size fit on the page inside the margins.".into());
+        }
+
+        Ok(Template {
+            page,
+            label,
+            margins,
+            pitch,
+            columns,
+            rows,
+        })
+    }
+
+    /// The template of an Avery sheet by its product code, e.g. L7160 or 5160.
+    pub fn preset(name: &str) -> Result<Self, String> {
+        let points = |(width, height): (f32, f32)| (width * POINTS_PER_MM, height * POINTS_PER_MM);
+
+        PRESETS
+            .iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "Label template must be one of {}.",
+                    PRESETS
+                        .iter()
+                        .map(|preset| preset.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+            .and_then(|preset| {
+                Template::new(
+                    Paper::parse(preset.page)?,
+                    points(preset.label),
+                    points(preset.margins),
+                    points(preset.pitch),
+                )
+            })
+    }
+
+    pub fn per_sheet(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    // The top left corner of the label at the position on the sheet, counting across then down.
+    fn position(&self, i: usize) -> (f32, f32) {
+        (
+            self.margins.0 + (i % self.columns) as f32 * self.pitch.0,
+            self.margins.1 + (i / self.columns) as f32 * self.pitch.1,
+        )
+    }
+
+    // Where the QR Code and caption go on a label, from its top left corner.
+    fn place(&self, captions: bool) -> Placement {
+        let (width, height) = (self.label.0 - 2.0 * PADDING, self.label.1 - 2.0 * PADDING);
+
+        if !captions {
+            let side = width.min(height);
+            return Placement {
+                code: (
+                    PADDING + (width - side) / 2.0,
+                    PADDING + (height - side) / 2.0,
+                    side,
+                ),
+                caption: None,
+            };
+        }
+
+        if self.label.0 >= WIDE * self.label.1 {
+            let side = height;
+            let size = CAPTION_SIZE.min(height / 3.0);
+            let left = 2.0 * PADDING + side;
+            Placement {
+                code: (PADDING, PADDING, side),
+                caption: Some(Caption {
+                    x: (left + self.label.0 - PADDING) / 2.0,
+                    y: self.label.1 / 2.0 + size * 0.35,
+                    size,
+                    width: self.label.0 - PADDING - left,
+                }),
+            }
+        } else {
+            let size = CAPTION_SIZE.min(height / 5.0);
+            let side = width.min(height - size * 1.5);
+            Placement {
+                code: (PADDING + (width - side) / 2.0, PADDING, side),
+                caption: Some(Caption {
+                    x: self.label.0 / 2.0,
+                    y: PADDING + side + size * 1.2,
+                    size,
+                    width,
+                }),
+            }
+        }
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::preset("L7160").unwrap()
+    }
+}
+
+struct Placement {
+    code: (f32, f32, f32),
+    caption: Option<Caption>,
+}
+
+struct Caption {
+    x: f32,
+    y: f32,
+    size: f32,
+    width: f32,
+}
+
+/// Write the QR Codes onto sheets of labels in consecutive positions, as one PDF with a page for each sheet when the
+/// path ends in .pdf and otherwise as SVG, numbering the files, e.g. labels-001.svg, when there is more than one
+/// sheet.  Returns the paths of the files written.
+pub fn write(
+    path: &Path,
+    codes: &[Code],
+    template: &Template,
+    document: &pdf::Document,
+    style: &Style,
+    captions: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let placement = template.place(captions);
+    let sheets = codes.chunks(template.per_sheet());
+
+    if is_pdf(path) {
+        let pages = sheets
+            .map(|codes| pdf_sheet(codes, template, &placement, document.font.as_ref(), style));
+        pdf::write_pages(path, template.page, document, captions, pages)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let count = sheets.len();
+    let mut paths = Vec::with_capacity(count);
+    for (number, codes) in sheets.enumerate() {
+        let path = if count > 1 {
+            sheet::numbered(path, number + 1)
+        } else {
+            path.to_path_buf()
+        };
+        fs::write(&path, svg_sheet(codes, template, &placement, style))?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Whether labels written to the path are a PDF rather than SVG.
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+// PDF pages are drawn from the bottom left corner, so positions from the top are flipped.
+fn pdf_sheet(
+    codes: &[Code],
+    template: &Template,
+    placement: &Placement,
+    font: Option<&pdf::Font>,
+    style: &Style,
+) -> Content {
+    let mut content = Content::new();
+    let top = template.page.height;
+
+    for (i, code) in codes.iter().enumerate() {
+        let (left, y) = template.position(i);
+        let (x, code_y, side) = placement.code;
+        pdf::draw(
+            &mut content,
+            &code.qr_code,
+            style,
+            left + x,
+            top - y - code_y - side,
+            side,
+        );
+        if let Some(caption) = &placement.caption {
+            pdf::caption(
+                &mut content,
+                &code.name,
+                style,
+                font,
+                left + caption.x,
+                top - y - caption.y,
+                caption.size,
+                caption.width,
+            );
+        }
+    }
+
+    content
+}
+
+fn svg_sheet(codes: &[Code], template: &Template, placement: &Placement, style: &Style) -> String {
+    let mut svg = svg::begin(template.page.width, template.page.height);
+
+    for (i, code) in codes.iter().enumerate() {
+        let (left, top) = template.position(i);
+        let (x, y, side) = placement.code;
+        svg::code(&mut svg, &code.qr_code, style, left + x, top + y, side);
+        if let Some(caption) = &placement.caption {
+            svg::caption(
+                &mut svg,
+                &code.name,
+                style,
+                left + caption.x,
+                top + caption.y,
+                caption.size,
+                caption.width,
+            );
+        }
+    }
+
+    svg::end(&mut svg);
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Batch;
+
+    fn batch(count: usize) -> Batch {
+        let batch = Batch::default();
+        for row in 0..count {
+            let name = format!("code {}", row);
+            let qr_code = qrcodegen::QrCode::encode_text(&name, qrcodegen::QrCodeEcc::Low).unwrap();
+            batch.add(Path::new("in.csv"), row as u64, &name, &qr_code);
+        }
+        batch
+    }
+
+    #[test]
+    fn should_fit_avery_presets() {
+        for (name, labels) in [
+            ("L7160", 21),
+            ("L7163", 14),
+            ("L7165", 8),
+            ("L7651", 65),
+            ("5160", 30),
+            ("5163", 10),
+        ] {
+            assert_eq!(
+                labels,
+                Template::preset(name).unwrap().per_sheet(),
+                "{}",
+                name
+            );
+        }
+        assert!(Template::preset("L9999").is_err());
+    }
+
+    #[test]
+    fn should_reject_overlapping_labels() {
+        let points = |width: f32, height: f32| (width * POINTS_PER_MM, height * POINTS_PER_MM);
+
+        assert!(Template::new(
+            Paper::default(),
+            points(50.0, 50.0),
+            points(10.0, 10.0),
+            points(60.0, 60.0)
+        )
+        .is_ok());
+        assert!(Template::new(
+            Paper::default(),
+            points(50.0, 50.0),
+            points(10.0, 10.0),
+            points(40.0, 60.0)
+        )
+        .is_err());
+        assert!(Template::new(
+            Paper::default(),
+            points(250.0, 50.0),
+            points(0.0, 0.0),
+            points(250.0, 50.0)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_write_numbered_svg_sheets() {
+        let path = std::env::temp_dir().join("qrgen_should_write_numbered_svg_sheets.svg");
+        let template = Template::preset("L7163").unwrap();
+
+        let paths = write(
+            &path,
+            &batch(15).codes(),
+            &template,
+            &pdf::Document::default(),
+            &Style::default(),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(2, paths.len());
+        let first = fs::read_to_string(&paths[0]).unwrap();
+        assert!(first.contains("width=\"210.00mm\" height=\"297.00mm\""));
+        assert_eq!(14, first.matches("<text").count());
+        assert!(first.contains(">code 13</text>"));
+        assert_eq!(
+            1,
+            fs::read_to_string(&paths[1])
+                .unwrap()
+                .matches("<text")
+                .count()
+        );
+        for path in paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_write_a_pdf_page_for_each_sheet() {
+        let path = std::env::temp_dir().join("qrgen_should_write_a_pdf_page_for_each_sheet.pdf");
+        let template = Template::preset("L7160").unwrap();
+
+        let paths = write(
+            &path,
+            &batch(22).codes(),
+            &template,
+            &pdf::Document::default(),
+            &Style::default(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(vec![path.clone()], paths);
+        let pdf = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+        assert!(pdf.contains("/Count 2"));
+        assert!(!pdf.contains("/Helvetica"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use log::warn;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+const LOCK_FILE: &str = ".qrgen.lock";
+
+/// A lock file in the output directory, held for the length of a run so two runs can't write into the same directory
+/// at once.  The file holds the id of the process that created it and is removed when the lock is dropped.
+#[derive(Debug)]
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Create the lock file, failing if another run already holds it unless forced.
+    pub fn acquire(directory: &Path, force: bool) -> Result<Self, String> {
+        let path = directory.join(LOCK_FILE);
+        let mut options = OpenOptions::new();
+        if force {
+            options.write(true).create(true).truncate(true);
+        } else {
+            options.write(true).create_new(true);
+        }
+
+        match options.open(&path) {
+            Ok(mut file) => {
+                writeln!(file, "{}", process::id())
+                    .map_err(|e| format!("Unable to lock {}: {}", directory.display(), e))?;
+                Ok(OutputLock { path })
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(format!(
+                "{} is locked by another run (process {}).  Use --ignore-lock if {} was left behind by a run that \
+                 has stopped.",
+                directory.display(),
+                fs::read_to_string(&path).unwrap_or_default().trim(),
+                path.display()
+            )),
+            Err(e) => Err(format!("Unable to lock {}: {}", directory.display(), e)),
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("error removing lock {} {:?}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_refuse_a_locked_directory() {
+        let dir = std::env::temp_dir().join("qrgen_should_refuse_a_locked_directory");
+        fs::create_dir_all(&dir).unwrap();
+
+        let lock = OutputLock::acquire(&dir, false).unwrap();
+        assert!(OutputLock::acquire(&dir, false).is_err());
+        drop(lock);
+
+        assert!(!dir.join(LOCK_FILE).exists());
+        let lock = OutputLock::acquire(&dir, false).unwrap();
+        let forced = OutputLock::acquire(&dir, true);
+        assert!(forced.is_ok());
+        std::mem::forget(lock);
+        drop(forced);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
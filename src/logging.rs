@@ -0,0 +1,73 @@
+use log::kv::{self, Key, Value as KvValue, VisitSource};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+/// How log events are written to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// env_logger's human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line with the level, the message and the file and row it is about, when known.
+    Json,
+}
+
+/// Write a log event as a single line of JSON.
+pub fn write_json(buf: &mut impl Write, record: &log::Record) -> io::Result<()> {
+    writeln!(buf, "{}", Value::Object(event(record)))
+}
+
+/// The fields of a log event, the key-values attached to the record, e.g. file and row, along with its level and
+/// message.
+fn event(record: &log::Record) -> Map<String, Value> {
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+
+    let mut event = fields.0;
+    event.insert("level".into(), record.level().as_str().into());
+    event.insert("message".into(), record.args().to_string().into());
+    event
+}
+
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), kv::Error> {
+        let value = match value.to_u64() {
+            Some(number) => Value::from(number),
+            None => Value::from(value.to_string()),
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_write_event_as_json() {
+        let fields: &[(&str, KvValue)] = &[
+            ("file", KvValue::from("orders.csv")),
+            ("row", KvValue::from(12u64)),
+        ];
+        let mut buf = Vec::new();
+
+        write_json(
+            &mut buf,
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .args(format_args!("orders.csv row 12 (A-1) has no data"))
+                .key_values(&fields)
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "{\"file\":\"orders.csv\",\"level\":\"WARN\",\"message\":\"orders.csv row 12 (A-1) has no data\",\
+             \"row\":12}\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
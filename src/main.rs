@@ -1,26 +1,85 @@
-mod chunker;
+mod analysis;
+mod archive;
+mod batch;
+mod budget;
+mod cache;
+mod capacity;
+mod checksums;
+mod columns;
+mod crypto;
+mod dedupe;
+mod excel;
+mod exit;
 mod exporter;
+mod filter;
 mod generator;
-
+mod html;
+mod icc;
+mod input;
+mod json;
+mod jwt;
+mod labels;
+mod lock;
+mod logging;
+mod manifest;
+mod minisign;
+mod payload;
+mod pdf;
+mod poster;
+mod progress;
+mod queue;
+mod record;
+mod rejects;
+mod repl;
+mod retry;
+mod run;
+mod sequence;
+mod sheet;
+mod sign;
+mod space;
+mod stats;
+mod structured;
+mod svg;
+mod template;
+mod terminal;
+mod timings;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 use env_logger::Env;
 use log::{info, trace};
-use qrcodegen;
-use std::{env, ffi::OsStr, path::PathBuf};
-use structopt::StructOpt;
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+use structopt::{clap::AppSettings, StructOpt};
 
 #[derive(StructOpt)]
+#[structopt(setting = AppSettings::SubcommandsNegateReqs)]
 struct Opt {
-    /// Input file, must be specified.
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+
+    /// Input file, must be specified.  Use - to read from standard input, or an http:// or https:// URL to download
+    /// the input.  Glob patterns such as data/*.csv are expanded when the shell has not already done so.
     #[structopt(name = "infile", parse(from_os_str), required = true)]
     infile: Vec<PathBuf>,
 
-    /// Output path, or current working directory if not specified or - provided.
+    /// Output path, or current working directory if not specified.  - writes the QR Code of the encode subcommand to
+    /// standard output and isn't accepted otherwise.  {date}, {time} and {run_id} are replaced with the UTC date and
+    /// time the run started and a random id, e.g. -o out/{date} for a folder a day.
+    /// A path ending in .tar, .tar.gz or .tgz writes the outputs into that archive instead of a directory.
     #[structopt(
         name = "output path",
         short = "o",
         long = "output",
-        default_value = "-",
-        parse(from_os_str = parse_output_directory)
+        default_value = ".",
+        parse(from_os_str = parse_output_directory),
+        global = true
     )]
     output: PathBuf,
 
@@ -30,7 +89,8 @@ struct Opt {
         short = "m",
         long = "min",
         default_value = "1",
-        parse(try_from_str = parse_qr_version)
+        parse(try_from_str = parse_qr_version),
+        global = true
     )]
     qr_version_min: qrcodegen::Version,
 
@@ -40,7 +100,8 @@ struct Opt {
         short = "x",
         long = "max",
         default_value = "40",
-        parse(try_from_str = parse_qr_version)
+        parse(try_from_str = parse_qr_version),
+        global = true
     )]
     qr_version_max: qrcodegen::Version,
 
@@ -54,36 +115,522 @@ struct Opt {
         short = "e",
         long = "error",
         default_value = "High",
-        parse(try_from_str = parse_qr_ecc)
+        parse(try_from_str = parse_qr_ecc),
+        global = true
     )]
     error_correction: qrcodegen::QrCodeEcc,
 
-    /// The number of lines to try and process in parallel, if not specified defaults to 1 and file is processed line by
-    /// line.
+    /// Split a record too long for one QR Code of the maximum version across up to 16 linked by structured append,
+    /// written as name-1of3 and so on, or as the frames of one animated file in the GIF format.
+    #[structopt(long = "structured-append", global = true)]
+    structured_append: bool,
+
+    /// The milliseconds an animated GIF shows each QR Code of a structured append sequence for.
+    #[structopt(
+        name = "gif interval",
+        long = "gif-interval",
+        default_value = "500",
+        global = true
+    )]
+    gif_interval: u64,
+
+    /// The number of records that can be read ahead of the QR Codes being generated, which are generated in
+    /// parallel.  At most 10000 are held whatever the chunk size, so memory use stays bounded.
     #[structopt(
         name = "chunk size",
         short = "c",
         long = "chunk",
-        default_value = "1",
+        default_value = "256",
         parse(try_from_str = parse_chunk_size)
     )]
     chunk_size: usize,
 
+    /// Exit with code 2 if any record failed or was skipped with a warning, so partial failures can be detected, e.g.
+    /// in CI.  Unreadable input and unwritable output always exit with 3 and 4.
+    #[structopt(long = "strict", global = true)]
+    strict: bool,
+
+    /// Stop at the first record or file that fails, once the QR Codes already being generated are written, and exit
+    /// with an error.  Useful when trying out a new data source.
+    #[structopt(long = "fail-fast", global = true)]
+    fail_fast: bool,
+
+    /// Run even if another run has locked the output directory, e.g. when a run that was killed left its lock file
+    /// behind.
+    #[structopt(long = "ignore-lock", global = true)]
+    ignore_lock: bool,
+
+    /// Write the outputs as a tar archive to standard output instead of files in the output directory, e.g. to run
+    /// in a read-only container.  Paths in the archive are relative to the output directory.
+    #[structopt(long = "tar", global = true)]
+    tar: bool,
+
+    /// When the output path is an archive, start a new numbered volume before one would grow past this size before
+    /// compression, e.g. 500M gives codes-001.tar.gz, codes-002.tar.gz and so on.
+    #[structopt(long = "volume-size", global = true, parse(try_from_str = parse_volume_size))]
+    volume_size: Option<u64>,
+
+    /// When the output path is an archive, start a new numbered volume after this many files.
+    #[structopt(long = "volume-files", global = true, parse(try_from_str = parse_volume_files))]
+    volume_files: Option<u64>,
+
+    /// Stop with an error if the output directory doesn't exist rather than creating it.
+    #[structopt(long = "no-create", global = true)]
+    no_create: bool,
+
+    /// Don't check there is enough free space in the output directory before generating.
+    #[structopt(long = "no-space-check", global = true)]
+    no_space_check: bool,
+
+    /// The number of worker threads used to generate QR Codes, defaults to one per core.
+    #[structopt(
+        name = "threads",
+        long = "threads",
+        global = true,
+        parse(try_from_str = parse_threads)
+    )]
+    threads: Option<usize>,
+
+    /// The number of input files to read at the same time, e.g. to use every core on many small files.  With more
+    /// than one, which of two files with the same data counts as the duplicate for --dedupe is not fixed.
+    #[structopt(
+        name = "parallel files",
+        long = "parallel-files",
+        default_value = "1",
+        parse(try_from_str = parse_parallel_files)
+    )]
+    parallel_files: usize,
+
+    /// Keep memory use within about this many bytes, e.g. 256M, by limiting how many records are read ahead and how
+    /// many images are drawn at once.  K, M and G suffixes are accepted.
+    #[structopt(
+        name = "max memory",
+        long = "max-memory",
+        global = true,
+        parse(try_from_str = budget::MemoryBudget::parse)
+    )]
+    max_memory: Option<budget::MemoryBudget>,
+
+    /// Give up on a record that takes longer than this many seconds to encode, draw and write, e.g. 2.5, so a stuck
+    /// output can't stall the run.  Records that time out are added to the rejects report, and a write still going
+    /// at the time is thrown away when it finishes rather than left as an output.
+    #[structopt(
+        name = "record timeout",
+        long = "record-timeout",
+        global = true,
+        parse(try_from_str = parse_record_timeout)
+    )]
+    record_timeout: Option<Duration>,
+
+    /// The number of times to try writing a QR Code again after an error that may go away by itself, e.g. a network
+    /// share that briefly drops out.
+    #[structopt(
+        name = "write retries",
+        long = "write-retries",
+        default_value = "0",
+        global = true
+    )]
+    write_retries: u32,
+
+    /// The milliseconds to wait before the first retry of a write, doubling for each retry after.
+    #[structopt(
+        name = "retry backoff",
+        long = "retry-backoff",
+        default_value = "100",
+        global = true
+    )]
+    retry_backoff: u64,
+
     /// A flag indicating if the first line of the CSV is a header and should be skipped, defaults to false if not
     /// specified.
     #[structopt(name = "has headers", short = "s", long = "skip")]
     has_headers: bool,
 
+    /// Skip the first line of each CSV or Excel file when it looks like a header row, e.g. filename,data, rather
+    /// than a record.
+    #[structopt(long = "detect-header", conflicts_with = "has headers")]
+    detect_header: bool,
+
+    /// The layout of the input files, either Auto, CSV, JSON, NDJSON or Excel.  Auto picks JSON for .json files,
+    /// NDJSON for .ndjson and .jsonl files, Excel for .xlsx, .xlsm, .xlsb, .xls and .ods files and CSV for anything
+    /// else.  JSON records are objects with a name and data field, and may override the settings with ecc,
+    /// min_version, max_version, mask, format, scale and border fields.
+    #[structopt(
+        name = "input format",
+        long = "input-format",
+        default_value = "Auto",
+        parse(try_from_str = parse_input_format)
+    )]
+    input_format: input::InputFormat,
+
+    /// The field delimiter used by CSV input, a single character, tab, or auto to detect comma, semicolon, tab or
+    /// pipe from the first lines of each file.  Defaults to a comma, or a tab for .tsv files.
+    #[structopt(
+        name = "delimiter",
+        short = "d",
+        long = "delimiter",
+        parse(try_from_str = parse_delimiter)
+    )]
+    delimiter: Option<input::Delimiter>,
+
+    /// Skip CSV lines that start with the given character, e.g. #, so input files can be annotated.
+    #[structopt(
+        name = "comment character",
+        long = "comment",
+        parse(try_from_str = parse_ascii_char)
+    )]
+    comment: Option<u8>,
+
+    /// The character used to quote CSV fields.
+    #[structopt(
+        name = "quote character",
+        long = "quote",
+        default_value = "\"",
+        parse(try_from_str = parse_ascii_char)
+    )]
+    quote: u8,
+
+    /// The character used to escape quotes inside quoted CSV fields, e.g. \ for exports that write \".  Quotes are
+    /// otherwise escaped by doubling them.
+    #[structopt(
+        name = "escape character",
+        long = "escape",
+        parse(try_from_str = parse_ascii_char)
+    )]
+    escape: Option<u8>,
+
+    /// A flag indicating that two quotes in a row inside a quoted CSV field should not be read as one quote.
+    #[structopt(long = "no-double-quote")]
+    no_double_quote: bool,
+
+    /// The CSV or Excel column holding the file name of each record, either a column number starting at 1 or a name
+    /// from the header row.
+    #[structopt(
+        name = "name column",
+        long = "name-col",
+        default_value = "1",
+        parse(try_from_str = parse_column)
+    )]
+    name_column: columns::Column,
+
+    /// The CSV or Excel column holding the data to encode for each record, either a column number starting at 1 or
+    /// a name from the header row.  Defaults to 2, or 1 when naming by row.
+    #[structopt(
+        name = "data column",
+        long = "data-col",
+        parse(try_from_str = parse_column)
+    )]
+    data_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the modification time to give each output file, either a column number
+    /// starting at 1 or a name from the header row.  Times are Unix seconds, a date, e.g. 2024-05-01, or a UTC date
+    /// and time, e.g. 2024-05-01T09:30:00Z.
+    #[structopt(
+        name = "mtime column",
+        long = "mtime-col",
+        parse(try_from_str = parse_column)
+    )]
+    mtime_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding an error correction level for each record, High, Quartile, Medium or Low, in
+    /// place of --error, either a column number starting at 1 or a name from the header row.  Records with it empty
+    /// use --error.
+    #[structopt(
+        name = "ecc column",
+        long = "ecc-col",
+        parse(try_from_str = parse_column)
+    )]
+    ecc_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the minimum QR Code version for each record in place of --min, either a column
+    /// number starting at 1 or a name from the header row.  Records with it empty use --min.
+    #[structopt(name = "min column", long = "min-col", parse(try_from_str = parse_column))]
+    min_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the maximum QR Code version for each record in place of --max, either a column
+    /// number starting at 1 or a name from the header row.  Records with it empty use --max.
+    #[structopt(name = "max column", long = "max-col", parse(try_from_str = parse_column))]
+    max_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the mask for each record in place of --mask, either a column number starting
+    /// at 1 or a name from the header row.  Records with it empty use --mask, or the best mask when it isn't given.
+    #[structopt(
+        name = "mask column",
+        long = "mask-col",
+        parse(try_from_str = parse_column)
+    )]
+    mask_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the output formats for each record in place of --format, e.g. png or
+    /// svg,png, either a column number starting at 1 or a name from the header row.  Records with it empty use
+    /// --format.
+    #[structopt(
+        name = "format column",
+        long = "format-col",
+        parse(try_from_str = parse_column)
+    )]
+    format_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the scale for each record in place of --scale, either a column number starting
+    /// at 1 or a name from the header row.  Records with it empty use --scale.
+    #[structopt(
+        name = "scale column",
+        long = "scale-col",
+        parse(try_from_str = parse_column)
+    )]
+    scale_column: Option<columns::Column>,
+
+    /// The CSV or Excel column holding the border for each record in place of --border, either a column number
+    /// starting at 1 or a name from the header row.  Records with it empty use --border.
+    #[structopt(
+        name = "border column",
+        long = "border-col",
+        parse(try_from_str = parse_column)
+    )]
+    border_column: Option<columns::Column>,
+
+    /// Build the data to encode from a template instead of a single column, e.g.
+    /// "https://t.example/{sku}?batch={batch}".  Columns in braces are given by number starting at 1 or by header
+    /// name, use {{ and }} for literal braces.
+    #[structopt(
+        name = "data template",
+        long = "data-template",
+        conflicts_with = "data column",
+        parse(try_from_str = template::Template::parse)
+    )]
+    data_template: Option<template::Template>,
+
+    /// Build a structured payload from several columns instead of encoding a single column.  wifi builds a network
+    /// login, WIFI:T:WPA;S:ssid;P:password;;, from ssid, password, security (WPA, WEP or nopass) and hidden columns.
+    /// geo builds a location, geo:latitude,longitude, from latitude, longitude and optional altitude columns.  epc
+    /// builds a SEPA credit transfer from iban, bic, beneficiary, amount, reference, text and purpose columns.  upi
+    /// builds a UPI payment link, upi://pay?pa=vpa&pn=payee, from vpa, payee, amount, note and reference columns.
+    /// otpauth builds a TOTP enrolment from issuer, account, secret, digits, period and algorithm columns.  bitcoin
+    /// builds a BIP-21 payment request from address, amount, label and message columns, and ethereum an EIP-681 one
+    /// from address and amount columns.
+    /// Columns are found by name in the header row or, without one, in that order starting at the data column.
+    #[structopt(
+        name = "payload",
+        long = "payload",
+        conflicts_with_all = &["data column", "data template"],
+        parse(try_from_str = parse_payload_type)
+    )]
+    payload: Option<payload::PayloadType>,
+
+    /// Encode a JWT signed with the key given by --hmac-key-env or --hmac-key-file, holding claims taken from
+    /// columns, e.g. "sub=id,role,exp=expires".  A column given on its own is also the claim name, and exp, nbf and
+    /// iat are written as numbers.
+    #[structopt(
+        name = "jwt claims",
+        long = "jwt-claims",
+        conflicts_with_all = &["data column", "data template", "payload"],
+        parse(try_from_str = jwt::Claims::parse)
+    )]
+    jwt_claims: Option<jwt::Claims>,
+
+    /// Only generate CSV and Excel rows matching a condition, e.g. 'country == "DE" && stock > 0'.  Columns are
+    /// given by number starting at 1 or by header name, and compared with ==, !=, <, <=, > or >= against a quoted
+    /// string or a number.  Conditions can be combined with &&, || and !, and grouped with parentheses.
+    #[structopt(
+        name = "filter",
+        long = "filter",
+        parse(try_from_str = filter::Filter::parse)
+    )]
+    filter: Option<filter::Filter>,
+
+    /// Where output file names come from, either Column to use the name column, Row to number the records in the
+    /// order they are read, e.g. 000001.svg, for input that only holds data, or Hash to use the first 16 hex digits
+    /// of the SHA-256 hash of the data, which always gives the same data the same name.
+    #[structopt(
+        name = "naming",
+        long = "name-from",
+        default_value = "Column",
+        parse(try_from_str = parse_naming)
+    )]
+    naming: record::Naming,
+
+    /// Text put before the data of each record to turn raw ids into links, e.g. https://example.com/p/.  Characters
+    /// in the data that can't appear in a URL, such as spaces, are escaped.
+    #[structopt(name = "url prefix", long = "url-prefix")]
+    url_prefix: Option<String>,
+
+    /// Text put after the data of each record, e.g. ?src=qr.
+    #[structopt(name = "url suffix", long = "url-suffix")]
+    url_suffix: Option<String>,
+
+    /// The environment variable holding a key used to append an HMAC-SHA256 signature to the data of each record,
+    /// so the codes can be checked offline with qrgen verify.
+    #[structopt(
+        name = "hmac key env",
+        long = "hmac-key-env",
+        global = true,
+        conflicts_with = "hmac key file"
+    )]
+    hmac_key_env: Option<String>,
+
+    /// A file holding the signing key, as an alternative to --hmac-key-env.
+    #[structopt(
+        name = "hmac key file",
+        long = "hmac-key-file",
+        global = true,
+        parse(from_os_str)
+    )]
+    hmac_key_file: Option<PathBuf>,
+
+    /// The text put between the data and its signature.
+    #[structopt(long = "hmac-separator", default_value = ".", global = true)]
+    hmac_separator: String,
+
+    /// The number of records to skip at the start of each input file before generating, e.g. to restart a failed run
+    /// partway through.  A header row is not counted.
+    #[structopt(name = "skip rows", long = "skip-rows", default_value = "0")]
+    skip_rows: usize,
+
+    /// The maximum number of records to generate from each input file, after any skipped by --skip-rows.
+    #[structopt(name = "take", long = "take")]
+    take: Option<usize>,
+
+    /// What to do with records that have no data, or no name when names come from a column, either Skip to leave them
+    /// out, Warn to leave them out with a warning or Error to stop processing the file.
+    #[structopt(
+        name = "blank policy",
+        long = "on-blank",
+        default_value = "Warn",
+        parse(try_from_str = parse_blank_policy)
+    )]
+    on_blank: record::BlankPolicy,
+
+    /// What to do with records whose name isn't a safe file name, e.g. one holding a / or starting with a dot or, on
+    /// Windows, a device name such as CON, either Replace to make it safe, Skip to leave them out with a warning or
+    /// Error to stop processing the file.
+    #[structopt(
+        name = "unsafe name policy",
+        long = "on-unsafe-name",
+        default_value = "Replace",
+        global = true,
+        parse(try_from_str = parse_unsafe_name_policy)
+    )]
+    on_unsafe_name: record::UnsafeNamePolicy,
+
+    /// What to do with records whose name was already used by an earlier record in the run, either Suffix to add the
+    /// first free number, e.g. name-2, Overwrite to let the later record replace the earlier file or Error to stop
+    /// processing the file.  Names are compared without case.
+    #[structopt(
+        name = "duplicate name policy",
+        long = "on-duplicate-name",
+        default_value = "Suffix",
+        global = true,
+        parse(try_from_str = parse_duplicate_name_policy)
+    )]
+    on_duplicate_name: record::DuplicateNamePolicy,
+
+    /// Write file names in ASCII, replacing other characters with the nearest ASCII, e.g. Müller becomes Muller, for
+    /// shares and zip tools that mangle other names.
+    #[structopt(long = "ascii-names", global = true)]
+    ascii_names: bool,
+
+    /// The case of file names made from the name column, either Keep, Lower or Upper, so names that only differ in
+    /// case don't collide on a file system that ignores case.
+    #[structopt(
+        name = "name case",
+        long = "name-case",
+        default_value = "Keep",
+        global = true,
+        parse(try_from_str = parse_name_case)
+    )]
+    name_case: record::NameCase,
+
+    /// The longest file name, in bytes, with the extension.  Longer names are cut short and the start of their hash
+    /// added so names that start the same stay apart.
+    #[structopt(
+        name = "max name length",
+        long = "max-name-length",
+        default_value = "255",
+        global = true,
+        parse(try_from_str = parse_max_name_length)
+    )]
+    max_name_length: usize,
+
+    /// Spread outputs across this many levels of subdirectories named from the hash of their name, e.g. 2 writes
+    /// ab/cd/name.png, so no one directory holds too many files.
+    #[structopt(long = "shard", global = true, parse(try_from_str = parse_shard))]
+    shard: Option<u8>,
+
+    /// Write the outputs of each input file under the same subdirectory of the output as the file is in below the
+    /// directory holding all the input files, e.g. for data/**/*.csv.
+    #[structopt(long = "mirror-dirs", global = true)]
+    mirror_dirs: bool,
+
+    /// Write each format into its own subdirectory of the output named after it, e.g. svg/ and png/ for --format
+    /// svg,png.
+    #[structopt(long = "format-dirs", global = true)]
+    format_dirs: bool,
+
+    /// What to do with records whose data was already seen earlier in the run, either Skip to not generate them or
+    /// Link to hard link the earlier output under their name.  Every record is generated if not specified.
+    #[structopt(
+        name = "dedupe",
+        long = "dedupe",
+        parse(try_from_str = parse_dedupe)
+    )]
+    dedupe: Option<dedupe::Dedupe>,
+
+    /// Hard link an output whose image is byte for byte the same as one already written in the run rather than
+    /// writing it again, to save disk on batches that repeat payloads and settings.  The file is written in full where
+    /// the file system can't link it.
+    #[structopt(long = "link-identical", global = true)]
+    link_identical: bool,
+
+    /// The name of the worksheet to read from Excel input, defaults to the first sheet in the workbook.
+    #[structopt(name = "sheet name", long = "sheet")]
+    sheet: Option<String>,
+
+    /// An HTTP header to send when the input is a URL, in the form "Name: value".  May be given more than once,
+    /// e.g. for authorization.
+    #[structopt(
+        name = "header",
+        long = "header",
+        number_of_values = 1,
+        parse(try_from_str = parse_header)
+    )]
+    headers: Vec<(String, String)>,
+
+    /// The character encoding of the input files, e.g. latin1 or shift-jis, which is transcoded to UTF-8 while
+    /// reading.  Defaults to UTF-8.
+    #[structopt(
+        name = "input encoding",
+        long = "input-encoding",
+        parse(try_from_str = parse_input_encoding)
+    )]
+    input_encoding: Option<&'static encoding_rs::Encoding>,
+
     /// A flag indicating if output will be logged, defaults to false if not specified.
-    #[structopt(short = "l", long = "log")]
+    #[structopt(short = "l", long = "log", global = true)]
     log: bool,
 
+    /// The format of log events, either Text or Json for one JSON object per event with the level, message and the
+    /// input file and row it is about, when known.
+    #[structopt(
+        name = "log format",
+        long = "log-format",
+        default_value = "Text",
+        global = true,
+        parse(try_from_str = parse_log_format)
+    )]
+    log_format: logging::LogFormat,
+
     /// Verbose logging mode (-v, -vv, -vvv)
-    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences), global = true)]
     verbose: usize,
 
     /// The size of the border on the generated QR Code, defaults to 4 if not specified.
-    #[structopt(short = "b", long = "border", default_value = "4")]
+    #[structopt(
+        short = "b",
+        long = "border",
+        default_value = "4",
+        parse(try_from_str = parse_border),
+        global = true
+    )]
     border: u8,
 
     /// The mask value to apply to the QR Code, between 0 and 7 (inclusive).
@@ -91,41 +638,77 @@ struct Opt {
         name = "mask",
         short = "k",
         long = "mask",
-        parse(try_from_str = parse_qr_mask)
+        parse(try_from_str = parse_qr_mask),
+        global = true
     )]
     mask: Option<qrcodegen::Mask>,
 
-    /// The target output format.  Defaults to SVG if not specified.
+    /// The target output format.  Defaults to SVG if not specified.  Several formats separated by commas, e.g.
+    /// svg,png, write each QR Code in every one of them.
     #[structopt(
         name = "output format type",
         short = "f",
         long = "format",
         default_value = "SVG",
-        parse(try_from_str = parse_qr_format)
+        parse(try_from_str = parse_qr_formats),
+        global = true
     )]
-    format: exporter::ExportFormat,
+    formats: exporter::Formats,
 
     /// The side length (measured in pixels, must be positive) of each module, defaults to 8.  
-    /// This value only applies when using the PNG format.
-    /// Must be between 1 and 255 (inclusive)
+    /// This value only applies when using the PNG, AVIF or GIF format.
+    /// Must be between 1 and 4294967295 (inclusive), though the image must also fit in memory.
     #[structopt(
         short = "a",
         long = "scale",
         default_value = "8",
-        parse(try_from_str = parse_qr_scale)
+        parse(try_from_str = parse_qr_scale),
+        global = true
     )]
-    scale: u8,
+    scale: u32,
 
-    /// A flag indicating if the svg output should render the <rect /> tag.  Ignored if using PNG.
-    #[structopt(long = "no-rect")]
+    /// A flag indicating if the svg output should render the <rect /> tag, or for ICO to leave the background
+    /// transparent, as it is for GIF.  Ignored if using PNG or AVIF.
+    #[structopt(long = "no-rect", global = true)]
     no_rect: bool,
 
+    /// What to do when a QR Code's file already exists, either Skip to leave it, Overwrite to replace it, Error to
+    /// fail the record or Rename to write the QR Code under a numbered name, e.g. A-1-1.svg.
+    #[structopt(
+        name = "on exists",
+        long = "on-exists",
+        default_value = "Overwrite",
+        global = true,
+        parse(try_from_str = parse_on_exists)
+    )]
+    on_exists: exporter::OnExists,
+
+    /// Skip records whose QR Code was already written by an earlier run, leaving a file that isn't empty, so an
+    /// interrupted run can be restarted without starting over.
+    #[structopt(long = "resume", global = true)]
+    resume: bool,
+
+    /// Keep a hash of the data and settings of each QR Code in .qrgen-cache.json in the output directory and skip the
+    /// records whose output was already generated from the same hash, so a repeat run only writes what changed.
+    #[structopt(long = "cache", global = true)]
+    cache: bool,
+
+    /// Compare the records with the manifest written by an earlier run with --manifest and only generate those that
+    /// are new or have changed since.
+    #[structopt(name = "previous manifest", long = "previous-manifest", global = true)]
+    previous_manifest: Option<PathBuf>,
+
+    /// Remove the outputs of records in the previous manifest that are no longer in the input.
+    #[structopt(long = "prune", global = true, requires = "previous manifest")]
+    prune: bool,
+
     /// Set the foreground colour of the QR code using a six-digit hex value. Defaults to 000000.
     #[structopt(
         short = "r",
         long = "foreground",
         default_value = "000000",
-        parse(try_from_str = parse_rgb_from_hex)
+        parse(try_from_str = parse_rgb_from_hex),
+        global = true
     )]
     forgeround: (u8, u8, u8),
 
@@ -134,9 +717,332 @@ struct Opt {
         short = "g",
         long = "background",
         default_value = "FFFFFF",
-        parse(try_from_str = parse_rgb_from_hex)
+        parse(try_from_str = parse_rgb_from_hex),
+        global = true
     )]
     background: (u8, u8, u8),
+
+    /// Write a manifest describing every generated QR Code to the given path.  The manifest is written as JSON if
+    /// the path ends in .json, otherwise CSV.
+    #[structopt(
+        name = "manifest path",
+        long = "manifest",
+        parse(from_os_str),
+        global = true
+    )]
+    manifest: Option<PathBuf>,
+
+    /// Sign the manifest with a minisign secret key file, writing the signature beside it with .minisig added to the
+    /// name, so it can be checked with minisign -Vm and the public key.
+    #[structopt(
+        name = "manifest key",
+        long = "manifest-key",
+        parse(from_os_str),
+        requires = "manifest path",
+        global = true
+    )]
+    manifest_key: Option<PathBuf>,
+
+    /// The environment variable holding the password of the --manifest-key, unless it was created without one.
+    #[structopt(
+        name = "manifest key password env",
+        long = "manifest-key-password-env",
+        requires = "manifest key",
+        global = true
+    )]
+    manifest_key_password_env: Option<String>,
+
+    /// Add this run's entries to the end of the --manifest rather than replacing it, so daily runs build up one
+    /// manifest.  Each entry has the id of the run that generated it.
+    #[structopt(long = "manifest-append", requires = "manifest path", global = true)]
+    manifest_append: bool,
+
+    // The id of this run, given to the entries in the manifest.
+    #[structopt(skip)]
+    run_id: String,
+
+    /// Write the records skipped or linked by --dedupe to the given path, along with the record they repeated.  The
+    /// report is written as JSON if the path ends in .json, otherwise CSV.
+    #[structopt(
+        name = "duplicates path",
+        long = "duplicates-report",
+        parse(from_os_str),
+        requires = "dedupe"
+    )]
+    duplicates: Option<PathBuf>,
+
+    /// Write the records that couldn't be generated, including those that timed out, to the given path along with
+    /// the reason.  The report is written as JSON if the path ends in .json, otherwise CSV.
+    #[structopt(
+        name = "rejects path",
+        long = "rejects-report",
+        parse(from_os_str),
+        global = true
+    )]
+    rejects: Option<PathBuf>,
+
+    /// Also write every QR Code of the run to one PDF at the given path, one to a page, in input order.
+    #[structopt(name = "pdf path", long = "pdf", parse(from_os_str), global = true)]
+    pdf: Option<PathBuf>,
+
+    /// The paper size of the pages of the PDF, A3, A4, A5, Letter, Legal or a width and height in millimetres, e.g.
+    /// 100x150.  A4 unless given, or the size of the label template's sheets for labels.
+    #[structopt(long = "paper", parse(try_from_str = pdf::Paper::parse), global = true)]
+    paper: Option<pdf::Paper>,
+
+    /// Print the name of each record with its QR Code in the PDF and on labels.
+    #[structopt(long = "captions", global = true)]
+    captions: bool,
+
+    /// Write the PDF and PDF labels as PDF/A-2b for archiving, with the sRGB colour profile and XMP metadata embedded.
+    /// Captions need --pdf-font, as PDF/A embeds every font it uses.
+    #[structopt(long = "pdfa", global = true)]
+    pdfa: bool,
+
+    /// A TrueType font to embed for captions in PDFs rather than Helvetica, e.g. to match a house style.
+    #[structopt(
+        name = "font path",
+        long = "pdf-font",
+        parse(from_os_str),
+        global = true
+    )]
+    pdf_font: Option<PathBuf>,
+
+    /// Also tile every QR Code of the run onto PNG contact sheets at the given path, numbered e.g. sheet-001.png when
+    /// they need more than one.
+    #[structopt(
+        name = "contact sheet path",
+        long = "contact-sheet",
+        parse(from_os_str),
+        global = true
+    )]
+    contact_sheet: Option<PathBuf>,
+
+    /// The number of QR Codes across each contact sheet.
+    #[structopt(long = "sheet-columns", default_value = "5", parse(try_from_str = parse_grid_cells), global = true)]
+    sheet_columns: u32,
+
+    /// The number of rows of QR Codes down each contact sheet.
+    #[structopt(long = "sheet-rows", default_value = "5", parse(try_from_str = parse_grid_cells), global = true)]
+    sheet_rows: u32,
+
+    /// The gap in pixels between the QR Codes on a contact sheet and around its edge.
+    #[structopt(long = "sheet-gap", default_value = "16", global = true)]
+    sheet_gap: u32,
+
+    /// Also place every QR Code of the run on sheets of labels, written as a PDF with a page for each sheet if the
+    /// path ends in .pdf, otherwise as SVG files numbered e.g. labels-001.svg when there is more than one sheet.
+    #[structopt(
+        name = "labels path",
+        long = "labels",
+        parse(from_os_str),
+        global = true
+    )]
+    labels: Option<PathBuf>,
+
+    /// The Avery sheet the labels are laid out for, L7160, L7163, L7165, L7651, 5160 or 5163.
+    #[structopt(
+        long = "label-template",
+        default_value = "L7160",
+        parse(try_from_str = labels::Template::preset),
+        global = true
+    )]
+    label_template: labels::Template,
+
+    /// The width and height of each label in millimetres, e.g. 50x30, in place of the template's.
+    #[structopt(long = "label-size", parse(try_from_str = parse_label_geometry), global = true)]
+    label_size: Option<(f32, f32)>,
+
+    /// The left and top margins in millimetres to the first label, e.g. 7.2x15.1, in place of the template's.
+    #[structopt(long = "label-margins", parse(try_from_str = parse_label_geometry), global = true)]
+    label_margins: Option<(f32, f32)>,
+
+    /// The distance in millimetres from the edge of one label to the same edge of the next, across and down, e.g.
+    /// 66x38.1, in place of the template's.
+    #[structopt(long = "label-pitch", parse(try_from_str = parse_label_geometry), global = true)]
+    label_pitch: Option<(f32, f32)>,
+
+    /// Also arrange every QR Code of the run in a grid on one large SVG poster at the given path, with each record's
+    /// name under its QR Code.
+    #[structopt(
+        name = "poster path",
+        long = "poster",
+        parse(from_os_str),
+        global = true
+    )]
+    poster: Option<PathBuf>,
+
+    /// The title printed across the top of the poster.
+    #[structopt(long = "poster-title", global = true)]
+    poster_title: Option<String>,
+
+    /// The number of QR Codes across the poster, as close to a square as the codes allow unless given.
+    #[structopt(long = "poster-columns", parse(try_from_str = parse_grid_cells), global = true)]
+    poster_columns: Option<u32>,
+
+    /// Also write every QR Code of the run into one HTML page at the given path, each an inline PNG with the record's
+    /// name under it, so a batch can be emailed as one attachment and viewed offline.
+    #[structopt(name = "html path", long = "html", parse(from_os_str), global = true)]
+    html: Option<PathBuf>,
+
+    /// Write the SHA-256 of every output written by the run to the given path in the format of sha256sum, with paths
+    /// relative to the output directory, so recipients can check the outputs arrived intact with sha256sum -c.
+    #[structopt(
+        name = "checksums path",
+        long = "checksums",
+        parse(from_os_str),
+        global = true
+    )]
+    checksums: Option<PathBuf>,
+
+    /// A flag indicating if statistics about the generated QR Codes (versions, segment modes, module counts and
+    /// payload lengths) should be printed at the end of the run.
+    #[structopt(long = "stats", global = true)]
+    stats: bool,
+
+    /// Write the end of run statistics to the given path as JSON.
+    #[structopt(
+        name = "stats path",
+        long = "stats-file",
+        parse(from_os_str),
+        global = true
+    )]
+    stats_file: Option<PathBuf>,
+
+    /// A flag indicating if the time spent reading input, encoding, rendering and writing files should be printed at
+    /// the end of the run, to help tune the chunk size and thread count.
+    #[structopt(long = "timings", global = true)]
+    timings: bool,
+
+    /// Write progress events as newline delimited JSON to the given file descriptor, e.g. 1 for stdout, so a wrapper
+    /// can show the progress of the run.  The events are started, chunk-done, file-done and finished.
+    #[structopt(
+        name = "progress fd",
+        long = "progress-events",
+        global = true,
+        parse(try_from_str = parse_progress_fd)
+    )]
+    progress_events: Option<i32>,
+
+    /// Log a warning for any QR Code whose payload uses more than the given percentage of the symbol's data
+    /// capacity.
+    #[structopt(
+        name = "capacity warning percent",
+        long = "capacity-warn",
+        parse(try_from_str = parse_percent),
+        global = true
+    )]
+    capacity_warn: Option<f64>,
+
+    /// A flag indicating if the penalty score of all eight masks should be printed for every QR Code, along with the
+    /// mask that was chosen.
+    #[structopt(long = "analyze-masks", global = true)]
+    analyze_masks: bool,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Encode a single value into a QR Code, e.g. qrgen encode "some text" -o out.png.  The output format is taken
+    /// from the output file extension when it is svg, png, ico, avif or gif.  -o - writes the QR Code to standard
+    /// output in the first format instead, e.g. to pipe it into another program.
+    Encode {
+        /// The value to encode.
+        #[structopt(name = "value", required_unless_one = &["file", "clipboard"])]
+        value: Option<String>,
+
+        /// Encode the raw contents of the given file instead of a value, e.g. a small config file.
+        #[structopt(long = "file", parse(from_os_str), conflicts_with = "value")]
+        file: Option<PathBuf>,
+
+        /// A flag indicating if the file contents should be base64 encoded before being placed in the QR Code.
+        #[structopt(long = "base64", requires = "file")]
+        base64: bool,
+
+        /// Encode the text on the system clipboard instead of a value.
+        #[structopt(long = "clipboard", conflicts_with_all = &["value", "file"])]
+        clipboard: bool,
+
+        /// Draw the QR Code in the terminal instead of writing a file, e.g. to scan a link with a phone.
+        #[structopt(long = "terminal", conflicts_with = "file")]
+        terminal: bool,
+    },
+
+    /// Generate numbered QR Codes without an input file, e.g. qrgen seq --start 1000 --count 5000 --template
+    /// "ASSET-{n:06}".
+    Seq {
+        /// The first number of the sequence.
+        #[structopt(long = "start", default_value = "1")]
+        start: u64,
+
+        /// How many QR Codes to generate.
+        #[structopt(long = "count")]
+        count: u64,
+
+        /// The data of each QR Code, where {n} is replaced by the number and {n:06} pads it with zeros to six digits.
+        #[structopt(
+            long = "template",
+            default_value = "{n}",
+            parse(try_from_str = sequence::NumberTemplate::parse)
+        )]
+        template: sequence::NumberTemplate,
+
+        /// The name of each output file in the same form as the template, defaults to the data itself.
+        #[structopt(long = "name-template", parse(try_from_str = sequence::NumberTemplate::parse))]
+        name_template: Option<sequence::NumberTemplate>,
+    },
+
+    /// Generate QR Codes that each hold a new random UUID, e.g. qrgen uuid --count 500 --mapping devices.csv.
+    Uuid {
+        /// How many QR Codes to generate.
+        #[structopt(long = "count")]
+        count: u64,
+
+        /// The name of each output file, where {n} is replaced by a count starting at 1 and {n:06} pads it with
+        /// zeros to six digits.  Defaults to the UUID itself.
+        #[structopt(long = "name-template", parse(try_from_str = sequence::NumberTemplate::parse))]
+        name_template: Option<sequence::NumberTemplate>,
+
+        /// Write a CSV file listing each UUID and the path of each file it was written to.
+        #[structopt(long = "mapping", parse(from_os_str))]
+        mapping: Option<PathBuf>,
+    },
+
+    /// Wait for messages on a Redis list and generate a QR Code for each as it arrives, e.g. qrgen queue
+    /// redis://localhost/ orders.  Messages are JSON objects with a name and data field, the same as NDJSON input.
+    Queue {
+        /// The Redis server to connect to.
+        #[structopt(name = "url")]
+        url: String,
+
+        /// The list to take messages from.
+        #[structopt(name = "list")]
+        list: String,
+    },
+
+    /// Type payloads one per line and generate each one straight away with the current settings, enter quit or exit
+    /// to finish.
+    Repl {
+        /// The name of each output file, where {n} is replaced by a count starting at 1 and {n:06} pads it with
+        /// zeros to six digits.
+        #[structopt(
+            long = "name-template",
+            default_value = "{n:06}",
+            parse(try_from_str = sequence::NumberTemplate::parse)
+        )]
+        name_template: sequence::NumberTemplate,
+
+        /// Draw each QR Code in the terminal instead of writing a file.
+        #[structopt(long = "terminal")]
+        terminal: bool,
+    },
+
+    /// Check the signatures of scanned payloads with the key given by --hmac-key-env or --hmac-key-file, e.g. qrgen
+    /// verify --hmac-key-env TICKET_KEY "TICKET-1.abc".  Exits with an error if any payload is not valid.
+    Verify {
+        /// The scanned payloads to check.
+        #[structopt(name = "payload", required = true)]
+        payloads: Vec<String>,
+    },
 }
 
 fn parse_rgb_from_hex(src: &str) -> Result<(u8, u8, u8), String> {
@@ -148,11 +1054,12 @@ fn parse_rgb_from_hex(src: &str) -> Result<(u8, u8, u8), String> {
     let g = u8::from_str_radix(&src[2..4], 16).unwrap_or(0);
     let b = u8::from_str_radix(&src[4..6], 16).unwrap_or(0);
 
-    return Ok((r, g, b));
+    Ok((r, g, b))
 }
 
+// - is kept as it is so encode can write to standard output, see run.
 fn parse_output_directory(src: &OsStr) -> PathBuf {
-    if src == "-" {
+    if src == "." {
         return env::current_dir().expect("Unable to access current working directory.");
     }
 
@@ -165,7 +1072,190 @@ fn parse_qr_format(src: &str) -> Result<exporter::ExportFormat, String> {
     match src.as_ref() {
         "SVG" => Ok(exporter::ExportFormat::SVG),
         "PNG" => Ok(exporter::ExportFormat::PNG),
-        _ => Err(String::from("Format must be either SVG or PNG.")),
+        "ICO" => Ok(exporter::ExportFormat::ICO),
+        "AVIF" => Ok(exporter::ExportFormat::AVIF),
+        "GIF" => Ok(exporter::ExportFormat::GIF),
+        _ => Err(String::from("Format must be SVG, PNG, ICO, AVIF or GIF.")),
+    }
+}
+
+fn parse_qr_formats(src: &str) -> Result<exporter::Formats, String> {
+    let formats = src
+        .split(',')
+        .map(|format| parse_qr_format(format.trim()))
+        .collect::<Result<_, _>>()?;
+
+    exporter::Formats::new(formats)
+        .ok_or_else(|| String::from("Format must be SVG, PNG, ICO, AVIF or GIF."))
+}
+
+fn parse_input_format(src: &str) -> Result<input::InputFormat, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "AUTO" => Ok(input::InputFormat::Auto),
+        "CSV" => Ok(input::InputFormat::Csv),
+        "JSON" => Ok(input::InputFormat::Json),
+        "NDJSON" => Ok(input::InputFormat::Ndjson),
+        "EXCEL" | "XLSX" => Ok(input::InputFormat::Excel),
+        _ => Err(String::from(
+            "Input format must be either Auto, CSV, JSON, NDJSON or Excel.",
+        )),
+    }
+}
+
+fn parse_delimiter(src: &str) -> Result<input::Delimiter, String> {
+    match src {
+        "auto" | "AUTO" => Ok(input::Delimiter::Auto),
+        "tab" | "TAB" | "\\t" => Ok(input::Delimiter::Byte(b'\t')),
+        _ if src.len() == 1 && src.is_ascii() => Ok(input::Delimiter::Byte(src.as_bytes()[0])),
+        _ => Err(String::from(
+            "Delimiter must be a single ASCII character, tab or auto.",
+        )),
+    }
+}
+
+fn parse_ascii_char(src: &str) -> Result<u8, String> {
+    match src.as_bytes() {
+        [c] if c.is_ascii() => Ok(*c),
+        _ => Err(String::from("Must be a single ASCII character.")),
+    }
+}
+
+fn parse_column(src: &str) -> Result<columns::Column, String> {
+    match src.parse::<usize>() {
+        Ok(0) => Err(String::from("Column numbers start at 1.")),
+        Ok(x) => Ok(columns::Column::Index(x - 1)),
+        Err(_) if !src.trim().is_empty() => Ok(columns::Column::Name(src.trim().to_string())),
+        Err(_) => Err(String::from("Column must be a number or a header name.")),
+    }
+}
+
+fn parse_naming(src: &str) -> Result<record::Naming, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "COLUMN" => Ok(record::Naming::Column),
+        "ROW" => Ok(record::Naming::Row),
+        "HASH" => Ok(record::Naming::Hash),
+        _ => Err(String::from("Naming must be either Column, Row or Hash.")),
+    }
+}
+
+fn parse_payload_type(src: &str) -> Result<payload::PayloadType, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "WIFI" => Ok(payload::PayloadType::Wifi),
+        "GEO" => Ok(payload::PayloadType::Geo),
+        "EPC" => Ok(payload::PayloadType::Epc),
+        "UPI" => Ok(payload::PayloadType::Upi),
+        "OTPAUTH" => Ok(payload::PayloadType::Otpauth),
+        "BITCOIN" => Ok(payload::PayloadType::Bitcoin),
+        "ETHEREUM" => Ok(payload::PayloadType::Ethereum),
+        _ => Err(String::from(
+            "Payload must be either wifi, geo, epc, upi, otpauth, bitcoin or ethereum.",
+        )),
+    }
+}
+
+fn parse_blank_policy(src: &str) -> Result<record::BlankPolicy, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "SKIP" => Ok(record::BlankPolicy::Skip),
+        "WARN" => Ok(record::BlankPolicy::Warn),
+        "ERROR" => Ok(record::BlankPolicy::Error),
+        _ => Err(String::from(
+            "Blank policy must be either Skip, Warn or Error.",
+        )),
+    }
+}
+
+fn parse_unsafe_name_policy(src: &str) -> Result<record::UnsafeNamePolicy, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "REPLACE" => Ok(record::UnsafeNamePolicy::Replace),
+        "SKIP" => Ok(record::UnsafeNamePolicy::Skip),
+        "ERROR" => Ok(record::UnsafeNamePolicy::Error),
+        _ => Err(String::from(
+            "Unsafe name policy must be either Replace, Skip or Error.",
+        )),
+    }
+}
+
+fn parse_duplicate_name_policy(src: &str) -> Result<record::DuplicateNamePolicy, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "SUFFIX" => Ok(record::DuplicateNamePolicy::Suffix),
+        "OVERWRITE" => Ok(record::DuplicateNamePolicy::Overwrite),
+        "ERROR" => Ok(record::DuplicateNamePolicy::Error),
+        _ => Err(String::from(
+            "Duplicate name policy must be either Suffix, Overwrite or Error.",
+        )),
+    }
+}
+
+fn parse_name_case(src: &str) -> Result<record::NameCase, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "KEEP" => Ok(record::NameCase::Keep),
+        "LOWER" => Ok(record::NameCase::Lower),
+        "UPPER" => Ok(record::NameCase::Upper),
+        _ => Err(String::from(
+            "Name case must be either Keep, Lower or Upper.",
+        )),
+    }
+}
+
+fn parse_log_format(src: &str) -> Result<logging::LogFormat, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "TEXT" => Ok(logging::LogFormat::Text),
+        "JSON" => Ok(logging::LogFormat::Json),
+        _ => Err(String::from("Log format must be either Text or Json.")),
+    }
+}
+
+fn parse_on_exists(src: &str) -> Result<exporter::OnExists, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "SKIP" => Ok(exporter::OnExists::Skip),
+        "OVERWRITE" => Ok(exporter::OnExists::Overwrite),
+        "ERROR" => Ok(exporter::OnExists::Error),
+        "RENAME" => Ok(exporter::OnExists::Rename),
+        _ => Err(String::from(
+            "On exists must be either Skip, Overwrite, Error or Rename.",
+        )),
+    }
+}
+
+fn parse_dedupe(src: &str) -> Result<dedupe::Dedupe, String> {
+    let src = src.to_uppercase();
+
+    match src.as_ref() {
+        "SKIP" => Ok(dedupe::Dedupe::Skip),
+        "LINK" => Ok(dedupe::Dedupe::Link),
+        _ => Err(String::from("Dedupe must be either Skip or Link.")),
+    }
+}
+
+fn parse_input_encoding(src: &str) -> Result<&'static encoding_rs::Encoding, String> {
+    encoding_rs::Encoding::for_label(src.as_bytes())
+        .ok_or_else(|| format!("Unknown input encoding {}.", src))
+}
+
+fn parse_header(src: &str) -> Result<(String, String), String> {
+    match src.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(String::from("Header must be in the form \"Name: value\".")),
     }
 }
 
@@ -194,78 +1284,776 @@ fn parse_qr_version(src: &str) -> Result<qrcodegen::Version, String> {
     }
 }
 
-fn parse_qr_mask(src: &str) -> Result<qrcodegen::Mask, String> {
-    let input = src.parse::<u8>();
+fn parse_qr_mask(src: &str) -> Result<qrcodegen::Mask, String> {
+    let input = src.parse::<u8>();
+
+    match input {
+        Ok(x) if x < 8 => Ok(qrcodegen::Mask::new(x)),
+        _ => Err(String::from("QR mask must be between 0 and 7 inclusive.")),
+    }
+}
+
+fn parse_chunk_size(src: &str) -> Result<usize, String> {
+    let input = src.parse::<usize>();
+
+    match input {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(String::from("Chunk size must be a number greater than 0.")),
+    }
+}
+
+fn parse_threads(src: &str) -> Result<usize, String> {
+    match src.parse::<usize>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(String::from("Threads must be a number greater than 0.")),
+    }
+}
+
+fn parse_parallel_files(src: &str) -> Result<usize, String> {
+    match src.parse::<usize>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(String::from(
+            "Parallel files must be a number greater than 0.",
+        )),
+    }
+}
+
+fn parse_max_name_length(src: &str) -> Result<usize, String> {
+    match src.parse::<usize>() {
+        Ok(x) if x >= record::MIN_NAME_LENGTH => Ok(x),
+        _ => Err(format!(
+            "Max name length must be a number of at least {}.",
+            record::MIN_NAME_LENGTH
+        )),
+    }
+}
+
+fn parse_volume_size(src: &str) -> Result<u64, String> {
+    space::parse_bytes(src).ok_or_else(|| {
+        String::from(
+            "Volume size must be a number of bytes greater than 0, optionally followed by K, M or G.",
+        )
+    })
+}
+
+fn parse_volume_files(src: &str) -> Result<u64, String> {
+    match src.parse::<u64>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(String::from(
+            "Volume files must be a number greater than 0.",
+        )),
+    }
+}
+
+fn parse_grid_cells(src: &str) -> Result<u32, String> {
+    match src.parse::<u32>() {
+        Ok(x) if (1..=1000).contains(&x) => Ok(x),
+        _ => Err(String::from(
+            "Columns and rows must be a number from 1 to 1000.",
+        )),
+    }
+}
+
+fn parse_label_geometry(src: &str) -> Result<(f32, f32), String> {
+    pdf::parse_size(src).ok_or_else(|| {
+        String::from("Label sizes, margins and pitches must be a width and height in millimetres, e.g. 63.5x38.1.")
+    })
+}
+
+fn parse_shard(src: &str) -> Result<u8, String> {
+    match src.parse::<u8>() {
+        Ok(x) if (1..=exporter::MAX_SHARD_LEVELS).contains(&x) => Ok(x),
+        _ => Err(format!(
+            "Shard must be a number from 1 to {}.",
+            exporter::MAX_SHARD_LEVELS
+        )),
+    }
+}
+
+fn parse_progress_fd(src: &str) -> Result<i32, String> {
+    match src.parse::<i32>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(format!(
+            "Progress events need a file descriptor from 1 to {}.",
+            i32::MAX
+        )),
+    }
+}
+
+fn parse_record_timeout(src: &str) -> Result<Duration, String> {
+    match src.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(x)) if !x.is_zero() => Ok(x),
+        _ => Err(String::from(
+            "Record timeout must be a number of seconds greater than 0.",
+        )),
+    }
+}
+
+fn parse_percent(src: &str) -> Result<f64, String> {
+    let input = src.parse::<f64>();
+
+    match input {
+        Ok(x) if (0.0..=100.0).contains(&x) => Ok(x),
+        _ => Err(String::from(
+            "The percentage must be a number between 0 and 100 inclusive.",
+        )),
+    }
+}
+
+fn parse_qr_scale(src: &str) -> Result<u32, String> {
+    let input = src.parse::<u32>();
+
+    match input {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(String::from(
+            "The module scale must be a number between 1 and 4294967295 inclusive.",
+        )),
+    }
+}
+
+fn parse_border(src: &str) -> Result<u8, String> {
+    src.parse::<u8>().map_err(|_| {
+        String::from("The border must be a number of modules between 0 and 255 inclusive.")
+    })
+}
+
+/// Split the output path of a single QR Code into the directory, file name and formats to write.  An existing
+/// directory gets a file with the default name, otherwise a svg, png, ico, avif or gif extension selects the format.
+fn split_output_file(
+    output: &Path,
+    formats: exporter::Formats,
+    default_name: &str,
+) -> (PathBuf, String, exporter::Formats) {
+    if output.is_dir() {
+        return (output.to_path_buf(), String::from(default_name), formats);
+    }
+
+    // A bare file name is in the current directory, which Path gives as an empty parent.
+    let directory = output
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let name = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from(default_name));
+    let formats = output
+        .extension()
+        .and_then(|e| parse_qr_format(&e.to_string_lossy()).ok())
+        .map_or(formats, exporter::Formats::from);
+
+    (directory, name, formats)
+}
+
+impl Opt {
+    /// The signer for the key given on the command line, if one was given.
+    fn signer(&self) -> Result<Option<sign::Signer>, i32> {
+        match sign::read_key(self.hmac_key_env.as_deref(), self.hmac_key_file.as_deref()) {
+            Ok(key) => Ok(key.map(|key| sign::Signer::new(key, self.hmac_separator.clone()))),
+            Err(e) => {
+                eprintln!("{}", e);
+                Err(exit::ERROR)
+            }
+        }
+    }
+
+    /// Create the output directory if it's missing, unless --no-create, and lock it for as long as the returned lock
+    /// is held.  Nothing is written to the output directory when writing an archive, so there is nothing to lock.
+    fn lock_output(&self) -> Result<Option<lock::OutputLock>, i32> {
+        if self.tar || archive::is_archive(&self.output) {
+            return Ok(None);
+        }
+
+        if !self.output.is_dir() {
+            if self.no_create {
+                eprintln!(
+                    "{} doesn't exist.  Create it or leave out --no-create.",
+                    self.output.display()
+                );
+                return Err(exit::Failure::Output as i32);
+            }
+
+            if let Err(e) = fs::create_dir_all(&self.output) {
+                eprintln!("Unable to create {}: {}", self.output.display(), e);
+                return Err(exit::Failure::Output as i32);
+            }
+            info!("created output directory {}", self.output.display());
+        }
+
+        lock::OutputLock::acquire(&self.output, self.ignore_lock)
+            .map(Some)
+            .map_err(|e| {
+                eprintln!("{}", e);
+                exit::ERROR
+            })
+    }
+
+    /// The archive to write the outputs into, standard output with --tar or the output path when it ends in .tar,
+    /// .tar.gz or .tgz.
+    fn archive(&self) -> Result<Option<archive::Archive>, i32> {
+        let volumes = archive::Volumes::new(self.volume_size, self.volume_files);
+        if volumes.is_split() && (self.tar || !archive::is_archive(&self.output)) {
+            eprintln!(
+                "--volume-size and --volume-files need an archive output path ending in .tar, .tar.gz or .tgz."
+            );
+            return Err(exit::ERROR);
+        }
+        if !self.tar && !archive::is_archive(&self.output) {
+            return Ok(None);
+        }
+
+        if self.tar && io::stdout().is_terminal() {
+            eprintln!("--tar writes the archive to standard output, redirect it to a file or another program.");
+            return Err(exit::ERROR);
+        }
+        if self.tar
+            && (self.stats || self.timings || self.analyze_masks || self.progress_events == Some(1))
+        {
+            eprintln!(
+                "--stats, --timings, --analyze-masks and --progress-events 1 can't be used with --tar, which writes \
+                 the archive to standard output."
+            );
+            return Err(exit::ERROR);
+        }
+        if self.resume
+            || self.cache
+            || self.prune
+            || self.dedupe == Some(dedupe::Dedupe::Link)
+            || self.link_identical
+            || self.mtime_column.is_some()
+        {
+            eprintln!(
+                "--resume, --cache, --prune, --dedupe link, --link-identical and --mtime-col can't be used when \
+                 writing an archive, as they need the outputs on disk."
+            );
+            return Err(exit::ERROR);
+        }
+
+        if self.tar {
+            return Ok(Some(archive::Archive::new(Box::new(io::BufWriter::new(
+                io::stdout(),
+            )))));
+        }
+        archive::Archive::create(&self.output, volumes)
+            .map(Some)
+            .map_err(|e| {
+                eprintln!("Unable to create {}: {}", self.output.display(), e);
+                exit::Failure::Output as i32
+            })
+    }
 
-    match input {
-        Ok(x) if x < 8 => Ok(qrcodegen::Mask::new(x)),
-        _ => Err(String::from("QR mask must be between 0 and 7 inclusive.")),
+    /// Where to write the manifest and the key to sign it with, if one was given.
+    fn manifest(&self) -> Result<Option<generator::ManifestConfig>, i32> {
+        let path = match &self.manifest {
+            Some(path) => path.clone(),
+            None => return Ok(None),
+        };
+        let key = match &self.manifest_key {
+            Some(key) => key,
+            None => {
+                return Ok(Some(generator::ManifestConfig::new(
+                    path,
+                    None,
+                    self.manifest_append,
+                    self.run_id.clone(),
+                )))
+            }
+        };
+
+        let password = match &self.manifest_key_password_env {
+            Some(variable) => match env::var(variable) {
+                Ok(password) => Some(password),
+                Err(_) => {
+                    eprintln!("The environment variable {} is not set.", variable);
+                    return Err(exit::ERROR);
+                }
+            },
+            None => None,
+        };
+        match minisign::SecretKey::read(key, password.as_deref()) {
+            Ok(key) => Ok(Some(generator::ManifestConfig::new(
+                path,
+                Some(key),
+                self.manifest_append,
+                self.run_id.clone(),
+            ))),
+            Err(e) => {
+                eprintln!("{}", e);
+                Err(exit::ERROR)
+            }
+        }
     }
-}
 
-fn parse_chunk_size(src: &str) -> Result<usize, String> {
-    let input = src.parse::<usize>();
+    // The label template with any of its geometry replaced by the options given.
+    fn label_template(&self) -> Result<labels::Template, i32> {
+        let template = self.label_template;
+        if self.labels.as_deref().is_some_and(|path| {
+            !labels::is_pdf(path)
+                && !path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("svg"))
+        }) {
+            eprintln!("--labels needs a path ending in .pdf or .svg.");
+            return Err(exit::ERROR);
+        }
 
-    match input {
-        Ok(x) if x > 0 => Ok(x),
-        _ => Err(String::from("Chunk size must be a number greater than 0.")),
+        labels::Template::new(
+            self.paper.unwrap_or(template.page),
+            self.label_size.unwrap_or(template.label),
+            self.label_margins.unwrap_or(template.margins),
+            self.label_pitch.unwrap_or(template.pitch),
+        )
+        .map_err(|e| {
+            eprintln!("{}", e);
+            exit::ERROR
+        })
     }
-}
 
-fn parse_qr_scale(src: &str) -> Result<u8, String> {
-    let input = src.parse::<u8>();
+    fn pdf_document(&self) -> Result<pdf::Document, i32> {
+        if self.pdfa && self.captions && self.pdf_font.is_none() {
+            eprintln!("--pdfa needs --pdf-font with --captions, as PDF/A embeds its fonts.");
+            return Err(exit::ERROR);
+        }
 
-    match input {
-        Ok(x) if x > 0 => Ok(x),
-        _ => Err(String::from(
-            "The module scale must be a number between 1 and 255 inclusive.",
-        )),
+        let font = match &self.pdf_font {
+            Some(path) => Some(pdf::Font::read(path).map_err(|e| {
+                eprintln!("{}", e);
+                exit::ERROR
+            })?),
+            None => None,
+        };
+        Ok(pdf::Document {
+            paper: self.paper.unwrap_or_default(),
+            pdfa: self.pdfa,
+            font,
+        })
     }
-}
 
-impl Opt {
-    fn into_generator(self) -> generator::Generator {
-        generator::Generator::new(
-            self.infile,
+    fn into_generator(self) -> Result<generator::Generator, i32> {
+        let signer = self.signer()?;
+        let manifest = self.manifest()?;
+        let archive = self.archive()?;
+        let label_template = self.label_template()?;
+        let pdf_document = self.pdf_document()?;
+        if self.jwt_claims.is_some() && signer.is_none() {
+            eprintln!("--jwt-claims needs a key from --hmac-key-env or --hmac-key-file.");
+            return Err(exit::ERROR);
+        }
+
+        let progress = match self.progress_events {
+            Some(fd) => match progress::Progress::open(fd) {
+                Ok(progress) => Some(progress),
+                Err(e) => {
+                    eprintln!("Unable to write progress events to {}: {}", fd, e);
+                    return Err(exit::ERROR);
+                }
+            },
+            None => None,
+        };
+
+        let previous = match &self.previous_manifest {
+            Some(path) => match manifest::Previous::read(path) {
+                Ok(previous) => Some(previous),
+                Err(e) => {
+                    eprintln!("Unable to read manifest {}: {}", path.display(), e);
+                    return Err(exit::ERROR);
+                }
+            },
+            None => None,
+        };
+
+        Ok(generator::Generator::new(
+            input::expand_globs(self.infile),
             generator::QrConfig::new(
                 self.qr_version_min,
                 self.qr_version_max,
                 self.error_correction,
                 self.mask,
+                structured::StructuredAppend::new(
+                    self.structured_append,
+                    Duration::from_millis(self.gif_interval),
+                ),
             ),
             generator::OutputConfig::new(
                 self.output,
                 self.border,
-                self.format,
+                self.formats,
                 self.scale,
                 self.no_rect,
                 self.forgeround,
                 self.background,
+                generator::Destination::new(self.on_exists, archive, self.link_identical),
+                generator::Incremental::new(self.resume, self.cache, previous, self.prune),
+                generator::FileNames::new(
+                    self.ascii_names,
+                    self.name_case,
+                    self.max_name_length,
+                    self.shard.unwrap_or(0),
+                    self.mirror_dirs,
+                    self.format_dirs,
+                ),
             ),
-            generator::ProcessingConfig::new(self.chunk_size, self.has_headers),
-        )
+            generator::InputConfig::new(
+                match (self.has_headers, self.detect_header) {
+                    (true, _) => input::HeaderRow::Present,
+                    (_, true) => input::HeaderRow::Auto,
+                    _ => input::HeaderRow::Absent,
+                },
+                self.input_format,
+                self.delimiter,
+                self.sheet,
+                self.headers,
+                self.input_encoding,
+                columns::ColumnMap::new(
+                    self.name_column,
+                    self.data_column.unwrap_or(match self.naming {
+                        record::Naming::Row => columns::Column::Index(0),
+                        _ => columns::Column::Index(1),
+                    }),
+                    self.mtime_column,
+                    columns::OverrideColumns {
+                        ecc: self.ecc_column,
+                        min: self.min_column,
+                        max: self.max_column,
+                        mask: self.mask_column,
+                        format: self.format_column,
+                        scale: self.scale_column,
+                        border: self.border_column,
+                    },
+                ),
+                match (self.data_template, self.payload, self.jwt_claims) {
+                    (Some(template), _, _) => payload::DataSource::Template(template),
+                    (_, Some(payload), _) => payload::DataSource::Build(payload),
+                    (_, _, Some(claims)) => payload::DataSource::Jwt(claims),
+                    _ => payload::DataSource::Column,
+                },
+                self.filter,
+                input::CsvOptions::new(
+                    self.quote,
+                    self.escape,
+                    !self.no_double_quote,
+                    self.comment,
+                ),
+            ),
+            generator::ProcessingConfig::new(
+                self.chunk_size,
+                self.naming,
+                self.skip_rows,
+                self.take,
+                self.dedupe,
+                generator::ErrorPolicy::new(
+                    self.on_blank,
+                    self.on_unsafe_name,
+                    self.on_duplicate_name,
+                    self.fail_fast,
+                    retry::Retry::new(
+                        self.write_retries,
+                        Duration::from_millis(self.retry_backoff),
+                    ),
+                ),
+                self.url_prefix,
+                self.url_suffix,
+                signer,
+                generator::Limits::new(
+                    self.parallel_files,
+                    self.max_memory,
+                    self.record_timeout,
+                    !self.no_space_check,
+                ),
+            ),
+            generator::ReportConfig::new(
+                manifest,
+                self.stats,
+                self.stats_file,
+                self.capacity_warn,
+                self.analyze_masks,
+                self.duplicates,
+                self.rejects,
+                self.checksums,
+                self.timings,
+                progress,
+            ),
+            generator::BatchConfig::new(
+                self.pdf,
+                pdf_document,
+                self.captions,
+                self.contact_sheet,
+                sheet::Grid::new(self.sheet_columns, self.sheet_rows, self.sheet_gap),
+                self.labels,
+                label_template,
+                self.poster,
+                poster::Layout::new(self.poster_title, self.poster_columns),
+                self.html,
+            ),
+        ))
     }
 }
 
 fn main() {
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+    let this_run = run::Run::start();
+    opt.output = this_run.expand(&opt.output);
+    opt.run_id = this_run.id().to_string();
 
     // Initialize logger
     if opt.log {
-        env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
-            0 => "warn",
-            1 => "info",
-            2 => "debug",
-            _ => "trace",
-        }))
-        .init();
-    }
-
-    info!("qrgen start");
-    let generator = opt.into_generator();
-    trace!("{}", generator);
-    generator.generate();
+        let mut builder =
+            env_logger::Builder::from_env(Env::default().default_filter_or(match opt.verbose {
+                0 => "warn",
+                1 => "info",
+                2 => "debug",
+                _ => "trace",
+            }));
+        if opt.log_format == logging::LogFormat::Json {
+            builder.format(logging::write_json);
+        }
+        builder.init();
+    }
+
+    if let Some(threads) = opt.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("Unable to start {} threads: {}", threads, e);
+            process::exit(exit::ERROR);
+        }
+    }
+
+    info!("qrgen start, run {}", this_run.id());
+    let strict = opt.strict || opt.fail_fast;
+    let generator = run(opt);
     info!("qrgen end");
+
+    // Skipped or failed records only fail the run with --strict, unreadable input and unwritable output always do.
+    match generator {
+        Ok(Some(generator)) => {
+            let failures = generator.failures();
+            match failures.worst() {
+                Some(exit::Failure::Record) if !strict => (),
+                Some(failure) => {
+                    eprintln!(
+                        "{} records or files failed or were skipped",
+                        failures.total()
+                    );
+                    process::exit(failure as i32);
+                }
+                None => (),
+            }
+        }
+        Ok(None) => (),
+        Err(code) => process::exit(code),
+    }
+}
+
+/// Stop before generating when the QR Codes for the records can't fit in the output directory.
+fn check_disk_space(
+    generator: &generator::Generator,
+    records: impl FnOnce() -> u64,
+) -> Result<(), i32> {
+    generator.check_disk_space(records).map_err(|e| {
+        eprintln!("{}", e);
+        exit::Failure::Output as i32
+    })
+}
+
+/// Run the command, returning the generator so the failures during the run can be reported, or the exit code of an
+/// error that stopped the run.  The output directory stays locked until the command has finished.
+fn run(mut opt: Opt) -> Result<Option<generator::Generator>, i32> {
+    // Only a single QR Code from encode can be written to standard output.
+    let stdout = opt.output == Path::new("-");
+    if stdout {
+        if !matches!(opt.cmd, Some(Command::Encode { .. })) {
+            eprintln!("-o - only writes to standard output for encode, use -o . for the current directory.");
+            return Err(exit::ERROR);
+        }
+        opt.output = parse_output_directory(OsStr::new("."));
+    }
+
+    let generator = match opt.cmd.take() {
+        Some(Command::Encode {
+            value,
+            file,
+            base64,
+            clipboard,
+            terminal,
+        }) => {
+            let value = if clipboard {
+                match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+                    Ok(text) => Some(text),
+                    Err(e) => {
+                        eprintln!("Unable to read the clipboard: {}", e);
+                        return Err(exit::Failure::Input as i32);
+                    }
+                }
+            } else {
+                value
+            };
+
+            if stdout && !terminal {
+                let generator = opt.into_generator()?;
+                trace!("{}", generator);
+
+                let image = match (value, file) {
+                    (Some(value), _) => generator.render_value(&value),
+                    (None, Some(file)) => match fs::read(&file) {
+                        Ok(bytes) if base64 => generator.render_value(&STANDARD.encode(bytes)),
+                        Ok(bytes) => generator.render_bytes(&bytes),
+                        Err(e) => {
+                            eprintln!("Unable to read {}: {}", file.display(), e);
+                            return Err(exit::Failure::Input as i32);
+                        }
+                    },
+                    (None, None) => unreachable!("clap requires a value or a file"),
+                };
+                if let Some(image) = image {
+                    if let Err(e) = io::stdout().lock().write_all(&image) {
+                        eprintln!("Unable to write to standard output: {}", e);
+                        return Err(exit::Failure::Output as i32);
+                    }
+                }
+                return Ok(Some(generator));
+            }
+
+            let default_name = file
+                .as_ref()
+                .and_then(|f| f.file_stem())
+                .map_or(String::from("qrcode"), |s| s.to_string_lossy().to_string());
+            let (output, name, formats) =
+                split_output_file(&opt.output, opt.formats.clone(), &default_name);
+            opt.output = output;
+            opt.formats = formats;
+
+            let _lock = if terminal { None } else { opt.lock_output()? };
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+
+            match (value, file) {
+                (Some(value), _) if terminal => {
+                    print!("{}", generator.draw_value(&value).unwrap_or_default())
+                }
+                (Some(value), _) => generator.generate_value(&name, &value),
+                (None, Some(file)) => match fs::read(&file) {
+                    Ok(bytes) if base64 => generator.generate_value(&name, &STANDARD.encode(bytes)),
+                    Ok(bytes) => generator.generate_bytes(&name, &bytes),
+                    Err(e) => {
+                        eprintln!("Unable to read {}: {}", file.display(), e);
+                        return Err(exit::Failure::Input as i32);
+                    }
+                },
+                (None, None) => unreachable!("clap requires a value or a file"),
+            }
+            Some(generator)
+        }
+        Some(Command::Seq {
+            start,
+            count,
+            template,
+            name_template,
+        }) => {
+            let records = match sequence::records(start, count, template, name_template) {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return Err(exit::ERROR);
+                }
+            };
+
+            let _lock = opt.lock_output()?;
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+            check_disk_space(&generator, || count)?;
+            generator.generate_records(Path::new("seq"), records);
+            Some(generator)
+        }
+        Some(Command::Uuid {
+            count,
+            name_template,
+            mapping,
+        }) => {
+            let records: Vec<record::Record> =
+                sequence::uuid_records(count, name_template).collect();
+
+            let _lock = opt.lock_output()?;
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+            check_disk_space(&generator, || records.len() as u64)?;
+            let outputs = generator.generate_listed(Path::new("uuid"), &records);
+
+            if let Some(path) = mapping {
+                if let Err(e) = sequence::write_mapping(&path, &records, &outputs) {
+                    eprintln!("Unable to write {}: {}", path.display(), e);
+                    return Err(exit::Failure::Output as i32);
+                }
+            }
+            Some(generator)
+        }
+        Some(Command::Queue { url, list }) => {
+            let _lock = opt.lock_output()?;
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+
+            if let Err(e) = queue::consume(&generator, &url, &list) {
+                eprintln!("Unable to read from {} {}: {}", url, list, e);
+                return Err(exit::Failure::Input as i32);
+            }
+            Some(generator)
+        }
+        Some(Command::Repl {
+            name_template,
+            terminal,
+        }) => {
+            let _lock = if terminal { None } else { opt.lock_output()? };
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+
+            let stdin = io::stdin();
+            let prompt = stdin.is_terminal();
+            if let Err(e) = repl::run(
+                &generator,
+                stdin.lock(),
+                io::stdout(),
+                &name_template,
+                terminal,
+                prompt,
+            ) {
+                eprintln!("Unable to read input: {}", e);
+                return Err(exit::Failure::Input as i32);
+            }
+            Some(generator)
+        }
+        Some(Command::Verify { payloads }) => {
+            let signer = match opt.signer()? {
+                Some(signer) => signer,
+                None => {
+                    eprintln!("A key is needed from --hmac-key-env or --hmac-key-file.");
+                    return Err(exit::ERROR);
+                }
+            };
+
+            let mut all_valid = true;
+            for payload in payloads {
+                let valid = signer.verify(&payload);
+                all_valid &= valid;
+                println!("{} {}", if valid { "valid" } else { "invalid" }, payload);
+            }
+
+            if !all_valid {
+                return Err(exit::ERROR);
+            }
+            None
+        }
+        None => {
+            let _lock = opt.lock_output()?;
+            let generator = opt.into_generator()?;
+            trace!("{}", generator);
+            check_disk_space(&generator, || generator.count_records())?;
+            generator.generate();
+            Some(generator)
+        }
+    };
+
+    Ok(generator)
 }
 
 #[cfg(test)]
@@ -275,9 +2063,59 @@ mod tests {
     #[test]
     fn should_parse_output_directory_to_cwd() {
         let expect = env::current_dir().unwrap();
-        let actual = parse_output_directory(OsStr::new("-"));
+        let actual = parse_output_directory(OsStr::new("."));
 
         assert_eq!(expect, actual);
+        assert_eq!(PathBuf::from("-"), parse_output_directory(OsStr::new("-")));
+    }
+
+    #[test]
+    fn should_refuse_stdout_output_outside_encode() {
+        let opt = Opt::from_iter_safe(["qrgen", "codes.csv", "-o", "-"]).unwrap();
+
+        assert_eq!(Some(exit::ERROR), run(opt).err());
+    }
+
+    #[test]
+    fn should_split_output_file_by_extension() {
+        let (directory, name, formats) = split_output_file(
+            Path::new("codes/out.png"),
+            exporter::ExportFormat::SVG.into(),
+            "qrcode",
+        );
+
+        assert_eq!(PathBuf::from("codes"), directory);
+        assert_eq!("out", name);
+        assert_eq!(
+            exporter::Formats::from(exporter::ExportFormat::PNG),
+            formats
+        );
+    }
+
+    #[test]
+    fn should_split_output_file_with_default_format() {
+        let (directory, name, formats) = split_output_file(
+            Path::new("out"),
+            exporter::ExportFormat::SVG.into(),
+            "qrcode",
+        );
+
+        assert_eq!(PathBuf::from("."), directory);
+        assert_eq!("out", name);
+        assert_eq!(
+            exporter::Formats::from(exporter::ExportFormat::SVG),
+            formats
+        );
+    }
+
+    #[test]
+    fn should_split_output_directory() {
+        let cwd = env::current_dir().unwrap();
+        let (directory, name, _) =
+            split_output_file(&cwd, exporter::ExportFormat::PNG.into(), "wg0");
+
+        assert_eq!(cwd, directory);
+        assert_eq!("wg0", name);
     }
 
     #[test]
@@ -307,10 +2145,219 @@ mod tests {
         assert_eq!(exporter::ExportFormat::SVG, res);
     }
 
+    #[test]
+    fn should_parse_qr_format_to_gif() {
+        let res = parse_qr_format("gif").unwrap();
+        assert_eq!(exporter::ExportFormat::GIF, res);
+    }
+
     #[test]
     fn should_parse_qr_format_to_error() {
         let res = parse_qr_format("error").err();
-        assert_eq!(Some("Format must be either SVG or PNG.".to_string()), res);
+        assert_eq!(
+            Some("Format must be SVG, PNG, ICO, AVIF or GIF.".to_string()),
+            res
+        );
+    }
+
+    #[test]
+    fn should_parse_qr_formats() {
+        let res = parse_qr_formats("svg, PNG").unwrap();
+        assert_eq!(
+            vec![exporter::ExportFormat::SVG, exporter::ExportFormat::PNG],
+            res.iter().collect::<Vec<_>>()
+        );
+        assert!(parse_qr_formats("svg,bmp").is_err());
+    }
+
+    #[test]
+    fn should_parse_input_format() {
+        assert_eq!(Ok(input::InputFormat::Ndjson), parse_input_format("ndjson"));
+        assert_eq!(
+            Some("Input format must be either Auto, CSV, JSON, NDJSON or Excel.".to_string()),
+            parse_input_format("xml").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_delimiter() {
+        assert_eq!(Ok(input::Delimiter::Byte(b';')), parse_delimiter(";"));
+        assert_eq!(Ok(input::Delimiter::Byte(b'\t')), parse_delimiter("tab"));
+        assert_eq!(Ok(input::Delimiter::Byte(b'\t')), parse_delimiter("\\t"));
+        assert_eq!(Ok(input::Delimiter::Auto), parse_delimiter("auto"));
+    }
+
+    #[test]
+    fn should_parse_delimiter_to_error() {
+        let res = parse_delimiter(";;").err();
+        assert_eq!(
+            Some("Delimiter must be a single ASCII character, tab or auto.".to_string()),
+            res
+        );
+    }
+
+    #[test]
+    fn should_parse_ascii_char() {
+        assert_eq!(Ok(b'#'), parse_ascii_char("#"));
+        assert_eq!(
+            Some("Must be a single ASCII character.".to_string()),
+            parse_ascii_char("//").err()
+        );
+        assert!(parse_ascii_char("é").is_err());
+    }
+
+    #[test]
+    fn should_parse_column() {
+        assert_eq!(Ok(columns::Column::Index(4)), parse_column("5"));
+        assert_eq!(
+            Ok(columns::Column::Name("payload".to_string())),
+            parse_column("payload")
+        );
+    }
+
+    #[test]
+    fn should_parse_column_to_error() {
+        assert_eq!(
+            Some("Column numbers start at 1.".to_string()),
+            parse_column("0").err()
+        );
+        assert!(parse_column(" ").is_err());
+    }
+
+    #[test]
+    fn should_parse_naming() {
+        assert_eq!(Ok(record::Naming::Row), parse_naming("row"));
+        assert_eq!(Ok(record::Naming::Hash), parse_naming("Hash"));
+        assert_eq!(
+            Some("Naming must be either Column, Row or Hash.".to_string()),
+            parse_naming("index").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_payload_type() {
+        assert_eq!(Ok(payload::PayloadType::Wifi), parse_payload_type("WiFi"));
+        assert_eq!(Ok(payload::PayloadType::Geo), parse_payload_type("geo"));
+        assert_eq!(Ok(payload::PayloadType::Epc), parse_payload_type("EPC"));
+        assert_eq!(Ok(payload::PayloadType::Upi), parse_payload_type("upi"));
+        assert_eq!(
+            Ok(payload::PayloadType::Otpauth),
+            parse_payload_type("otpauth")
+        );
+        assert_eq!(
+            Ok(payload::PayloadType::Ethereum),
+            parse_payload_type("Ethereum")
+        );
+        assert!(parse_payload_type("wlan").is_err());
+    }
+
+    #[test]
+    fn should_parse_unsafe_name_policy() {
+        assert_eq!(
+            Ok(record::UnsafeNamePolicy::Skip),
+            parse_unsafe_name_policy("skip")
+        );
+        assert_eq!(
+            Some(String::from(
+                "Unsafe name policy must be either Replace, Skip or Error."
+            )),
+            parse_unsafe_name_policy("strip").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_duplicate_name_policy() {
+        assert_eq!(
+            Ok(record::DuplicateNamePolicy::Overwrite),
+            parse_duplicate_name_policy("overwrite")
+        );
+        assert_eq!(
+            Some(String::from(
+                "Duplicate name policy must be either Suffix, Overwrite or Error."
+            )),
+            parse_duplicate_name_policy("rename").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_name_case() {
+        assert_eq!(Ok(record::NameCase::Lower), parse_name_case("lower"));
+        assert_eq!(
+            Some(String::from(
+                "Name case must be either Keep, Lower or Upper."
+            )),
+            parse_name_case("title").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_blank_policy() {
+        assert_eq!(Ok(record::BlankPolicy::Error), parse_blank_policy("error"));
+        assert_eq!(
+            Some("Blank policy must be either Skip, Warn or Error.".to_string()),
+            parse_blank_policy("ignore").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_log_format() {
+        assert_eq!(Ok(logging::LogFormat::Json), parse_log_format("json"));
+        assert_eq!(
+            Some("Log format must be either Text or Json.".to_string()),
+            parse_log_format("xml").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_on_exists() {
+        assert_eq!(Ok(exporter::OnExists::Rename), parse_on_exists("rename"));
+        assert_eq!(
+            Some("On exists must be either Skip, Overwrite, Error or Rename.".to_string()),
+            parse_on_exists("append").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_dedupe() {
+        assert_eq!(Ok(dedupe::Dedupe::Link), parse_dedupe("link"));
+        assert_eq!(
+            Some("Dedupe must be either Skip or Link.".to_string()),
+            parse_dedupe("drop").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_input_encoding() {
+        assert_eq!(
+            Ok(encoding_rs::WINDOWS_1252),
+            parse_input_encoding("latin1")
+        );
+        assert_eq!(
+            Ok(encoding_rs::SHIFT_JIS),
+            parse_input_encoding("shift-jis")
+        );
+        assert_eq!(
+            Some("Unknown input encoding klingon.".to_string()),
+            parse_input_encoding("klingon").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_header() {
+        assert_eq!(
+            Ok(("Authorization".to_string(), "Bearer a:b".to_string())),
+            parse_header("Authorization: Bearer a:b")
+        );
+    }
+
+    #[test]
+    fn should_parse_header_to_error() {
+        let res = parse_header(": value").err();
+        assert_eq!(
+            Some("Header must be in the form \"Name: value\".".to_string()),
+            res
+        );
+        assert!(parse_header("Authorization").is_err());
     }
 
     #[test]
@@ -387,20 +2434,30 @@ mod tests {
     fn should_parse_qr_scale_to_error_low() {
         let res = parse_qr_scale("0").err();
         assert_eq!(
-            Some("The module scale must be a number between 1 and 255 inclusive.".to_string()),
+            Some(
+                "The module scale must be a number between 1 and 4294967295 inclusive.".to_string()
+            ),
             res
         );
     }
 
     #[test]
     fn should_parse_qr_scale_to_error_high() {
-        let res = parse_qr_scale("256").err();
+        let res = parse_qr_scale("4294967296").err();
         assert_eq!(
-            Some("The module scale must be a number between 1 and 255 inclusive.".to_string()),
+            Some(
+                "The module scale must be a number between 1 and 4294967295 inclusive.".to_string()
+            ),
             res
         );
     }
 
+    #[test]
+    fn should_parse_qr_scale_above_255() {
+        assert_eq!(Ok(256), parse_qr_scale("256"));
+        assert_eq!(Ok(1000), parse_qr_scale("1000"));
+    }
+
     #[test]
     fn should_parse_qr_mask_to_error_high() {
         let res = parse_qr_mask("8").err();
@@ -410,6 +2467,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_parse_percent() {
+        assert_eq!(Ok(85.5), parse_percent("85.5"));
+    }
+
+    #[test]
+    fn should_parse_percent_to_error() {
+        let res = parse_percent("101").err();
+        assert_eq!(
+            Some("The percentage must be a number between 0 and 100 inclusive.".to_string()),
+            res
+        );
+    }
+
     #[test]
     fn should_parse_chunk_size_to_error() {
         let res = parse_chunk_size("0").err();
@@ -419,12 +2490,91 @@ mod tests {
         );
     }
 
+    #[test]
+    fn should_parse_max_name_length() {
+        assert_eq!(Ok(100), parse_max_name_length("100"));
+        assert_eq!(
+            Some("Max name length must be a number of at least 16.".to_string()),
+            parse_max_name_length("8").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_volume_limits() {
+        assert_eq!(Ok(500 << 20), parse_volume_size("500M"));
+        assert!(parse_volume_size("0").is_err());
+        assert_eq!(Ok(10_000), parse_volume_files("10000"));
+        assert_eq!(
+            Some("Volume files must be a number greater than 0.".to_string()),
+            parse_volume_files("0").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_grid_cells() {
+        assert_eq!(Ok(4), parse_grid_cells("4"));
+        assert_eq!(
+            Some("Columns and rows must be a number from 1 to 1000.".to_string()),
+            parse_grid_cells("0").err()
+        );
+    }
+
+    #[test]
+    fn should_parse_label_geometry() {
+        let (width, height) = parse_label_geometry("63.5x38.1").unwrap();
+        assert!((width - 180.0).abs() < 0.01 && (height - 108.0).abs() < 0.01);
+        assert_eq!(Ok((0.0, 0.0)), parse_label_geometry("0x0mm"));
+        assert!(parse_label_geometry("63.5").is_err());
+    }
+
+    #[test]
+    fn should_parse_shard() {
+        assert_eq!(Ok(2), parse_shard("2"));
+        assert_eq!(
+            Some("Shard must be a number from 1 to 8.".to_string()),
+            parse_shard("0").err()
+        );
+        assert!(parse_shard("9").is_err());
+    }
+
     #[test]
     fn should_parse_chunk_size() {
         let res = parse_chunk_size("10").unwrap();
         assert_eq!(10, res);
     }
 
+    #[test]
+    fn should_parse_threads() {
+        assert_eq!(Ok(2), parse_threads("2"));
+        assert!(parse_threads("0").is_err());
+        assert!(parse_threads("all").is_err());
+    }
+
+    #[test]
+    fn should_parse_parallel_files() {
+        assert_eq!(Ok(4), parse_parallel_files("4"));
+        assert!(parse_parallel_files("0").is_err());
+    }
+
+    #[test]
+    fn should_parse_progress_fd() {
+        assert_eq!(Ok(3), parse_progress_fd("3"));
+        assert_eq!(
+            Some("Progress events need a file descriptor from 1 to 2147483647.".to_string()),
+            parse_progress_fd("0").err()
+        );
+        assert!(parse_progress_fd("4294967295").is_err());
+        assert!(parse_progress_fd("-1").is_err());
+    }
+
+    #[test]
+    fn should_parse_record_timeout() {
+        assert_eq!(Ok(Duration::from_millis(2500)), parse_record_timeout("2.5"));
+        assert!(parse_record_timeout("0").is_err());
+        assert!(parse_record_timeout("-1").is_err());
+        assert!(parse_record_timeout("soon").is_err());
+    }
+
     macro_rules! parse_qr_mask_tests {
         ($($name:ident: $value:expr,)*) => {
         $(
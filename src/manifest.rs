@@ -0,0 +1,299 @@
+use crate::{capacity, minisign};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    path::Path,
+    time::SystemTime,
+};
+
+/// A single line of the run manifest, describing how one input record was turned into an output
+/// file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub input: String,
+    pub row: u64,
+    pub name: String,
+    pub output: String,
+    pub version: u8,
+    pub ecc: String,
+    pub mask: u8,
+    pub mode: String,
+    pub modules: i32,
+    pub data_codewords: usize,
+    pub capacity_codewords: usize,
+    /// The hash of the data and settings the QR Code was generated from, missing from older manifests.
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// The name from the input when the output file was given another name.
+    #[serde(default)]
+    pub renamed_from: Option<String>,
+    /// The id of the run that generated the output, missing from older manifests.
+    #[serde(default)]
+    pub run_id: Option<String>,
+}
+
+impl ManifestEntry {
+    pub fn new(
+        input: &Path,
+        row: u64,
+        name: &str,
+        output: &Path,
+        qr_code: &qrcodegen::QrCode,
+        segments: &[qrcodegen::QrSegment],
+        hash: Option<&str>,
+        renamed_from: Option<&str>,
+        run_id: Option<&str>,
+    ) -> Self {
+        let utilization = capacity::Utilization::new(qr_code, segments);
+
+        ManifestEntry {
+            input: input.display().to_string(),
+            row,
+            name: name.to_string(),
+            output: output.display().to_string(),
+            version: qr_code.version().value(),
+            ecc: ecc_name(qr_code.error_correction_level()).to_string(),
+            mask: qr_code.mask().value(),
+            mode: segments
+                .iter()
+                .map(|s| mode_name(s.mode()))
+                .collect::<Vec<_>>()
+                .join("+"),
+            modules: qr_code.size(),
+            data_codewords: utilization.used,
+            capacity_codewords: utilization.capacity,
+            hash: hash.map(str::to_string),
+            renamed_from: renamed_from.map(str::to_string),
+            run_id: run_id.map(str::to_string),
+        }
+    }
+}
+
+/// Write the manifest to the given path.  A path ending in .json is written as a JSON array, anything
+/// else is written as CSV with a header row.  When appending, the entries go after those of the earlier runs already
+/// in the manifest.
+pub fn write(
+    path: &Path,
+    entries: &mut [ManifestEntry],
+    append: bool,
+) -> Result<(), Box<dyn Error>> {
+    // Records are processed in parallel so put them back in input order.
+    entries.sort_by(|a, b| a.input.cmp(&b.input).then(a.row.cmp(&b.row)));
+
+    // The whole manifest is written again, rather than added to the end, so a JSON array stays whole and a CSV
+    // manifest from an older version gains the new columns.
+    let earlier = if append && path.exists() {
+        read(path)?
+    } else {
+        Vec::new()
+    };
+    let file = File::create(path)?;
+
+    if is_json(path) {
+        serde_json::to_writer_pretty(
+            file,
+            &earlier.iter().chain(entries.iter()).collect::<Vec<_>>(),
+        )?;
+    } else {
+        let mut writer = csv::Writer::from_writer(file);
+        for entry in earlier.iter().chain(entries.iter()) {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+// Read a manifest written by write, as JSON if the path ends in .json and CSV otherwise.
+fn read(path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    Ok(if is_json(path) {
+        serde_json::from_reader(file)?
+    } else {
+        csv::Reader::from_reader(file)
+            .into_deserialize()
+            .collect::<Result<_, _>>()?
+    })
+}
+
+/// Sign the manifest written to the path, writing the signature beside it with .minisig added to the name the same
+/// as minisign, so it can be checked with `minisign -Vm manifest.csv -p qrgen.pub`.
+pub fn sign(path: &Path, key: &minisign::SecretKey) -> Result<(), Box<dyn Error>> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let signature = key.sign(
+        &fs::read(path)?,
+        &format!("timestamp:{}\tfile:{}\thashed", timestamp, file_name),
+    );
+
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".minisig");
+    fs::write(signature_path, signature)?;
+    Ok(())
+}
+
+/// The entries of an earlier run's manifest by record name, to find the records that are new or changed since.  A
+/// record written in more than one format has an entry for each.  When runs were appended to the manifest, only the
+/// entries from the last run to generate the record are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Previous(HashMap<String, Vec<ManifestEntry>>);
+
+impl Previous {
+    /// Read a manifest written by write, as JSON if the path ends in .json and CSV otherwise.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut previous: HashMap<String, Vec<ManifestEntry>> = HashMap::new();
+        for entry in read(path)? {
+            let entries = previous.entry(entry.name.clone()).or_default();
+            if entries
+                .first()
+                .is_some_and(|first| first.run_id != entry.run_id)
+            {
+                entries.clear();
+            }
+            entries.push(entry);
+        }
+        Ok(Previous(previous))
+    }
+
+    /// The entries for the name, one for each format, or none if the name isn't in the manifest.
+    pub fn get(&self, name: &str) -> &[ManifestEntry] {
+        self.0.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.0.values().flatten()
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+pub fn ecc_name(ecc: qrcodegen::QrCodeEcc) -> &'static str {
+    match ecc {
+        qrcodegen::QrCodeEcc::High => "High",
+        qrcodegen::QrCodeEcc::Low => "Low",
+        qrcodegen::QrCodeEcc::Quartile => "Quartile",
+        qrcodegen::QrCodeEcc::Medium => "Medium",
+    }
+}
+
+pub fn mode_name(mode: qrcodegen::QrSegmentMode) -> &'static str {
+    match mode {
+        qrcodegen::QrSegmentMode::Alphanumeric => "Alphanumeric",
+        qrcodegen::QrSegmentMode::Byte => "Byte",
+        qrcodegen::QrSegmentMode::Eci => "Eci",
+        qrcodegen::QrSegmentMode::Kanji => "Kanji",
+        qrcodegen::QrSegmentMode::Numeric => "Numeric",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(input: &str, row: u64) -> ManifestEntry {
+        let segments = qrcodegen::QrSegment::make_segments("12345");
+        let qr_code =
+            qrcodegen::QrCode::encode_segments(&segments, qrcodegen::QrCodeEcc::Low).unwrap();
+
+        ManifestEntry::new(
+            &PathBuf::from(input),
+            row,
+            "name",
+            &PathBuf::from("name.svg"),
+            &qr_code,
+            &segments,
+            Some("abc"),
+            None,
+            Some("1a2b3c4d"),
+        )
+    }
+
+    #[test]
+    fn should_describe_qr_code() {
+        let entry = entry("in.csv", 1);
+
+        assert_eq!(1, entry.version);
+        assert_eq!("High", entry.ecc);
+        assert_eq!("Numeric", entry.mode);
+        assert_eq!(21, entry.modules);
+        assert_eq!(4, entry.data_codewords);
+        assert_eq!(9, entry.capacity_codewords);
+        assert_eq!("name.svg", entry.output);
+    }
+
+    #[test]
+    fn should_detect_json_extension() {
+        assert!(is_json(&PathBuf::from("manifest.JSON")));
+        assert!(!is_json(&PathBuf::from("manifest.csv")));
+        assert!(!is_json(&PathBuf::from("manifest")));
+    }
+
+    #[test]
+    fn should_write_csv_in_input_order() {
+        let path = std::env::temp_dir().join("qrgen_manifest_test.csv");
+        let mut entries = vec![entry("b.csv", 1), entry("a.csv", 2), entry("a.csv", 1)];
+
+        write(&path, &mut entries, false).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut lines = written.lines();
+        assert_eq!(
+            Some(
+                "input,row,name,output,version,ecc,mask,mode,modules,data_codewords,capacity_codewords,hash,renamed_from,\
+                 run_id"
+            ),
+            lines.next()
+        );
+        assert!(lines.next().unwrap().starts_with("a.csv,1,"));
+        assert!(lines.next().unwrap().starts_with("a.csv,2,"));
+        assert!(lines.next().unwrap().starts_with("b.csv,1,"));
+    }
+
+    #[test]
+    fn should_read_written_manifest() {
+        let path = std::env::temp_dir().join("qrgen_should_read_written_manifest.csv");
+        let mut entries = vec![entry("a.csv", 1)];
+
+        write(&path, &mut entries, false).unwrap();
+        let previous = Previous::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&entries[..], previous.get("name"));
+        assert!(previous.get("other").is_empty());
+    }
+
+    #[test]
+    fn should_append_runs_and_read_the_last() {
+        let path = std::env::temp_dir().join("qrgen_should_append_runs_and_read_the_last.json");
+        let _ = std::fs::remove_file(&path);
+        let later = ManifestEntry {
+            run_id: Some("5e6f7a8b".into()),
+            hash: Some("def".into()),
+            ..entry("a.csv", 1)
+        };
+
+        write(&path, &mut [entry("a.csv", 1)], true).unwrap();
+        write(&path, &mut [later.clone()], true).unwrap();
+        let entries = read(&path).unwrap();
+        let previous = Previous::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(vec![entry("a.csv", 1), later.clone()], entries);
+        assert_eq!(&[later], previous.get("name"));
+    }
+}
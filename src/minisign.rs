@@ -0,0 +1,208 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2::{digest::consts::U32, Blake2b, Blake2b512, Digest};
+use ed25519_dalek::{Signer, SigningKey};
+use std::{fmt, fs, path::Path};
+
+// The algorithm of a key, Ed25519, and of a signature, Ed25519 over the BLAKE2b-512 hash of the file.
+const KEY_ALGORITHM: &[u8; 2] = b"Ed";
+const HASHED_ALGORITHM: &[u8; 2] = b"ED";
+const SCRYPT: &[u8; 2] = b"Sc";
+const NO_KDF: &[u8; 2] = b"\0\0";
+const BLAKE2B: &[u8; 2] = b"B2";
+
+// The layout of a decoded secret key: the algorithms, the scrypt salt and limits, then the key id, the key and its
+// checksum, which are encrypted together when the key has a password.
+const SALT: usize = 6;
+const OPS_LIMIT: usize = 38;
+const MEM_LIMIT: usize = 46;
+const KEY_ID: usize = 54;
+const SECRET: usize = 62;
+const CHECKSUM: usize = 126;
+const KEY_LENGTH: usize = 158;
+
+/// A minisign secret key, used to sign the manifest so anyone with the public key can check it came from us with
+/// `minisign -V`.
+#[derive(Clone)]
+pub struct SecretKey {
+    id: [u8; 8],
+    signing: SigningKey,
+}
+
+impl SecretKey {
+    /// Read a secret key file written by `minisign -G`, decrypting it with the password unless it was created
+    /// without one.
+    pub fn read(path: &Path, password: Option<&str>) -> Result<Self, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read the key {}: {}", path.display(), e))?;
+        SecretKey::parse(&src, password)
+            .map_err(|e| format!("Unable to read the key {}: {}", path.display(), e))
+    }
+
+    fn parse(src: &str, password: Option<&str>) -> Result<Self, String> {
+        let mut key = src
+            .lines()
+            .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .and_then(|line| STANDARD.decode(line.trim()).ok())
+            .filter(|key| key.len() == KEY_LENGTH && key.starts_with(KEY_ALGORITHM))
+            .ok_or("it isn't a minisign secret key")?;
+
+        match (&key[2..4], password) {
+            (kdf, _) if kdf == NO_KDF => (),
+            (kdf, Some(password)) if kdf == SCRYPT => decrypt(&mut key, password)?,
+            (kdf, None) if kdf == SCRYPT => return Err("it needs a password".into()),
+            _ => return Err("it uses an unknown key derivation".into()),
+        }
+
+        if &key[4..6] != BLAKE2B || key[CHECKSUM..] != checksum(&key)[..] {
+            return Err("the password is wrong or the key is damaged".into());
+        }
+
+        let mut id = [0; 8];
+        id.copy_from_slice(&key[KEY_ID..SECRET]);
+        let mut seed = [0; 32];
+        seed.copy_from_slice(&key[SECRET..SECRET + 32]);
+        Ok(SecretKey {
+            id,
+            signing: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    /// The contents of a .minisig file signing the data, with the trusted comment signed along with it.
+    pub fn sign(&self, data: &[u8], trusted_comment: &str) -> String {
+        let mut signature = HASHED_ALGORITHM.to_vec();
+        signature.extend_from_slice(&self.id);
+        signature.extend_from_slice(&self.signing.sign(&Blake2b512::digest(data)).to_bytes());
+
+        let mut global = signature[10..].to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+
+        format!(
+            "untrusted comment: signature from qrgen secret key\n{}\ntrusted comment: {}\n{}\n",
+            STANDARD.encode(&signature),
+            trusted_comment,
+            STANDARD.encode(self.signing.sign(&global).to_bytes())
+        )
+    }
+}
+
+// Keep the key out of logs.
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("id", &format!("{:016X}", u64::from_le_bytes(self.id)))
+            .finish_non_exhaustive()
+    }
+}
+
+// The key id, key and checksum are XORed with a stream derived from the password using scrypt, with N, r and p picked
+// from the limits the same way libsodium does.
+fn decrypt(key: &mut [u8], password: &str) -> Result<(), String> {
+    let limit = |at: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&key[at..at + 8]);
+        u64::from_le_bytes(bytes)
+    };
+    let ops_limit = limit(OPS_LIMIT).max(32768);
+    let mem_limit = limit(MEM_LIMIT);
+    let r = 8;
+
+    let (max_n, p) = if ops_limit < mem_limit / 32 {
+        (ops_limit / (r * 4), None)
+    } else {
+        (mem_limit / (r * 128), Some(ops_limit / 4))
+    };
+    let log_n = (1..63).find(|n| 1u64 << n > max_n / 2).unwrap_or(63);
+    let p = p.map_or(1, |rp| (rp >> log_n).min(0x3fff_ffff) / r).max(1);
+
+    let params = scrypt::Params::new(log_n, r as u32, p as u32, 64)
+        .map_err(|_| "its scrypt limits are out of range")?;
+    let mut stream = [0; KEY_LENGTH - KEY_ID];
+    scrypt::scrypt(
+        password.as_bytes(),
+        &key[SALT..OPS_LIMIT],
+        &params,
+        &mut stream,
+    )
+    .map_err(|_| "its scrypt limits are out of range")?;
+
+    for (byte, mask) in key[KEY_ID..].iter_mut().zip(stream.iter()) {
+        *byte ^= mask;
+    }
+    Ok(())
+}
+
+fn checksum(key: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2b::<U32>::new();
+    hasher.update(KEY_ALGORITHM);
+    hasher.update(&key[KEY_ID..CHECKSUM]);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A secret key file for the seed as minisign would write it, encrypted when there is a password.
+    fn key_file(seed: [u8; 32], password: Option<&str>) -> (String, String) {
+        let signing = SigningKey::from_bytes(&seed);
+        let id = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        let mut key = KEY_ALGORITHM.to_vec();
+        key.extend_from_slice(if password.is_some() { SCRYPT } else { NO_KDF });
+        key.extend_from_slice(BLAKE2B);
+        key.extend_from_slice(&[9; 32]);
+        key.extend_from_slice(&32768u64.to_le_bytes());
+        key.extend_from_slice(&(1u64 << 20).to_le_bytes());
+        key.extend_from_slice(&id);
+        key.extend_from_slice(&signing.to_keypair_bytes());
+        key.extend_from_slice(&checksum(&key));
+        if let Some(password) = password {
+            // XOR is its own inverse.
+            decrypt(&mut key, password).unwrap();
+        }
+
+        let mut public = KEY_ALGORITHM.to_vec();
+        public.extend_from_slice(&id);
+        public.extend_from_slice(signing.verifying_key().as_bytes());
+
+        (
+            format!(
+                "untrusted comment: minisign encrypted secret key\n{}\n",
+                STANDARD.encode(key)
+            ),
+            STANDARD.encode(public),
+        )
+    }
+
+    #[test]
+    fn should_sign_for_minisign() {
+        let (secret, public) = key_file([7; 32], None);
+        let key = SecretKey::parse(&secret, None).unwrap();
+
+        let signature = key.sign(b"input,row\n", "timestamp:0\tfile:manifest.csv\thashed");
+
+        let public = minisign_verify::PublicKey::from_base64(&public).unwrap();
+        let signature = minisign_verify::Signature::decode(&signature).unwrap();
+        assert_eq!(
+            "timestamp:0\tfile:manifest.csv\thashed",
+            signature.trusted_comment()
+        );
+        assert!(public.verify(b"input,row\n", &signature, false).is_ok());
+        assert!(public.verify(b"input,row\nx", &signature, false).is_err());
+    }
+
+    #[test]
+    fn should_decrypt_key_with_password() {
+        let (secret, _) = key_file([7; 32], Some("hunter2"));
+
+        assert!(SecretKey::parse(&secret, Some("hunter2")).is_ok());
+        assert_eq!(
+            Some("the password is wrong or the key is damaged".to_string()),
+            SecretKey::parse(&secret, Some("wrong")).err()
+        );
+        assert_eq!(
+            Some("it needs a password".to_string()),
+            SecretKey::parse(&secret, None).err()
+        );
+    }
+}
@@ -0,0 +1,1053 @@
+use crate::{crypto, jwt, template::Template};
+use std::fmt;
+
+/// A structured payload built from several fields of each row, found by header name or, without a header row, in
+/// order starting at the data column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PayloadType {
+    /// A network to join, WIFI:T:WPA;S:ssid;P:password;;, from ssid, password, security and hidden fields.
+    Wifi,
+    /// A location, geo:latitude,longitude, from latitude, longitude and altitude fields.
+    Geo,
+    /// A SEPA credit transfer following EPC069-12, from iban, bic, beneficiary, amount, reference, text and purpose
+    /// fields.
+    Epc,
+    /// A UPI payment link, upi://pay?pa=vpa&pn=payee, from vpa, payee, amount, note and reference fields.
+    Upi,
+    /// A TOTP authenticator enrolment, otpauth://totp/issuer:account?secret=..., from issuer, account, secret,
+    /// digits, period and algorithm fields.
+    Otpauth,
+    /// A BIP-21 payment request, bitcoin:address?amount=..., from address, amount, label and message fields.
+    Bitcoin,
+    /// An EIP-681 payment request, ethereum:address?value=..., from address and amount fields.
+    Ethereum,
+}
+
+/// A field of a payload type, the first name is used in messages and the rest are accepted in header rows.
+struct Field {
+    names: &'static [&'static str],
+    required: bool,
+}
+
+const WIFI_FIELDS: [Field; 4] = [
+    Field {
+        names: &["ssid", "network"],
+        required: true,
+    },
+    Field {
+        names: &["password", "pass", "key"],
+        required: false,
+    },
+    Field {
+        names: &["security", "auth", "encryption"],
+        required: false,
+    },
+    Field {
+        names: &["hidden"],
+        required: false,
+    },
+];
+
+const GEO_FIELDS: [Field; 3] = [
+    Field {
+        names: &["latitude", "lat"],
+        required: true,
+    },
+    Field {
+        names: &["longitude", "lon", "lng", "long"],
+        required: true,
+    },
+    Field {
+        names: &["altitude", "alt", "elevation"],
+        required: false,
+    },
+];
+
+// The beneficiary isn't found by a column called name, as that normally holds the file name.
+const EPC_FIELDS: [Field; 7] = [
+    Field {
+        names: &["iban"],
+        required: true,
+    },
+    Field {
+        names: &["bic", "swift"],
+        required: false,
+    },
+    Field {
+        names: &["beneficiary", "beneficiaryname", "payee", "recipient"],
+        required: true,
+    },
+    Field {
+        names: &["amount"],
+        required: false,
+    },
+    Field {
+        names: &["reference", "creditorreference"],
+        required: false,
+    },
+    Field {
+        names: &["text", "remittance", "remittanceinformation"],
+        required: false,
+    },
+    Field {
+        names: &["purpose"],
+        required: false,
+    },
+];
+
+// The largest EPC payload in bytes allowed by EPC069-12.
+const EPC_MAX_LENGTH: usize = 331;
+
+const UPI_FIELDS: [Field; 5] = [
+    Field {
+        names: &["vpa", "upiid", "upi", "pa"],
+        required: true,
+    },
+    Field {
+        names: &["payee", "payeename", "pn", "beneficiary"],
+        required: true,
+    },
+    Field {
+        names: &["amount", "am"],
+        required: false,
+    },
+    Field {
+        names: &["note", "tn", "text"],
+        required: false,
+    },
+    Field {
+        names: &["reference", "tr", "transactionreference"],
+        required: false,
+    },
+];
+
+const OTPAUTH_FIELDS: [Field; 6] = [
+    Field {
+        names: &["issuer"],
+        required: false,
+    },
+    Field {
+        names: &["account", "accountname", "user", "username", "email"],
+        required: true,
+    },
+    Field {
+        names: &["secret", "key"],
+        required: true,
+    },
+    Field {
+        names: &["digits"],
+        required: false,
+    },
+    Field {
+        names: &["period", "interval"],
+        required: false,
+    },
+    Field {
+        names: &["algorithm"],
+        required: false,
+    },
+];
+
+const BITCOIN_FIELDS: [Field; 4] = [
+    Field {
+        names: &["address", "wallet"],
+        required: true,
+    },
+    Field {
+        names: &["amount"],
+        required: false,
+    },
+    Field {
+        names: &["label"],
+        required: false,
+    },
+    Field {
+        names: &["message"],
+        required: false,
+    },
+];
+
+const ETHEREUM_FIELDS: [Field; 2] = [
+    Field {
+        names: &["address", "wallet"],
+        required: true,
+    },
+    Field {
+        names: &["amount"],
+        required: false,
+    },
+];
+
+// The number of decimal places of a bitcoin, in satoshis, and of an ether, in wei.
+const BITCOIN_DECIMALS: usize = 8;
+const ETHER_DECIMALS: usize = 18;
+
+impl PayloadType {
+    fn fields(self) -> &'static [Field] {
+        match self {
+            PayloadType::Wifi => &WIFI_FIELDS,
+            PayloadType::Geo => &GEO_FIELDS,
+            PayloadType::Epc => &EPC_FIELDS,
+            PayloadType::Upi => &UPI_FIELDS,
+            PayloadType::Otpauth => &OTPAUTH_FIELDS,
+            PayloadType::Bitcoin => &BITCOIN_FIELDS,
+            PayloadType::Ethereum => &ETHEREUM_FIELDS,
+        }
+    }
+
+    /// Find the column of each field, by name in the header row if there is one, otherwise in order starting at
+    /// the given column.
+    pub fn resolve(
+        self,
+        headers: Option<&csv::StringRecord>,
+        first: usize,
+    ) -> Result<ResolvedPayload, String> {
+        let columns = match headers {
+            Some(headers) => self
+                .fields()
+                .iter()
+                .map(|field| {
+                    let column = headers
+                        .iter()
+                        .position(|h| field.names.contains(&normalize(h).as_str()));
+                    match column {
+                        None if field.required => Err(format!(
+                            "The {} payload needs a column named {} in the header row.",
+                            self, field.names[0]
+                        )),
+                        column => Ok(column),
+                    }
+                })
+                .collect::<Result<_, _>>()?,
+            None => (first..first + self.fields().len()).map(Some).collect(),
+        };
+
+        Ok(ResolvedPayload {
+            payload: self,
+            columns,
+        })
+    }
+
+    /// Build the payload from the value of each field, in the order of the fields, None when it is missing or empty.
+    fn build(self, values: &[Option<&str>]) -> Result<String, String> {
+        match self {
+            PayloadType::Wifi => wifi(values),
+            PayloadType::Geo => geo(values),
+            PayloadType::Epc => epc(values),
+            PayloadType::Upi => upi(values),
+            PayloadType::Otpauth => otpauth(values),
+            PayloadType::Bitcoin => bitcoin(values),
+            PayloadType::Ethereum => ethereum(values),
+        }
+    }
+}
+
+impl fmt::Display for PayloadType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadType::Wifi => write!(f, "wifi"),
+            PayloadType::Geo => write!(f, "geo"),
+            PayloadType::Epc => write!(f, "epc"),
+            PayloadType::Upi => write!(f, "upi"),
+            PayloadType::Otpauth => write!(f, "otpauth"),
+            PayloadType::Bitcoin => write!(f, "bitcoin"),
+            PayloadType::Ethereum => write!(f, "ethereum"),
+        }
+    }
+}
+
+/// Lower case with spaces, underscores and dashes removed, so Wi-Fi Password matches password.
+fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// How the data of each record is made from the fields of a row.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DataSource {
+    /// The value of the data column.
+    #[default]
+    Column,
+    Template(Template),
+    Build(PayloadType),
+    /// A JWT signed with the HMAC key, holding claims taken from columns.
+    Jwt(jwt::Claims),
+}
+
+impl fmt::Display for DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataSource::Column => write!(f, "column"),
+            DataSource::Template(template) => write!(f, "template {}", template),
+            DataSource::Build(payload) => write!(f, "{} payload", payload),
+            DataSource::Jwt(claims) => write!(f, "JWT {}", claims),
+        }
+    }
+}
+
+/// A payload type whose fields have been found in a particular file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPayload {
+    payload: PayloadType,
+    columns: Vec<Option<usize>>,
+}
+
+impl ResolvedPayload {
+    pub fn render(&self, row: &csv::StringRecord) -> Result<String, String> {
+        let values: Vec<Option<&str>> = self
+            .columns
+            .iter()
+            .map(|column| column.and_then(|c| row.get(c)).filter(|v| !v.is_empty()))
+            .collect();
+
+        self.payload.build(&values)
+    }
+}
+
+fn wifi(values: &[Option<&str>]) -> Result<String, String> {
+    let (ssid, password, security, hidden) = (values[0], values[1], values[2], values[3]);
+    let ssid = ssid.ok_or("has no ssid")?;
+
+    let security = match security.map(str::to_uppercase).as_deref() {
+        None if password.is_some() => "WPA",
+        None | Some("NOPASS") | Some("NONE") | Some("OPEN") => "nopass",
+        Some("WPA") | Some("WPA2") | Some("WPA3") | Some("WPA/WPA2") => "WPA",
+        Some("WEP") => "WEP",
+        Some(other) => {
+            return Err(format!(
+                "has security {}, which must be WPA, WEP or nopass",
+                other
+            ))
+        }
+    };
+
+    let mut wifi = format!("WIFI:T:{};S:{};", security, escape_wifi(ssid));
+
+    if security != "nopass" {
+        let password = password.ok_or("has no password")?;
+        wifi.push_str(&format!("P:{};", escape_wifi(password)));
+    }
+
+    if parse_bool("hidden", hidden)? {
+        wifi.push_str("H:true;");
+    }
+
+    wifi.push(';');
+    Ok(wifi)
+}
+
+/// Backslash the characters that have a meaning in WIFI: strings.
+fn escape_wifi(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | '"' | ':') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn geo(values: &[Option<&str>]) -> Result<String, String> {
+    let latitude = parse_number("latitude", values[0], -90.0..=90.0)?;
+    let longitude = parse_number("longitude", values[1], -180.0..=180.0)?;
+
+    match values[2] {
+        Some(altitude) => {
+            parse_number("altitude", Some(altitude), f64::MIN..=f64::MAX)?;
+            Ok(format!("geo:{},{},{}", latitude, longitude, altitude))
+        }
+        None => Ok(format!("geo:{},{}", latitude, longitude)),
+    }
+}
+
+fn epc(values: &[Option<&str>]) -> Result<String, String> {
+    let iban = parse_iban(values[0].ok_or("has no iban")?)?;
+    let bic = values[1].map(parse_bic).transpose()?;
+    let beneficiary = check_length("beneficiary", values[2], 70)?.ok_or("has no beneficiary")?;
+    let amount = values[3]
+        .map(|amount| parse_amount(amount, "euros").map(|amount| format!("EUR{}", amount)))
+        .transpose()?;
+    let reference = check_length("reference", values[4], 35)?;
+    let text = check_length("text", values[5], 140)?;
+    let purpose = values[6].map(parse_purpose).transpose()?;
+
+    if reference.is_some() && text.is_some() {
+        return Err(String::from(
+            "has both a reference and text, only one can be given",
+        ));
+    }
+
+    let mut lines = vec![
+        "BCD".to_string(),
+        "002".to_string(),
+        "1".to_string(),
+        "SCT".to_string(),
+        bic.unwrap_or_default(),
+        beneficiary.to_string(),
+        iban,
+        amount.unwrap_or_default(),
+        purpose.unwrap_or_default(),
+        reference.unwrap_or_default().to_string(),
+        text.unwrap_or_default().to_string(),
+    ];
+
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    let epc = lines.join("\n");
+    if epc.len() > EPC_MAX_LENGTH {
+        return Err(format!(
+            "makes an EPC payload of {} bytes, which is more than {}",
+            epc.len(),
+            EPC_MAX_LENGTH
+        ));
+    }
+
+    Ok(epc)
+}
+
+fn upi(values: &[Option<&str>]) -> Result<String, String> {
+    let vpa = parse_vpa(values[0].ok_or("has no vpa")?)?;
+    let payee = values[1].ok_or("has no payee")?;
+
+    // The address only holds characters that are safe in a query, and some apps don't decode an escaped @.
+    let mut upi = format!("upi://pay?pa={}&pn={}", vpa, percent_encode(payee));
+
+    if let Some(amount) = values[2] {
+        upi.push_str(&format!("&am={}", parse_amount(amount, "rupees")?));
+    }
+    if let Some(note) = values[3] {
+        upi.push_str(&format!("&tn={}", percent_encode(note)));
+    }
+    if let Some(reference) = values[4] {
+        upi.push_str(&format!("&tr={}", percent_encode(reference)));
+    }
+
+    upi.push_str("&cu=INR");
+    Ok(upi)
+}
+
+fn otpauth(values: &[Option<&str>]) -> Result<String, String> {
+    let issuer = values[0];
+    let account = values[1].ok_or("has no account")?;
+    let secret = parse_base32_secret(values[2].ok_or("has no secret")?)?;
+
+    if issuer.is_some_and(|i| i.contains(':')) || account.contains(':') {
+        return Err(String::from(
+            "has a colon in the issuer or account, which can't be used in the label",
+        ));
+    }
+
+    let mut otpauth = match issuer {
+        Some(issuer) => format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}",
+            percent_encode(issuer),
+            percent_encode(account),
+            secret,
+            percent_encode(issuer)
+        ),
+        None => format!(
+            "otpauth://totp/{}?secret={}",
+            percent_encode(account),
+            secret
+        ),
+    };
+
+    if let Some(algorithm) = values[5] {
+        match algorithm.to_uppercase().replace('-', "").as_str() {
+            algorithm @ ("SHA1" | "SHA256" | "SHA512") => {
+                otpauth.push_str(&format!("&algorithm={}", algorithm))
+            }
+            _ => {
+                return Err(format!(
+                    "has algorithm {}, which must be SHA1, SHA256 or SHA512",
+                    algorithm
+                ))
+            }
+        }
+    }
+
+    if let Some(digits) = values[3] {
+        match digits {
+            "6" | "8" => otpauth.push_str(&format!("&digits={}", digits)),
+            _ => return Err(format!("has digits {}, which must be 6 or 8", digits)),
+        }
+    }
+
+    if let Some(period) = values[4] {
+        match period.parse::<u32>() {
+            Ok(period) if period > 0 => otpauth.push_str(&format!("&period={}", period)),
+            _ => {
+                return Err(format!(
+                    "has period {}, which must be a number of seconds",
+                    period
+                ))
+            }
+        }
+    }
+
+    Ok(otpauth)
+}
+
+/// Check the secret is base32, removing spaces and padding that authenticator apps don't expect.
+fn parse_base32_secret(value: &str) -> Result<String, String> {
+    let secret: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>()
+        .to_uppercase();
+
+    if !secret.is_empty() && secret.chars().all(|c| matches!(c, 'A'..='Z' | '2'..='7')) {
+        Ok(secret)
+    } else {
+        Err(String::from("has a secret that is not base32"))
+    }
+}
+
+fn bitcoin(values: &[Option<&str>]) -> Result<String, String> {
+    let address = values[0].ok_or("has no address")?;
+    if !crypto::is_bitcoin_address(address) {
+        return Err(format!(
+            "has address {}, which is not a valid Bitcoin address",
+            address
+        ));
+    }
+
+    let mut parameters = Vec::new();
+
+    if let Some(amount) = values[1] {
+        let satoshis = crypto::parse_units(amount, BITCOIN_DECIMALS)
+            .filter(|&s| s <= 21_000_000 * 10u128.pow(BITCOIN_DECIMALS as u32))
+            .ok_or_else(|| {
+                format!(
+                    "has amount {}, which must be a number of bitcoin with up to 8 decimal places",
+                    amount
+                )
+            })?;
+        parameters.push(format!(
+            "amount={}",
+            format_units(satoshis, BITCOIN_DECIMALS)
+        ));
+    }
+    if let Some(label) = values[2] {
+        parameters.push(format!("label={}", percent_encode(label)));
+    }
+    if let Some(message) = values[3] {
+        parameters.push(format!("message={}", percent_encode(message)));
+    }
+
+    Ok(with_query(format!("bitcoin:{}", address), &parameters))
+}
+
+fn ethereum(values: &[Option<&str>]) -> Result<String, String> {
+    let address = values[0].ok_or("has no address")?;
+    if !crypto::is_ethereum_address(address) {
+        return Err(format!(
+            "has address {}, which is not a valid Ethereum address",
+            address
+        ));
+    }
+
+    let mut parameters = Vec::new();
+
+    if let Some(amount) = values[1] {
+        let wei = crypto::parse_units(amount, ETHER_DECIMALS).ok_or_else(|| {
+            format!(
+                "has amount {}, which must be a number of ether with up to 18 decimal places",
+                amount
+            )
+        })?;
+        parameters.push(format!("value={}", wei));
+    }
+
+    Ok(with_query(format!("ethereum:{}", address), &parameters))
+}
+
+/// Format an amount in its smallest units as a decimal, without trailing zeros.
+fn format_units(units: u128, decimals: usize) -> String {
+    let scale = 10u128.pow(decimals as u32);
+    let fraction = format!("{:0width$}", units % scale, width = decimals);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        (units / scale).to_string()
+    } else {
+        format!("{}.{}", units / scale, fraction)
+    }
+}
+
+fn with_query(uri: String, parameters: &[String]) -> String {
+    if parameters.is_empty() {
+        uri
+    } else {
+        format!("{}?{}", uri, parameters.join("&"))
+    }
+}
+
+/// Check the virtual payment address is a handle and a provider, e.g. shop@okaxis.
+fn parse_vpa(value: &str) -> Result<String, String> {
+    let valid = match value.split_once('@') {
+        Some((handle, provider)) => {
+            (2..=256).contains(&handle.len())
+                && handle
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+                && (2..=64).contains(&provider.len())
+                && provider.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => false,
+    };
+
+    if valid {
+        Ok(value.to_lowercase())
+    } else {
+        Err(format!(
+            "has vpa {}, which is not a valid UPI address",
+            value
+        ))
+    }
+}
+
+/// Encode everything but unreserved characters for use in a URI query.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(b));
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Remove spaces from the IBAN and check its length and check digits.
+fn parse_iban(value: &str) -> Result<String, String> {
+    let iban: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let invalid = || format!("has iban {}, which is not a valid IBAN", value);
+
+    if !(15..=34).contains(&iban.len())
+        || !iban.chars().all(|c| c.is_ascii_alphanumeric())
+        || !iban[..2].chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return Err(invalid());
+    }
+
+    // Move the country and check digits to the end, turn letters into numbers and check the remainder is 1.
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .fold(0u32, |remainder, c| {
+            let digit = c.to_digit(36).unwrap();
+            if digit < 10 {
+                (remainder * 10 + digit) % 97
+            } else {
+                (remainder * 100 + digit) % 97
+            }
+        });
+
+    if remainder == 1 {
+        Ok(iban)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Check the BIC is 8 or 11 characters, four letters for the bank, two for the country and then letters or digits.
+fn parse_bic(value: &str) -> Result<String, String> {
+    let bic = value.to_uppercase();
+    let valid = bic.is_ascii()
+        && (bic.len() == 8 || bic.len() == 11)
+        && bic[..6].chars().all(|c| c.is_ascii_alphabetic())
+        && bic[6..].chars().all(|c| c.is_ascii_alphanumeric());
+
+    if valid {
+        Ok(bic)
+    } else {
+        Err(format!("has bic {}, which is not a valid BIC", value))
+    }
+}
+
+/// Check the amount is between 0.01 and 999999999.99 with at most two decimal places, formatting it with two.
+fn parse_amount(value: &str, currency: &str) -> Result<String, String> {
+    let invalid = || {
+        format!(
+            "has amount {}, which must be between 0.01 and 999999999.99 {}",
+            value, currency
+        )
+    };
+
+    let (units, cents) = value.split_once('.').unwrap_or((value, ""));
+    if units.is_empty()
+        || units.len() > 9
+        || cents.len() > 2
+        || !units
+            .chars()
+            .chain(cents.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let units: u64 = units.parse().map_err(|_| invalid())?;
+    let cents: u64 = format!("{:0<2}", cents).parse().map_err(|_| invalid())?;
+    if units == 0 && cents == 0 {
+        return Err(invalid());
+    }
+
+    Ok(format!("{}.{:02}", units, cents))
+}
+
+/// Check the purpose is a four letter ISO 20022 purpose code, e.g. CHAR for a charitable payment.
+fn parse_purpose(value: &str) -> Result<String, String> {
+    let purpose = value.to_uppercase();
+
+    if purpose.len() == 4 && purpose.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(purpose)
+    } else {
+        Err(format!(
+            "has purpose {}, which must be a four letter code",
+            value
+        ))
+    }
+}
+
+/// Check an optional field is no longer than the given number of characters.
+fn check_length<'a>(
+    field: &str,
+    value: Option<&'a str>,
+    max: usize,
+) -> Result<Option<&'a str>, String> {
+    match value {
+        Some(value) if value.chars().count() > max => Err(format!(
+            "has a {} of {} characters, which is more than {}",
+            field,
+            value.chars().count(),
+            max
+        )),
+        value => Ok(value),
+    }
+}
+
+/// Check the field is a plain decimal number in the range, keeping it as written so no precision is lost or added.
+/// Exponents, a leading + and the like parse as numbers but aren't allowed in a geo: URI, so they're refused.
+fn parse_number<'a>(
+    field: &str,
+    value: Option<&'a str>,
+    range: std::ops::RangeInclusive<f64>,
+) -> Result<&'a str, String> {
+    let value = value.ok_or_else(|| format!("has no {}", field))?;
+
+    match value.parse::<f64>() {
+        Ok(number) if is_decimal(value) && range.contains(&number) => Ok(value),
+        Ok(_) if is_decimal(value) => Err(format!(
+            "has {} {}, which must be between {} and {}",
+            field,
+            value,
+            range.start(),
+            range.end()
+        )),
+        _ => Err(format!(
+            "has {} {}, which must be a decimal number, e.g. -12.5",
+            field, value
+        )),
+    }
+}
+
+/// Digits with an optional minus sign and decimal places, the only numbers RFC 5870 allows.
+fn is_decimal(value: &str) -> bool {
+    value
+        .strip_prefix('-')
+        .unwrap_or(value)
+        .splitn(2, '.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn parse_bool(field: &str, value: Option<&str>) -> Result<bool, String> {
+    match value.map(str::to_lowercase).as_deref() {
+        None | Some("false") | Some("no") | Some("n") | Some("0") => Ok(false),
+        Some("true") | Some("yes") | Some("y") | Some("1") => Ok(true),
+        Some(other) => Err(format!(
+            "has {} {}, which must be true or false",
+            field, other
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(fields: &[&str]) -> csv::StringRecord {
+        csv::StringRecord::from(fields.to_vec())
+    }
+
+    #[test]
+    fn should_build_wifi_from_named_columns() {
+        let headers = row(&["name", "Hidden", "SSID", "Password", "Security"]);
+        let payload = PayloadType::Wifi.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok("WIFI:T:WPA;S:Cafe\\;Guest;P:p\\:ss\\\\w\\,d;H:true;;".to_string()),
+            payload.render(&row(&["a", "yes", "Cafe;Guest", "p:ss\\w,d", "wpa2"]))
+        );
+        assert_eq!(
+            Ok("WIFI:T:nopass;S:Open;;".to_string()),
+            payload.render(&row(&["b", "", "Open", "", ""]))
+        );
+    }
+
+    #[test]
+    fn should_build_wifi_from_positions() {
+        let payload = PayloadType::Wifi.resolve(None, 1).unwrap();
+
+        assert_eq!(
+            Ok("WIFI:T:WEP;S:Home;P:secret;;".to_string()),
+            payload.render(&row(&["a", "Home", "secret", "WEP"]))
+        );
+    }
+
+    #[test]
+    fn should_build_geo() {
+        let headers = row(&["name", "Lng", "Lat", "Elevation"]);
+        let payload = PayloadType::Geo.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok("geo:51.5007,-0.1246,96".to_string()),
+            payload.render(&row(&["ben", "-0.1246", "51.5007", "96"]))
+        );
+        assert_eq!(
+            Ok("geo:-33.8568,151.2153".to_string()),
+            payload.render(&row(&["opera", "151.2153", "-33.8568"]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_geo() {
+        let payload = PayloadType::Geo.resolve(None, 0).unwrap();
+
+        assert_eq!(
+            Err("has latitude 91, which must be between -90 and 90".to_string()),
+            payload.render(&row(&["91", "0"]))
+        );
+        assert!(payload.render(&row(&["0", "-180.5"])).is_err());
+        assert!(payload.render(&row(&["0", "east"])).is_err());
+        assert!(payload.render(&row(&["0", "0", "high"])).is_err());
+        assert_eq!(
+            Err("has altitude 1e3, which must be a decimal number, e.g. -12.5".to_string()),
+            payload.render(&row(&["0", "0", "1e3"]))
+        );
+        assert!(payload.render(&row(&["+10", "0"])).is_err());
+        assert!(payload.render(&row(&["0", ".5"])).is_err());
+        assert!(payload.render(&row(&["0", "0", "inf"])).is_err());
+        assert!(payload.render(&row(&["0"])).is_err());
+        assert!(PayloadType::Geo
+            .resolve(Some(&row(&["lat", "name"])), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn should_build_epc() {
+        let headers = row(&["name", "IBAN", "BIC", "Beneficiary", "Amount", "Text"]);
+        let payload = PayloadType::Epc.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok("BCD\n002\n1\nSCT\nBHBLDEHHXXX\nFranz Mustermänn\nDE71110220330123456789\nEUR12.30\n\n\nInvoice 42".to_string()),
+            payload.render(&row(&[
+                "inv42",
+                "DE71 1102 2033 0123 4567 89",
+                "bhbldehhxxx",
+                "Franz Mustermänn",
+                "12.3",
+                "Invoice 42"
+            ]))
+        );
+        assert_eq!(
+            Ok("BCD\n002\n1\nSCT\n\nRed Cross\nDE71110220330123456789".to_string()),
+            payload.render(&row(&["donate", "DE71110220330123456789", "", "Red Cross"]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_epc() {
+        let payload = PayloadType::Epc.resolve(None, 0).unwrap();
+        let iban = "DE71110220330123456789";
+
+        assert!(payload
+            .render(&row(&["DE71110220330123456788", "", "A"]))
+            .is_err());
+        assert!(payload.render(&row(&[iban, "BHBL", "A"])).is_err());
+        assert!(payload.render(&row(&[iban, "ÄÄÄÄ", "A"])).is_err());
+        assert!(payload.render(&row(&[iban, "", ""])).is_err());
+        assert!(payload.render(&row(&[iban, "", &"A".repeat(71)])).is_err());
+        assert!(payload.render(&row(&[iban, "", "A", "0.00"])).is_err());
+        assert!(payload.render(&row(&[iban, "", "A", "1.234"])).is_err());
+        assert!(payload
+            .render(&row(&[iban, "", "A", "1000000000"]))
+            .is_err());
+        assert!(payload
+            .render(&row(&[iban, "", "A", "1", "RF18539007547034", "Invoice"]))
+            .is_err());
+        assert!(payload
+            .render(&row(&[iban, "", "A", "1", "", "", "GIFT1"]))
+            .is_err());
+    }
+
+    #[test]
+    fn should_build_upi() {
+        let headers = row(&["name", "VPA", "Payee Name", "Amount", "Note"]);
+        let payload = PayloadType::Upi.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok(
+                "upi://pay?pa=shop@okaxis&pn=Tea%20%26%20Co&am=250.50&tn=Invoice%20%2342&cu=INR"
+                    .to_string()
+            ),
+            payload.render(&row(&[
+                "inv42",
+                "Shop@okaxis",
+                "Tea & Co",
+                "250.5",
+                "Invoice #42"
+            ]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_upi() {
+        let payload = PayloadType::Upi.resolve(None, 0).unwrap();
+
+        assert!(payload.render(&row(&["shop", "Tea"])).is_err());
+        assert!(payload.render(&row(&["shop@ok axis", "Tea"])).is_err());
+        assert!(payload.render(&row(&["shop@okaxis"])).is_err());
+        assert!(payload.render(&row(&["shop@okaxis", "Tea", "-1"])).is_err());
+    }
+
+    #[test]
+    fn should_build_otpauth() {
+        let headers = row(&[
+            "name",
+            "Issuer",
+            "Account",
+            "Secret",
+            "Digits",
+            "Period",
+            "Algorithm",
+        ]);
+        let payload = PayloadType::Otpauth.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok("otpauth://totp/ACME%20Co:jo%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60".to_string()),
+            payload.render(&row(&[
+                "jo",
+                "ACME Co",
+                "jo@example.com",
+                "jbsw y3dp ehpk 3pxp",
+                "8",
+                "60",
+                "sha-256"
+            ]))
+        );
+        assert_eq!(
+            Ok("otpauth://totp/jo?secret=JBSWY3DPEHPK3PXP".to_string()),
+            payload.render(&row(&["jo", "", "jo", "JBSWY3DPEHPK3PXP===="]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_otpauth() {
+        let payload = PayloadType::Otpauth.resolve(None, 0).unwrap();
+        let secret = "JBSWY3DPEHPK3PXP";
+
+        assert!(payload
+            .render(&row(&["ACME", "jo", "JBSWY3DPEHPK3PX1"]))
+            .is_err());
+        assert!(payload.render(&row(&["ACME:Co", "jo", secret])).is_err());
+        assert!(payload.render(&row(&["ACME", "jo", secret, "7"])).is_err());
+        assert!(payload
+            .render(&row(&["ACME", "jo", secret, "6", "0"]))
+            .is_err());
+        assert!(payload
+            .render(&row(&["ACME", "jo", secret, "6", "30", "MD5"]))
+            .is_err());
+    }
+
+    #[test]
+    fn should_build_bitcoin() {
+        let headers = row(&["name", "Address", "Amount", "Label", "Message"]);
+        let payload = PayloadType::Bitcoin.resolve(Some(&headers), 1).unwrap();
+
+        assert_eq!(
+            Ok("bitcoin:1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2?amount=0.015&label=Luke-Jr&message=Donation%20for%20project%20xyz".to_string()),
+            payload.render(&row(&[
+                "donate",
+                "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+                "0.01500000",
+                "Luke-Jr",
+                "Donation for project xyz"
+            ]))
+        );
+        assert_eq!(
+            Ok("bitcoin:1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_string()),
+            payload.render(&row(&["donate", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"]))
+        );
+    }
+
+    #[test]
+    fn should_build_ethereum() {
+        let payload = PayloadType::Ethereum.resolve(None, 0).unwrap();
+
+        assert_eq!(
+            Ok(
+                "ethereum:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed?value=1500000000000000000"
+                    .to_string()
+            ),
+            payload.render(&row(&["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", "1.5"]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_crypto() {
+        let bitcoin = PayloadType::Bitcoin.resolve(None, 0).unwrap();
+        let ethereum = PayloadType::Ethereum.resolve(None, 0).unwrap();
+
+        assert!(bitcoin
+            .render(&row(&["1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"]))
+            .is_err());
+        assert!(bitcoin
+            .render(&row(&["1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", "0.000000001"]))
+            .is_err());
+        assert!(bitcoin
+            .render(&row(&["1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", "21000001"]))
+            .is_err());
+        assert!(ethereum.render(&row(&["0x1234"])).is_err());
+        assert!(ethereum
+            .render(&row(&["0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed", "-1"]))
+            .is_err());
+    }
+
+    #[test]
+    fn should_reject_invalid_wifi() {
+        let payload = PayloadType::Wifi.resolve(None, 0).unwrap();
+
+        assert!(payload.render(&row(&["", "secret"])).is_err());
+        assert!(payload.render(&row(&["Home", "", "WPA"])).is_err());
+        assert!(payload.render(&row(&["Home", "secret", "TKIP"])).is_err());
+        assert!(payload
+            .render(&row(&["Home", "secret", "WPA", "maybe"]))
+            .is_err());
+        assert_eq!(
+            Err("The wifi payload needs a column named ssid in the header row.".to_string()),
+            PayloadType::Wifi.resolve(Some(&row(&["name", "password"])), 1)
+        );
+    }
+}
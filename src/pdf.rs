@@ -0,0 +1,545 @@
+use crate::batch::{self, Code, Style};
+use crate::icc;
+use flate2::{write::ZlibEncoder, Compression};
+use pdf_writer::{
+    types::{FontFlags, OutputIntentSubtype},
+    writers::OutputIntent,
+    Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr,
+};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+use uuid::Uuid;
+use xmp_writer::{DateTime, Timezone, XmpWriter};
+
+/// The number of PDF points, 1/72 of an inch, in a millimetre.
+pub const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+// Room left clear around the QR Code on each page.
+const MARGIN: f32 = 15.0 * POINTS_PER_MM;
+const CAPTION_SIZE: f32 = 12.0;
+const FONT: Name = Name(b"F1");
+// The characters a font has widths for, those of WinAnsiEncoding that are shown.
+const FIRST_CHAR: u8 = 0x20;
+const LAST_CHAR: u8 = 0xFF;
+
+// The widths of the printable ASCII characters in Helvetica, in thousandths of the font size.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// The size of the pages of a PDF, in points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paper {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Paper {
+    /// Parse the name of a paper size, A3, A4, A5, Letter or Legal, or a width and height in millimetres, e.g.
+    /// 100x150.
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let (width, height) = match src.to_lowercase().as_str() {
+            "a3" => (297.0, 420.0),
+            "a4" => (210.0, 297.0),
+            "a5" => (148.0, 210.0),
+            "letter" => (215.9, 279.4),
+            "legal" => (215.9, 355.6),
+            other => parse_size(other)
+                .filter(|(width, height)| *width > 0.0 && *height > 0.0)
+                .map(|(width, height)| (width / POINTS_PER_MM, height / POINTS_PER_MM))
+                .ok_or_else(|| {
+                    String::from(
+                        "Paper must be A3, A4, A5, Letter, Legal or a width and height in millimetres, e.g. 100x150.",
+                    )
+                })?,
+        };
+
+        Ok(Paper {
+            width: width * POINTS_PER_MM,
+            height: height * POINTS_PER_MM,
+        })
+    }
+}
+
+impl Default for Paper {
+    fn default() -> Self {
+        Paper::parse("a4").unwrap()
+    }
+}
+
+/// How PDFs are written: the size of their pages, unless laid out for labels, whether as PDF/A-2b for archiving rather
+/// than plain PDF, and the TrueType font to embed for captions rather than Helvetica, which PDF/A needs as it can't
+/// refer to fonts the reader is expected to have.
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    pub paper: Paper,
+    pub pdfa: bool,
+    pub font: Option<Font>,
+}
+
+/// A TrueType font embedded whole in the PDFs, with the widths of the WinAnsiEncoding characters in thousandths of
+/// the font size.
+#[derive(Clone)]
+pub struct Font {
+    name: String,
+    data: Vec<u8>,
+    widths: Vec<f32>,
+    flags: FontFlags,
+    bbox: Rect,
+    italic_angle: f32,
+    ascent: f32,
+    descent: f32,
+    cap_height: f32,
+}
+
+impl Font {
+    /// Read a TrueType font from the file, which must allow embedding in documents.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path)
+            .map_err(|e| format!("Unable to read the font {}: {}", path.display(), e))?;
+        Font::parse(data).map_err(|e| format!("Unable to use the font {}: {}", path.display(), e))
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Self, String> {
+        if ttf_parser::fonts_in_collection(&data).is_some() {
+            return Err("font collections can't be embedded, use a single .ttf font".into());
+        }
+        let face = ttf_parser::Face::parse(&data, 0).map_err(|e| e.to_string())?;
+        if face.tables().glyf.is_none() {
+            return Err("only TrueType outlines can be embedded, not OpenType CFF".into());
+        }
+        if face.permissions() == Some(ttf_parser::Permissions::Restricted) {
+            return Err("the font's licence doesn't allow embedding".into());
+        }
+
+        // PDF names can't have spaces, PostScript names shouldn't but some fonts have them anyway.
+        let name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .map(|name| name.replace(' ', "-"))
+            .ok_or("the font has no PostScript name")?;
+
+        let units = f32::from(face.units_per_em()) / 1000.0;
+        let widths = (FIRST_CHAR..=LAST_CHAR)
+            .map(|code| match code {
+                0x7F..=0x9F => 0.0,
+                _ => face
+                    .glyph_index(char::from(code))
+                    .and_then(|glyph| face.glyph_hor_advance(glyph))
+                    .map_or(0.0, |advance| (f32::from(advance) / units).round()),
+            })
+            .collect();
+
+        let mut flags = FontFlags::NON_SYMBOLIC;
+        flags.set(FontFlags::FIXED_PITCH, face.is_monospaced());
+        flags.set(FontFlags::ITALIC, face.is_italic());
+        let bbox = face.global_bounding_box();
+        let scaled = |value: i16| f32::from(value) / units;
+
+        Ok(Font {
+            bbox: Rect::new(
+                scaled(bbox.x_min),
+                scaled(bbox.y_min),
+                scaled(bbox.x_max),
+                scaled(bbox.y_max),
+            ),
+            italic_angle: face.italic_angle(),
+            ascent: scaled(face.ascender()),
+            descent: scaled(face.descender()),
+            cap_height: scaled(face.capital_height().unwrap_or_else(|| face.ascender())),
+            name,
+            widths,
+            flags,
+            data,
+        })
+    }
+
+    fn width(&self, code: u8) -> f32 {
+        self.widths[usize::from(code - FIRST_CHAR)]
+    }
+}
+
+// The font file is too big to be of use in logs.
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Font").field("name", &self.name).finish()
+    }
+}
+
+/// Write a PDF with a page for each QR Code, as large as fits the page inside the margin and centred, with the name
+/// of the record under it when captioned.
+pub fn write(
+    path: &Path,
+    codes: &[Code],
+    document: &Document,
+    style: &Style,
+    captions: bool,
+) -> io::Result<()> {
+    let paper = document.paper;
+    let pages = codes.iter().map(|code| {
+        let mut content = Content::new();
+        let caption_height = if captions { CAPTION_SIZE * 2.5 } else { 0.0 };
+        let side = (paper.width - 2.0 * MARGIN).min(paper.height - 2.0 * MARGIN - caption_height);
+        let left = (paper.width - side) / 2.0;
+        let bottom = (paper.height - side + caption_height) / 2.0;
+        draw(&mut content, &code.qr_code, style, left, bottom, side);
+        if captions {
+            caption(
+                &mut content,
+                &code.name,
+                style,
+                document.font.as_ref(),
+                paper.width / 2.0,
+                bottom - CAPTION_SIZE * 1.5,
+                CAPTION_SIZE,
+                paper.width - 2.0 * MARGIN,
+            );
+        }
+        content
+    });
+
+    write_pages(path, paper, document, captions, pages)
+}
+
+/// Write a PDF of pages the same size with the given contents, with the document's font, or Helvetica, for captions
+/// when they have text.  PDF/A documents also get the sRGB profile the colours are in, XMP metadata saying which
+/// part of PDF/A they conform to, and a file identifier.
+pub fn write_pages<I>(
+    path: &Path,
+    paper: Paper,
+    document: &Document,
+    text: bool,
+    pages: I,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = Content>,
+{
+    let catalog_id = Ref::new(1);
+    let pages_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let mut page_ids = Vec::new();
+
+    let mut pdf = Pdf::new();
+    for content in pages {
+        let page_id = Ref::new(4 + 2 * page_ids.len() as i32);
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(page_id);
+        page.parent(pages_id)
+            .media_box(Rect::new(0.0, 0.0, paper.width, paper.height))
+            .contents(content_id);
+        if text {
+            page.resources().fonts().pair(FONT, font_id);
+        }
+        page.finish();
+
+        pdf.stream(content_id, &deflate(&content.finish())?)
+            .filter(Filter::FlateDecode);
+        page_ids.push(page_id);
+    }
+
+    pdf.pages(pages_id)
+        .kids(page_ids.iter().copied())
+        .count(page_ids.len() as i32);
+
+    // The rest of the objects follow the pages, as there's no knowing how many pages there are until they're written.
+    let mut next_id = Ref::new(4 + 2 * page_ids.len() as i32);
+    if text {
+        match &document.font {
+            Some(font) => embed(&mut pdf, font_id, &mut next_id, font)?,
+            None => {
+                pdf.type1_font(font_id)
+                    .base_font(Name(b"Helvetica"))
+                    .encoding_predefined(Name(b"WinAnsiEncoding"));
+            }
+        }
+    }
+
+    let mut catalog = pdf.catalog(catalog_id);
+    catalog.pages(pages_id);
+    if !document.pdfa {
+        catalog.finish();
+        return fs::write(path, pdf.finish());
+    }
+
+    let metadata_id = next_id.bump();
+    let profile_id = next_id.bump();
+    catalog.metadata(metadata_id);
+    catalog
+        .insert(Name(b"OutputIntents"))
+        .array()
+        .push()
+        .start::<OutputIntent>()
+        .subtype(OutputIntentSubtype::PDFA)
+        .output_condition_identifier(TextStr("sRGB IEC61966-2.1"))
+        .dest_output_profile(profile_id);
+    catalog.finish();
+
+    pdf.metadata(metadata_id, xmp().as_bytes());
+    pdf.icc_profile(profile_id, &deflate(&icc::srgb())?)
+        .n(3)
+        .filter(Filter::FlateDecode);
+    let id = Uuid::new_v4().as_bytes().to_vec();
+    pdf.set_file_id((id.clone(), id));
+    fs::write(path, pdf.finish())
+}
+
+// A simple TrueType font in WinAnsiEncoding, with the whole font file embedded.
+fn embed(pdf: &mut Pdf, id: Ref, next_id: &mut Ref, font: &Font) -> io::Result<()> {
+    let descriptor_id = next_id.bump();
+    let file_id = next_id.bump();
+    let name = Name(font.name.as_bytes());
+
+    let mut dict = pdf.indirect(id).dict();
+    dict.pair(Name(b"Type"), Name(b"Font"))
+        .pair(Name(b"Subtype"), Name(b"TrueType"))
+        .pair(Name(b"BaseFont"), name)
+        .pair(Name(b"FirstChar"), i32::from(FIRST_CHAR))
+        .pair(Name(b"LastChar"), i32::from(LAST_CHAR))
+        .pair(Name(b"Encoding"), Name(b"WinAnsiEncoding"))
+        .pair(Name(b"FontDescriptor"), descriptor_id);
+    dict.insert(Name(b"Widths"))
+        .array()
+        .items(font.widths.iter().copied());
+    dict.finish();
+
+    pdf.font_descriptor(descriptor_id)
+        .name(name)
+        .flags(font.flags)
+        .bbox(font.bbox)
+        .italic_angle(font.italic_angle)
+        .ascent(font.ascent)
+        .descent(font.descent)
+        .cap_height(font.cap_height)
+        .stem_v(80.0)
+        .font_file2(file_id);
+
+    pdf.stream(file_id, &deflate(&font.data)?)
+        .filter(Filter::FlateDecode)
+        .pair(Name(b"Length1"), font.data.len() as i32);
+    Ok(())
+}
+
+// The XMP metadata PDF/A identifies itself by, with the time the document was made.
+fn xmp() -> String {
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let part = |range: std::ops::Range<usize>| now[range].parse().unwrap_or_default();
+    let date = DateTime {
+        year: part(0..4),
+        month: Some(part(5..7) as u8),
+        day: Some(part(8..10) as u8),
+        hour: Some(part(11..13) as u8),
+        minute: Some(part(14..16) as u8),
+        second: Some(part(17..19) as u8),
+        timezone: Some(Timezone::Utc),
+    };
+
+    let mut xmp = XmpWriter::new();
+    xmp.producer("qrgen")
+        .creator_tool(concat!("qrgen ", env!("CARGO_PKG_VERSION")))
+        .create_date(date)
+        .modify_date(date)
+        .metadata_date(date)
+        .pdfa_part("2")
+        .pdfa_conformance("B");
+    xmp.finish(None)
+}
+
+/// Draw the QR Code as a square of the given side with its lower left corner at x, y, in points.
+pub fn draw(
+    content: &mut Content,
+    qr_code: &qrcodegen::QrCode,
+    style: &Style,
+    x: f32,
+    y: f32,
+    side: f32,
+) {
+    let module = side / style.modules(qr_code) as f32;
+    let border = f32::from(style.border);
+
+    if !style.no_rect {
+        fill(content, style.background);
+        content.rect(x, y, side, side).fill_nonzero();
+    }
+
+    fill(content, style.foreground);
+    for (column, row, len) in batch::runs(qr_code) {
+        content.rect(
+            x + (column as f32 + border) * module,
+            y + side - (row as f32 + border + 1.0) * module,
+            len as f32 * module,
+            module,
+        );
+    }
+    content.fill_nonzero();
+}
+
+/// Write the text centred on x with its baseline at y, made smaller if it would be wider than the width.
+pub fn caption(
+    content: &mut Content,
+    text: &str,
+    style: &Style,
+    font: Option<&Font>,
+    x: f32,
+    y: f32,
+    size: f32,
+    width: f32,
+) {
+    let size = fit_text(text, font, size, width);
+
+    fill(content, style.foreground);
+    content
+        .begin_text()
+        .set_font(FONT, size)
+        .next_line(x - text_width(text, font, size) / 2.0, y)
+        .show(Str(&win_ansi(text)))
+        .end_text();
+}
+
+/// The width of the text in the font, or Helvetica, at the font size, in the same units as the size.
+pub fn text_width(text: &str, font: Option<&Font>, size: f32) -> f32 {
+    let units: f32 = win_ansi(text)
+        .iter()
+        .map(|b| match font {
+            Some(font) => font.width(*b),
+            None => f32::from(char_width(*b)),
+        })
+        .sum();
+    units * size / 1000.0
+}
+
+/// The font size, no larger than the one given, at which the text fits the width.
+pub fn fit_text(text: &str, font: Option<&Font>, size: f32, width: f32) -> f32 {
+    size.min(width * size / text_width(text, font, size).max(f32::EPSILON))
+}
+
+/// Parse a width and height in millimetres, e.g. 63.5x38.1 or 7.2x15.1mm, as points.
+pub fn parse_size(src: &str) -> Option<(f32, f32)> {
+    let src = src.trim().to_lowercase();
+    let (width, height) = src.trim_end_matches("mm").split_once('x')?;
+    let (width, height): (f32, f32) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (width.is_finite() && height.is_finite() && width >= 0.0 && height >= 0.0)
+        .then_some((width * POINTS_PER_MM, height * POINTS_PER_MM))
+}
+
+fn fill(content: &mut Content, (r, g, b): (u8, u8, u8)) {
+    content.set_fill_rgb(
+        f32::from(r) / 255.0,
+        f32::from(g) / 255.0,
+        f32::from(b) / 255.0,
+    );
+}
+
+// WinAnsiEncoding matches Latin-1 from 0xA0, which covers most names, anything else is shown as a question mark.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match u32::from(c) {
+            code @ (0x20..=0x7E | 0xA0..=0xFF) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+// Letters outside ASCII are about as wide as a lower case letter.
+fn char_width(b: u8) -> u16 {
+    match b {
+        0x20..=0x7E => HELVETICA_WIDTHS[usize::from(b - 0x20)],
+        _ => 556,
+    }
+}
+
+fn deflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_paper() {
+        assert_eq!(Ok(Paper::default()), Paper::parse("A4"));
+        let label = Paper::parse("100x150mm").unwrap();
+        assert!((label.width - 283.46).abs() < 0.01);
+        assert!((label.height - 425.2).abs() < 0.01);
+        assert!(Paper::parse("b5").is_err());
+        assert!(Paper::parse("0x150").is_err());
+    }
+
+    #[test]
+    fn should_write_a_page_for_each_code() {
+        let path = std::env::temp_dir().join("qrgen_should_write_a_page_for_each_code.pdf");
+        let batch = batch::Batch::default();
+        for (row, name) in ["a", "b", "c"].iter().enumerate() {
+            let qr_code = qrcodegen::QrCode::encode_text(name, qrcodegen::QrCodeEcc::Low).unwrap();
+            batch.add(Path::new("in.csv"), row as u64, name, &qr_code);
+        }
+
+        write(
+            &path,
+            &batch.codes(),
+            &Document::default(),
+            &Style::default(),
+            true,
+        )
+        .unwrap();
+
+        let pdf = fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-"));
+        assert!(text.contains("/Count 3"));
+        assert_eq!(3, text.matches("/Type /Page\n").count());
+        assert!(text.contains("/Helvetica"));
+        assert!(!text.contains("/OutputIntents"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_write_pdfa_with_profile_and_metadata() {
+        let path =
+            std::env::temp_dir().join("qrgen_should_write_pdfa_with_profile_and_metadata.pdf");
+        let batch = batch::Batch::default();
+        let qr_code = qrcodegen::QrCode::encode_text("a", qrcodegen::QrCodeEcc::Low).unwrap();
+        batch.add(Path::new("in.csv"), 0, "a", &qr_code);
+        let document = Document {
+            pdfa: true,
+            ..Default::default()
+        };
+
+        write(&path, &batch.codes(), &document, &Style::default(), false).unwrap();
+
+        let pdf = fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/OutputIntents [<<\n    /Type /OutputIntent\n    /S /GTS_PDFA1"));
+        assert!(text.contains("/Type /Metadata"));
+        assert!(text.contains("<pdfaid:part>2</pdfaid:part>"));
+        assert!(text.contains("<pdfaid:conformance>B</pdfaid:conformance>"));
+        assert!(text.contains("/ID ["));
+        assert!(!text.contains("/Helvetica"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_encode_captions_as_win_ansi() {
+        assert_eq!(b"Caf\xE9 ?".to_vec(), win_ansi("Café 日"));
+    }
+
+    #[test]
+    fn should_shrink_text_to_fit() {
+        assert_eq!(12.0, fit_text("a", None, 12.0, 100.0));
+        let size = fit_text("a long name for a small label", None, 12.0, 50.0);
+        assert!(size < 12.0);
+        assert!((text_width("a long name for a small label", None, size) - 50.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::batch::{Code, Style};
+use crate::pdf::POINTS_PER_MM;
+use crate::svg;
+use std::{fs, io, path::Path};
+
+const MARGIN: f32 = 20.0 * POINTS_PER_MM;
+const SIDE: f32 = 60.0 * POINTS_PER_MM;
+const GAP: f32 = 15.0 * POINTS_PER_MM;
+const CAPTION_SIZE: f32 = 16.0;
+const TITLE_SIZE: f32 = 48.0;
+
+/// The title across the top of a poster and the number of QR Codes in each row, as close to square as the codes allow
+/// unless given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    title: Option<String>,
+    columns: Option<u32>,
+}
+
+impl Layout {
+    pub fn new(title: Option<String>, columns: Option<u32>) -> Self {
+        Layout { title, columns }
+    }
+
+    fn columns(&self, codes: usize) -> usize {
+        match self.columns {
+            Some(columns) => columns as usize,
+            None => (codes as f64).sqrt().ceil() as usize,
+        }
+        .clamp(1, codes.max(1))
+    }
+}
+
+/// Write the QR Codes as one SVG poster, in a grid with each record's name under its QR Code and the title above.
+pub fn write(path: &Path, codes: &[Code], layout: &Layout, style: &Style) -> io::Result<()> {
+    let columns = layout.columns(codes.len());
+    let rows = codes.len().div_ceil(columns);
+    let title = if layout.title.is_some() {
+        TITLE_SIZE * 2.0
+    } else {
+        0.0
+    };
+    let cell = (SIDE + GAP, SIDE + CAPTION_SIZE * 2.0 + GAP);
+    let width = 2.0 * MARGIN + columns as f32 * cell.0 - GAP;
+    let height = 2.0 * MARGIN + title + rows as f32 * cell.1 - GAP;
+
+    let mut poster = svg::begin(width, height);
+    if !style.no_rect {
+        poster += &format!(
+            "\t<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            svg::colour(style.background)
+        );
+    }
+    if let Some(text) = &layout.title {
+        svg::caption(
+            &mut poster,
+            text,
+            style,
+            width / 2.0,
+            MARGIN + TITLE_SIZE,
+            TITLE_SIZE,
+            width - 2.0 * MARGIN,
+        );
+    }
+
+    for (i, code) in codes.iter().enumerate() {
+        let x = MARGIN + (i % columns) as f32 * cell.0;
+        let y = MARGIN + title + (i / columns) as f32 * cell.1;
+        svg::code(&mut poster, &code.qr_code, style, x, y, SIDE);
+        svg::caption(
+            &mut poster,
+            &code.name,
+            style,
+            x + SIDE / 2.0,
+            y + SIDE + CAPTION_SIZE * 1.5,
+            CAPTION_SIZE,
+            SIDE + GAP / 2.0,
+        );
+    }
+
+    svg::end(&mut poster);
+    fs::write(path, poster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Batch;
+
+    #[test]
+    fn should_pick_columns_close_to_square() {
+        assert_eq!(3, Layout::default().columns(7));
+        assert_eq!(3, Layout::default().columns(9));
+        assert_eq!(1, Layout::default().columns(1));
+        assert_eq!(2, Layout::new(None, Some(10)).columns(2));
+    }
+
+    #[test]
+    fn should_write_a_titled_poster() {
+        let path = std::env::temp_dir().join("qrgen_should_write_a_titled_poster.svg");
+        let batch = Batch::default();
+        for (row, name) in ["Alice", "Bob & Carol"].iter().enumerate() {
+            let qr_code = qrcodegen::QrCode::encode_text(name, qrcodegen::QrCodeEcc::Low).unwrap();
+            batch.add(Path::new("guests.csv"), row as u64, name, &qr_code);
+        }
+
+        write(
+            &path,
+            &batch.codes(),
+            &Layout::new(Some("Check-in".into()), None),
+            &Style::default(),
+        )
+        .unwrap();
+
+        let poster = fs::read_to_string(&path).unwrap();
+        assert!(poster.contains(">Check-in</text>"));
+        assert!(poster.contains(">Bob &amp; Carol</text>"));
+        assert_eq!(3, poster.matches("<text").count());
+        assert_eq!(3, poster.matches("<svg").count());
+        fs::remove_file(&path).unwrap();
+    }
+}
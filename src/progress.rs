@@ -0,0 +1,140 @@
+use log::debug;
+use serde_json::{json, Value};
+use std::{
+    fmt, io,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Writes progress events as newline delimited JSON so a wrapper, e.g. a GUI, can follow a run as it happens.
+#[derive(Clone)]
+pub struct Progress {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+    records: Arc<AtomicU64>,
+}
+
+impl Progress {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Progress {
+            out: Arc::new(Mutex::new(Box::new(out))),
+            records: Default::default(),
+        }
+    }
+
+    /// Write events to the given file descriptor, 1 and 2 for stdout and stderr or, on Unix, one opened by the
+    /// program that started qrgen.
+    pub fn open(fd: i32) -> io::Result<Self> {
+        match fd {
+            1 => Ok(Progress::new(io::stdout())),
+            2 => Ok(Progress::new(io::stderr())),
+            _ => open_fd(fd),
+        }
+    }
+
+    pub fn started(&self, files: usize) {
+        self.emit(json!({ "event": "started", "files": files }));
+    }
+
+    /// Another chunk of records from the file has been generated, the count is of all the records so far.
+    pub fn chunk_done(&self, file: &Path, records: u64) {
+        self.emit(json!({
+            "event": "chunk-done",
+            "file": file.display().to_string(),
+            "records": records,
+        }));
+    }
+
+    pub fn file_done(&self, file: &Path, records: u64) {
+        self.records.fetch_add(records, Ordering::Relaxed);
+        self.emit(json!({
+            "event": "file-done",
+            "file": file.display().to_string(),
+            "records": records,
+        }));
+    }
+
+    pub fn finished(&self, failures: u64) {
+        self.emit(json!({
+            "event": "finished",
+            "records": self.records.load(Ordering::Relaxed),
+            "failures": failures,
+        }));
+    }
+
+    // A wrapper that stops listening shouldn't stop the run, so write errors are only logged.
+    fn emit(&self, event: Value) {
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", event).and_then(|_| out.flush()) {
+            debug!("unable to write progress event {}", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<Progress> {
+    use std::{fs::File, os::unix::io::FromRawFd};
+
+    // Safety: the descriptor was handed to qrgen for progress events, so nothing else in the process uses it and
+    // the file can own it.  A descriptor that isn't open fails the metadata check and is forgotten, not closed.
+    let file = unsafe { File::from_raw_fd(fd) };
+    if let Err(e) = file.metadata() {
+        std::mem::forget(file);
+        return Err(e);
+    }
+    Ok(Progress::new(file))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> io::Result<Progress> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "only 1 for stdout and 2 for stderr are supported on this platform",
+    ))
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Progress").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_write_events_as_lines() {
+        let buffer = Buffer::default();
+        let progress = Progress::new(buffer.clone());
+
+        progress.started(1);
+        progress.chunk_done(Path::new("a.csv"), 256);
+        progress.file_done(Path::new("a.csv"), 300);
+        progress.finished(2);
+
+        assert_eq!(
+            "{\"event\":\"started\",\"files\":1}\n\
+             {\"event\":\"chunk-done\",\"file\":\"a.csv\",\"records\":256}\n\
+             {\"event\":\"file-done\",\"file\":\"a.csv\",\"records\":300}\n\
+             {\"event\":\"finished\",\"failures\":2,\"records\":300}\n",
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+        );
+    }
+}
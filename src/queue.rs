@@ -0,0 +1,36 @@
+use crate::{generator::Generator, json};
+use log::{info, trace};
+use std::path::Path;
+
+/// Take messages from the front of a Redis list as they arrive and generate a QR Code for each, until the
+/// connection fails or a failure stops the run with --fail-fast.  Messages are JSON objects with a name and data
+/// field, the same as a line of NDJSON input.
+pub fn consume(generator: &Generator, url: &str, list: &str) -> redis::RedisResult<()> {
+    let client = redis::Client::open(url)?;
+    let mut connection = client.get_connection()?;
+    let source = Path::new(list);
+    info!("waiting for messages on {}", list);
+
+    for row in 1.. {
+        // A timeout of zero blocks until a message arrives.
+        let (_, message): (String, String) = redis::cmd("BLPOP")
+            .arg(list)
+            .arg(0)
+            .query(&mut connection)?;
+        trace!("received message {}", row);
+
+        if let Some((mut record, overrides)) = json::read_object(row, &message) {
+            if let Some(overrides) = generator.overrides(source, &record, overrides) {
+                record.overrides = overrides;
+                generator.generate_entry(source, &record);
+            }
+            generator.write_reports();
+        }
+
+        if generator.stopped() {
+            break;
+        }
+    }
+
+    Ok(())
+}
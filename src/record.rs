@@ -0,0 +1,394 @@
+use crate::exporter;
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// A single payload read from an input file, ready to be encoded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    pub row: u64,
+    pub name: String,
+    pub data: String,
+    pub overrides: Overrides,
+    /// The name from the input when it had to be changed to make a safe or unique file name.
+    pub renamed_from: Option<String>,
+    /// The modification time to give the output file.
+    pub mtime: Option<SystemTime>,
+}
+
+impl Record {
+    pub fn new(row: u64, name: String, data: String) -> Self {
+        Record {
+            row,
+            name,
+            data,
+            overrides: Default::default(),
+            renamed_from: None,
+            mtime: None,
+        }
+    }
+
+    /// Give the record another file name, keeping the name from the input for the manifest.
+    pub fn rename(&mut self, name: String) {
+        let original = std::mem::replace(&mut self.name, name);
+        self.renamed_from.get_or_insert(original);
+    }
+}
+
+/// Where the name of each output file comes from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Naming {
+    /// The name column of the input, or the name field of JSON records.
+    #[default]
+    Column,
+    /// A zero-padded count of the records read so far, starting at 1, e.g. 000001.
+    Row,
+    /// The start of the SHA-256 hash of the data, so identical data always gets the same name.
+    Hash,
+}
+
+/// What happens to records with no data, or no name when names come from a column.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BlankPolicy {
+    /// Leave the record out without saying so.
+    Skip,
+    /// Leave the record out and log a warning.
+    #[default]
+    Warn,
+    /// Stop processing the input file.
+    Error,
+}
+
+/// What happens to records whose name isn't safe to use as a file name.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnsafeNamePolicy {
+    /// Use the name with the unsafe parts replaced or removed.
+    #[default]
+    Replace,
+    /// Leave the record out and log a warning.
+    Skip,
+    /// Stop processing the input file.
+    Error,
+}
+
+/// The name made safe to use as a file name in the output directory.  Path separators and control characters are
+/// replaced with underscores, and leading dots and trailing spaces are removed so a name can't climb out of the
+/// directory, hide the file or be refused by Windows.  A name with nothing left becomes an underscore, and on Windows a
+/// reserved device name gets an underscore after it, e.g. CON_.
+pub fn safe_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_start_matches('.').trim_end_matches(' ');
+
+    match name {
+        "" => String::from("_"),
+        name if cfg!(windows) && is_reserved_name(name) => {
+            let (stem, extension) = name.split_at(name.find('.').unwrap_or(name.len()));
+            format!("{}_{}", stem, extension)
+        }
+        name => name.to_string(),
+    }
+}
+
+// Device names Windows reserves in every directory.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// True if Windows refuses the name as a file name because it is a reserved device name, in any case and with or
+/// without an extension, e.g. CON or nul.svg.
+pub fn is_reserved_name(name: &str) -> bool {
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(' ');
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// The case of file names made from the names in the input.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NameCase {
+    /// Use the name as it is.
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+}
+
+impl NameCase {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            NameCase::Keep => name.to_string(),
+            NameCase::Lower => name.to_lowercase(),
+            NameCase::Upper => name.to_uppercase(),
+        }
+    }
+}
+
+/// What happens to a record whose name was already used by an earlier record in the run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicateNamePolicy {
+    /// Add the first free number to the name, e.g. name-2.
+    #[default]
+    Suffix,
+    /// Let the record overwrite the earlier record's file.
+    Overwrite,
+    /// Stop processing the input file.
+    Error,
+}
+
+/// The names used so far in a run.  Names are compared without case, as they are by the file systems of Windows and
+/// macOS.
+#[derive(Debug, Default)]
+pub struct UsedNames(Mutex<HashMap<String, u32>>);
+
+impl UsedNames {
+    /// Mark the name as used, returning false if it already was.
+    pub fn claim(&self, name: &str) -> bool {
+        claim(&mut self.0.lock().unwrap(), name)
+    }
+
+    /// Mark the first unused numbered form of the name as used, starting from name-2, and return it.
+    pub fn claim_numbered(&self, name: &str) -> String {
+        let mut names = self.0.lock().unwrap();
+        loop {
+            let number = names.get(&name.to_lowercase()).copied().unwrap_or(2);
+            names.insert(name.to_lowercase(), number + 1);
+            let numbered = format!("{}-{}", name, number);
+            if claim(&mut names, &numbered) {
+                return numbered;
+            }
+        }
+    }
+}
+
+// Each name maps to the next number to try when it is used again.
+fn claim(names: &mut HashMap<String, u32>, name: &str) -> bool {
+    let key = name.to_lowercase();
+    if names.contains_key(&key) {
+        return false;
+    }
+
+    names.insert(key, 2);
+    true
+}
+
+// The number of hex digits of the SHA-256 hash used when naming by hash.
+const HASH_NAME_LENGTH: usize = 16;
+
+/// The name given to data when naming by hash.
+pub fn hash_name(data: &str) -> String {
+    let digest = Sha256::digest(data.as_bytes());
+    let mut name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    name.truncate(HASH_NAME_LENGTH);
+    name
+}
+
+/// Read a modification time given as Unix seconds, a date, e.g. 2024-05-01, or a UTC date and time, e.g.
+/// 2024-05-01T09:30:00Z or 2024-05-01 09:30:00.
+pub fn parse_mtime(value: &str) -> Result<SystemTime, String> {
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+
+    let date_time = match value.len() {
+        10 => format!("{} 00:00:00", value),
+        _ => value.to_string(),
+    };
+    humantime::parse_rfc3339_weak(&date_time)
+        .map_err(|_| format!("{} is not a date, date and time or Unix time", value))
+}
+
+// The number of hex digits of the name's hash added to a shortened name, so long names that start the same stay apart.
+const SHORTENED_HASH_LENGTH: usize = 8;
+
+/// The shortest file name length shorten_name can make names fit in, leaving room for the hash and a short extension.
+pub const MIN_NAME_LENGTH: usize = 16;
+
+/// The name cut short, with the start of its hash added, so the file name with the extension fits in the given number
+/// of bytes.  Names that already fit are returned as they are.
+pub fn shorten_name(name: &str, extension: &str, max_length: usize) -> String {
+    let extension_length = 1 + extension.len();
+    if name.len() + extension_length <= max_length {
+        return name.to_string();
+    }
+
+    let mut end = max_length.saturating_sub(1 + SHORTENED_HASH_LENGTH + extension_length);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!(
+        "{}-{}",
+        &name[..end],
+        &hash_name(name)[..SHORTENED_HASH_LENGTH]
+    )
+}
+
+/// Put the data between a URL prefix and suffix, escaping any characters that can't appear in a URL, such as spaces,
+/// so raw ids can be turned into links.
+pub fn wrap_url(data: &str, prefix: &str, suffix: &str) -> String {
+    let mut url = String::with_capacity(prefix.len() + data.len() + suffix.len());
+    url.push_str(prefix);
+
+    for c in data.chars() {
+        if c.is_ascii_graphic()
+            && !matches!(c, '"' | '<' | '>' | '\\' | '^' | '`' | '{' | '|' | '}')
+        {
+            url.push(c);
+        } else {
+            let mut buffer = [0; 4];
+            for b in c.encode_utf8(&mut buffer).bytes() {
+                url.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+
+    url.push_str(suffix);
+    url
+}
+
+/// QR and output settings supplied by an individual record which take precedence over the command line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub error_correction: Option<qrcodegen::QrCodeEcc>,
+    pub qr_version_min: Option<qrcodegen::Version>,
+    pub qr_version_max: Option<qrcodegen::Version>,
+    pub mask: Option<qrcodegen::Mask>,
+    pub formats: Option<exporter::Formats>,
+    pub scale: Option<u32>,
+    pub border: Option<u8>,
+}
+
+/// The text of each override a record gives, from its columns in CSV or Excel input or its fields in JSON.  Missing or
+/// empty values override nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverrideValues<'a> {
+    pub ecc: Option<Cow<'a, str>>,
+    pub min: Option<Cow<'a, str>>,
+    pub max: Option<Cow<'a, str>>,
+    pub mask: Option<Cow<'a, str>>,
+    pub format: Option<Cow<'a, str>>,
+    pub scale: Option<Cow<'a, str>>,
+    pub border: Option<Cow<'a, str>>,
+}
+
+impl OverrideValues<'_> {
+    /// Read the overrides the same way as the command line options they take precedence over.
+    pub fn parse(&self) -> Result<Overrides, String> {
+        fn value<'a>(value: &'a Option<Cow<str>>) -> Option<&'a str> {
+            value.as_deref().filter(|value| !value.is_empty())
+        }
+
+        Ok(Overrides {
+            error_correction: value(&self.ecc).map(crate::parse_qr_ecc).transpose()?,
+            qr_version_min: value(&self.min).map(crate::parse_qr_version).transpose()?,
+            qr_version_max: value(&self.max).map(crate::parse_qr_version).transpose()?,
+            mask: value(&self.mask).map(crate::parse_qr_mask).transpose()?,
+            formats: value(&self.format)
+                .map(crate::parse_qr_formats)
+                .transpose()?,
+            scale: value(&self.scale).map(crate::parse_qr_scale).transpose()?,
+            border: value(&self.border).map(crate::parse_border).transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_detect_reserved_names() {
+        assert!(is_reserved_name("CON"));
+        assert!(is_reserved_name("nul.svg"));
+        assert!(is_reserved_name("Lpt1 .txt"));
+        assert!(!is_reserved_name("CONSOLE"));
+        assert!(!is_reserved_name("COM10"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn should_rename_reserved_names_on_windows() {
+        assert_eq!("CON_", safe_name("CON"));
+        assert_eq!("aux_.backup", safe_name("aux.backup"));
+    }
+
+    #[test]
+    fn should_shorten_long_names() {
+        assert_eq!("short", shorten_name("short", "svg", 255));
+
+        let long = "é".repeat(200);
+        let shortened = shorten_name(&long, "svg", 255);
+        assert!(shortened.len() + 4 <= 255);
+        assert!(shortened.starts_with("éé"));
+        assert_ne!(shortened, shorten_name(&format!("{}x", long), "svg", 255));
+    }
+
+    #[test]
+    fn should_parse_mtime() {
+        let may_day = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_521_600);
+
+        assert_eq!(Ok(may_day), parse_mtime("1714521600"));
+        assert_eq!(Ok(may_day), parse_mtime("2024-05-01"));
+        assert_eq!(Ok(may_day), parse_mtime("2024-05-01T00:00:00Z"));
+        assert_eq!(
+            Ok(may_day + Duration::from_secs(9 * 3600 + 30 * 60)),
+            parse_mtime("2024-05-01 09:30:00")
+        );
+        assert!(parse_mtime("May Day").is_err());
+    }
+
+    #[test]
+    fn should_number_used_names() {
+        let names = UsedNames::default();
+
+        assert!(names.claim("a"));
+        assert!(names.claim("a-3"));
+        assert!(!names.claim("A"));
+        assert_eq!("A-2", names.claim_numbered("A"));
+        assert_eq!("a-4", names.claim_numbered("a"));
+    }
+
+    #[test]
+    fn should_make_names_safe() {
+        assert_eq!("invoice-1", safe_name("invoice-1"));
+        assert_eq!("_etc_passwd", safe_name("/etc/passwd"));
+        assert_eq!("_.._secret", safe_name("..\\../secret"));
+        assert_eq!("a_b", safe_name("a\tb"));
+        assert_eq!("hidden", safe_name(".hidden  "));
+        assert_eq!("_", safe_name(".. "));
+    }
+
+    #[test]
+    fn should_name_by_sha256_prefix() {
+        // sha256("hello") = 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
+        assert_eq!("2cf24dba5fb0a30e", hash_name("hello"));
+    }
+
+    #[test]
+    fn should_wrap_url() {
+        assert_eq!(
+            "https://example.com/p/A-1?src=qr",
+            wrap_url("A-1", "https://example.com/p/", "?src=qr")
+        );
+        assert_eq!(
+            "https://example.com/p/caf%C3%A9%20%7B1%7D",
+            wrap_url("café {1}", "https://example.com/p/", "")
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use serde::Serialize;
+use std::{error::Error, fs::File, path::Path, sync::Mutex};
+
+/// A record whose QR Code couldn't be generated and why.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Reject {
+    pub input: String,
+    pub row: u64,
+    pub name: String,
+    pub reason: String,
+}
+
+/// The records rejected during a run, so they can be fixed and generated again.
+#[derive(Default)]
+pub struct Rejects(Mutex<Vec<Reject>>);
+
+impl Rejects {
+    pub fn add(&self, input: &Path, row: u64, name: &str, reason: String) {
+        self.0.lock().unwrap().push(Reject {
+            input: input.display().to_string(),
+            row,
+            name: name.to_string(),
+            reason,
+        });
+    }
+
+    /// Write the rejects to the given path in row order, as a JSON array when it ends in .json and CSV otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut rejects = self.0.lock().unwrap();
+        rejects.sort_by(|a, b| a.input.cmp(&b.input).then(a.row.cmp(&b.row)));
+        let file = File::create(path)?;
+
+        if path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("json"))
+            .unwrap_or(false)
+        {
+            serde_json::to_writer_pretty(file, &*rejects)?;
+        } else {
+            let mut writer = csv::Writer::from_writer(file);
+            for reject in rejects.iter() {
+                writer.serialize(reject)?;
+            }
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_write_rejects_in_row_order() {
+        let path = std::env::temp_dir().join("qrgen_should_write_rejects_in_row_order.csv");
+        let rejects = Rejects::default();
+        rejects.add(Path::new("a.csv"), 3, "c", "timed out after 1s".into());
+        rejects.add(Path::new("a.csv"), 1, "a", "DataTooLong".into());
+
+        rejects.write(&path).unwrap();
+
+        assert_eq!(
+            "input,row,name,reason\na.csv,1,a,DataTooLong\na.csv,3,c,timed out after 1s\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{generator::Generator, record::Record, sequence::NumberTemplate};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// Read payloads one per line and generate each one as soon as it is entered, until the input ends, quit or exit is
+/// entered or a failure stops the run with --fail-fast.  Outputs are named from the template numbered from 1, or
+/// drawn in the terminal instead of written to a file.
+pub fn run<R: BufRead, W: Write>(
+    generator: &Generator,
+    mut input: R,
+    mut output: W,
+    names: &NumberTemplate,
+    terminal: bool,
+    prompt: bool,
+) -> io::Result<()> {
+    let mut count = 0;
+
+    loop {
+        if prompt {
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let data = line.trim_end_matches(['\r', '\n']);
+        match data.trim() {
+            "" => continue,
+            "quit" | "exit" => break,
+            _ => (),
+        }
+
+        if terminal {
+            if let Some(drawn) = generator.draw_value(data) {
+                write!(output, "{}", drawn)?;
+            }
+            continue;
+        }
+
+        count += 1;
+        let record = Record::new(count, names.render(count), data.to_string());
+        for path in generator.generate_entry(Path::new("-"), &record) {
+            writeln!(output, "{}", path.display())?;
+        }
+
+        if generator.stopped() {
+            break;
+        }
+    }
+
+    generator.write_reports();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{OutputConfig, QrConfig};
+    use std::{fs, io::Cursor};
+
+    fn generator(output: std::path::PathBuf) -> Generator {
+        Generator::new(
+            Vec::new(),
+            QrConfig::new(
+                qrcodegen::Version::new(1),
+                qrcodegen::Version::new(40),
+                qrcodegen::QrCodeEcc::Low,
+                None,
+                Default::default(),
+            ),
+            OutputConfig::new(
+                output,
+                4,
+                Default::default(),
+                8,
+                false,
+                (0, 0, 0),
+                (255, 255, 255),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            ),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn should_generate_each_line_until_quit() {
+        let dir = std::env::temp_dir().join("qrgen_should_generate_each_line_until_quit");
+        fs::create_dir_all(&dir).unwrap();
+        let names = NumberTemplate::parse("label-{n:02}").unwrap();
+        let mut output = Vec::new();
+
+        run(
+            &generator(dir.clone()),
+            Cursor::new("first\n\nsecond\r\nquit\nthird\n"),
+            &mut output,
+            &names,
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert!(dir.join("label-01.svg").exists());
+        assert!(dir.join("label-02.svg").exists());
+        assert!(!dir.join("label-03.svg").exists());
+        assert_eq!(2, String::from_utf8(output).unwrap().lines().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_draw_in_terminal() {
+        let names = NumberTemplate::parse("{n}").unwrap();
+        let mut output = Vec::new();
+
+        run(
+            &generator(std::env::temp_dir()),
+            Cursor::new("a"),
+            &mut output,
+            &names,
+            true,
+            true,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("> "));
+        assert!(output.contains('█'));
+    }
+}
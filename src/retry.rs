@@ -0,0 +1,96 @@
+use log::debug;
+use std::{io, thread, time::Duration};
+
+/// How many more times a failed write is tried, waiting for the backoff before the first retry and twice as long
+/// before each one after.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Retry {
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl Retry {
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Retry { attempts, backoff }
+    }
+
+    /// Run the function until it succeeds, fails with an error that won't go away by itself or runs out of retries.
+    pub fn run<T>(self, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = self.backoff;
+
+        for _ in 0..self.attempts {
+            match f() {
+                Err(e) if is_transient(&e) => {
+                    debug!("retrying in {:?} after {}", backoff, e);
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+
+        f()
+    }
+}
+
+// Errors that trying again won't fix, such as a missing output directory, are reported straight away.
+fn is_transient(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::AlreadyExists
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::InvalidData
+            | io::ErrorKind::Unsupported
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::IsADirectory
+            | io::ErrorKind::NotADirectory
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_retry_transient_errors() {
+        let mut calls = 0;
+
+        let result = Retry::new(3, Duration::ZERO).run(|| {
+            calls += 1;
+            match calls {
+                1 | 2 => Err(io::Error::from(io::ErrorKind::Interrupted)),
+                _ => Ok(calls),
+            }
+        });
+
+        assert_eq!(3, result.unwrap());
+    }
+
+    #[test]
+    fn should_give_up_after_attempts() {
+        let mut calls = 0;
+
+        let result: io::Result<()> = Retry::new(2, Duration::ZERO).run(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(3, calls);
+    }
+
+    #[test]
+    fn should_not_retry_permanent_errors() {
+        let mut calls = 0;
+
+        let result: io::Result<()> = Retry::new(2, Duration::ZERO).run(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(1, calls);
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use uuid::Uuid;
+
+// The number of hex digits of a random UUID used as the id of a run.
+const RUN_ID_LENGTH: usize = 8;
+
+/// A single run of qrgen, with the time it started and an id to tell it apart from other runs.
+#[derive(Clone, Debug)]
+pub struct Run {
+    id: String,
+    started: SystemTime,
+}
+
+impl Run {
+    pub fn start() -> Self {
+        let mut id = Uuid::new_v4().simple().to_string();
+        id.truncate(RUN_ID_LENGTH);
+        Run {
+            id,
+            started: SystemTime::now(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Replace the {date}, {time} and {run_id} placeholders in the path with the UTC date the run started, e.g.
+    /// 2024-05-01, the time, e.g. 093000, and the id of the run.  Other text is left as it is.
+    pub fn expand(&self, path: &Path) -> PathBuf {
+        let src = match path.to_str() {
+            Some(src) if src.contains('{') => src,
+            _ => return path.to_path_buf(),
+        };
+
+        // e.g. 2024-05-01T09:30:00Z, without the colons that Windows doesn't allow in file names.
+        let started = humantime::format_rfc3339_seconds(self.started).to_string();
+        PathBuf::from(
+            src.replace("{date}", &started[..10])
+                .replace("{time}", &started[11..19].replace(':', ""))
+                .replace("{run_id}", &self.id),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn should_expand_placeholders() {
+        let run = Run {
+            id: "1a2b3c4d".into(),
+            started: SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_555_800),
+        };
+
+        assert_eq!(
+            Path::new("out/2024-05-01/093000-1a2b3c4d/{other}"),
+            run.expand(Path::new("out/{date}/{time}-{run_id}/{other}"))
+        );
+        assert_eq!(Path::new("out"), run.expand(Path::new("out")));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::record::Record;
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    /// The number, zero-padded to the given width.
+    Number(usize),
+}
+
+/// Text holding a number from a sequence, e.g. ASSET-{n:06}.  {n} is replaced by the number, {n:06} pads it with
+/// zeros to six digits, and {{ or }} stand for a literal brace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl NumberTemplate {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        field.push(c);
+                    }
+
+                    if !closed {
+                        return Err(format!("The template field {{{} is not closed.", field));
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Number(parse_field(&field)?));
+                }
+                '}' => return Err(String::from("The template has a } without a matching {.")),
+                _ => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Number(_))) {
+            return Err(String::from(
+                "The template must include {n} so that each code is different.",
+            ));
+        }
+
+        Ok(NumberTemplate {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    pub fn render(&self, n: u64) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Number(width) => format!("{:0width$}", n, width = width),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for NumberTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Parse the inside of a template field, either n or n:0 followed by a width.
+fn parse_field(field: &str) -> Result<usize, String> {
+    let invalid = || {
+        format!(
+            "The template field {{{}}} must be {{n}}, or {{n:06}} to pad with zeros.",
+            field
+        )
+    };
+
+    match field.trim().split_once(':') {
+        None if field.trim() == "n" => Ok(0),
+        Some((n, width)) if n.trim() == "n" && width.starts_with('0') => {
+            width.parse::<usize>().map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Records numbered from start, count of them, named and filled in from templates.  Numbers past the largest a u64
+/// can hold are refused before any record is made, rather than stopping short of the count.
+pub fn records(
+    start: u64,
+    count: u64,
+    data: NumberTemplate,
+    name: Option<NumberTemplate>,
+) -> Result<impl Iterator<Item = Record>, String> {
+    if count > 0 && start.checked_add(count - 1).is_none() {
+        return Err(format!(
+            "Numbering {} codes from {} would go past {}.",
+            count,
+            start,
+            u64::MAX
+        ));
+    }
+
+    Ok((0..count).map(move |i| {
+        let n = start + i;
+        let data_value = data.render(n);
+        let name_value = match &name {
+            Some(name) => name.render(n),
+            None => data_value.clone(),
+        };
+        Record::new(i + 1, name_value, data_value)
+    }))
+}
+
+/// Records each holding a new random UUID, named from the template numbered from 1, or by the UUID itself.
+pub fn uuid_records(count: u64, name: Option<NumberTemplate>) -> impl Iterator<Item = Record> {
+    (1..=count).map(move |n| {
+        let uuid = Uuid::new_v4().to_string();
+        let name = match &name {
+            Some(name) => name.render(n),
+            None => uuid.clone(),
+        };
+        Record::new(n, name, uuid)
+    })
+}
+
+/// Write a CSV file listing the UUID held by each record and the path of each file it was written to, one line for
+/// each.  Records that weren't written are left out.
+pub fn write_mapping(
+    path: &Path,
+    records: &[Record],
+    outputs: &[Vec<PathBuf>],
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(File::create(path)?);
+    writer.write_record(["uuid", "path"])?;
+
+    for (record, outputs) in records.iter().zip(outputs) {
+        for output in outputs {
+            writer.write_record([record.data.as_str(), &output.to_string_lossy()])?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_padded_numbers() {
+        let template = NumberTemplate::parse("ASSET-{n:06}").unwrap();
+
+        assert_eq!("ASSET-001000", template.render(1000));
+        assert_eq!("ASSET-1234567", template.render(1234567));
+        assert_eq!("{7}", NumberTemplate::parse("{{{n}}}").unwrap().render(7));
+    }
+
+    #[test]
+    fn should_reject_invalid_templates() {
+        assert!(NumberTemplate::parse("ASSET").is_err());
+        assert!(NumberTemplate::parse("ASSET-{n").is_err());
+        assert!(NumberTemplate::parse("ASSET-{x}").is_err());
+        assert!(NumberTemplate::parse("ASSET-{n:6}").is_err());
+    }
+
+    #[test]
+    fn should_refuse_numbers_past_u64_max() {
+        let data = || NumberTemplate::parse("{n}").unwrap();
+
+        assert_eq!(1, records(u64::MAX, 1, data(), None).unwrap().count());
+        assert!(records(u64::MAX, 3, data(), None).is_err());
+        assert!(records(u64::MAX - 1, 3, data(), None).is_err());
+        assert_eq!(0, records(u64::MAX, 0, data(), None).unwrap().count());
+    }
+
+    #[test]
+    fn should_number_records_from_start() {
+        let data = NumberTemplate::parse("https://t.example/{n}").unwrap();
+        let name = NumberTemplate::parse("{n:04}").ok();
+        let records: Vec<Record> = records(8, 3, data, name).unwrap().collect();
+
+        assert_eq!(3, records.len());
+        assert_eq!(
+            Record::new(1, "0008".into(), "https://t.example/8".into()),
+            records[0]
+        );
+        assert_eq!("0010", records[2].name);
+    }
+
+    #[test]
+    fn should_generate_unique_uuids() {
+        let records: Vec<Record> = uuid_records(3, None).collect();
+
+        assert_eq!(3, records.len());
+        assert_eq!(36, records[0].data.len());
+        assert_eq!(records[0].data, records[0].name);
+        assert_ne!(records[0].data, records[1].data);
+        assert_eq!(3, records[2].row);
+    }
+
+    #[test]
+    fn should_write_uuid_mapping() {
+        let path = std::env::temp_dir().join("qrgen_should_write_uuid_mapping.csv");
+        let records = vec![
+            Record::new(1, "0001".into(), "a-b".into()),
+            Record::new(2, "0002".into(), "c-d".into()),
+        ];
+        let outputs = vec![
+            vec![PathBuf::from("png/0001.png"), PathBuf::from("svg/0001.svg")],
+            Vec::new(),
+        ];
+
+        write_mapping(&path, &records, &outputs).unwrap();
+
+        assert_eq!(
+            "uuid,path\na-b,png/0001.png\na-b,svg/0001.svg\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
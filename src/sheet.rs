@@ -0,0 +1,215 @@
+use crate::batch::{self, Code, Style};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// How the QR Codes are laid out on a contact sheet, in columns and rows with a gap in pixels between them and around
+/// the edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    columns: u32,
+    rows: u32,
+    gap: u32,
+}
+
+impl Grid {
+    pub fn new(columns: u32, rows: u32, gap: u32) -> Self {
+        Grid { columns, rows, gap }
+    }
+
+    fn per_sheet(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid::new(5, 5, 16)
+    }
+}
+
+/// Write the QR Codes as PNG contact sheets tiled in the grid, numbering the sheets, e.g. sheet-001.png, when they
+/// need more than one.  Every cell is the size of the largest QR Code, with smaller ones centred in theirs.  Returns
+/// the paths of the sheets written.
+pub fn write(path: &Path, codes: &[Code], grid: Grid, style: &Style) -> io::Result<Vec<PathBuf>> {
+    let cell = codes
+        .iter()
+        .map(|code| (style.modules(&code.qr_code) as u32).checked_mul(style.scale))
+        .try_fold(0, |cell, side| side.map(|side| cell.max(side)))
+        .ok_or_else(too_large)?;
+
+    let sheets = codes.chunks(grid.per_sheet()).count();
+    let mut paths = Vec::with_capacity(sheets);
+    for (number, codes) in codes.chunks(grid.per_sheet()).enumerate() {
+        let path = if sheets > 1 {
+            numbered(path, number + 1)
+        } else {
+            path.to_path_buf()
+        };
+        write_sheet(&path, codes, grid, cell, style)?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+fn write_sheet(
+    path: &Path,
+    codes: &[Code],
+    grid: Grid,
+    cell: u32,
+    style: &Style,
+) -> io::Result<()> {
+    // Only as many rows as the codes fill, so the last sheet of a run isn't mostly empty.
+    let rows = (codes.len() as u32).div_ceil(grid.columns).min(grid.rows);
+    let columns = (codes.len() as u32).min(grid.columns);
+    let extent = |cells: u32| {
+        cells
+            .checked_mul(cell.checked_add(grid.gap)?)?
+            .checked_add(grid.gap)
+    };
+    let (width, height) = extent(columns)
+        .zip(extent(rows))
+        .filter(|(width, height)| u64::from(*width) * u64::from(*height) * 3 <= isize::MAX as u64)
+        .ok_or_else(too_large)?;
+
+    let (r, g, b) = style.background;
+    let mut data = [r, g, b].repeat((width * height) as usize);
+    let stride = width as usize * 3;
+    let scale = style.scale as usize;
+
+    for (i, code) in codes.iter().enumerate() {
+        let i = i as u32;
+        let offset = (cell - style.modules(&code.qr_code) as u32 * style.scale) / 2;
+        let left = grid.gap + (i % grid.columns) * (cell + grid.gap) + offset;
+        let top = grid.gap + (i / grid.columns) * (cell + grid.gap) + offset;
+        let border = usize::from(style.border) * scale;
+
+        for (x, y, len) in batch::runs(&code.qr_code) {
+            let x = left as usize + border + x as usize * scale;
+            let y = top as usize + border + y as usize * scale;
+            for row in y..y + scale {
+                let start = row * stride + x * 3;
+                for pixel in data[start..start + len as usize * scale * 3].chunks_exact_mut(3) {
+                    pixel.copy_from_slice(&[
+                        style.foreground.0,
+                        style.foreground.1,
+                        style.foreground.2,
+                    ]);
+                }
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the contact sheet is too large",
+    )
+}
+
+/// The path of a sheet when there are several, with its number before the extension.
+pub fn numbered(path: &Path, number: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:03}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{:03}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn batch(names: &[&str]) -> batch::Batch {
+        let batch = batch::Batch::default();
+        for (row, name) in names.iter().enumerate() {
+            let qr_code = qrcodegen::QrCode::encode_text(name, qrcodegen::QrCodeEcc::Low).unwrap();
+            batch.add(Path::new("in.csv"), row as u64, name, &qr_code);
+        }
+        batch
+    }
+
+    fn dimensions(path: &Path) -> (u32, u32) {
+        let reader = png::Decoder::new(File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        (reader.info().width, reader.info().height)
+    }
+
+    #[test]
+    fn should_tile_codes_into_numbered_sheets() {
+        let path = std::env::temp_dir().join("qrgen_should_tile_codes_into_numbered_sheets.png");
+        let style = Style {
+            scale: 2,
+            ..Default::default()
+        };
+
+        let paths = write(
+            &path,
+            &batch(&["a", "b", "c", "d", "e"]).codes(),
+            Grid::new(2, 2, 4),
+            &style,
+        )
+        .unwrap();
+
+        // Version 1 is 21 modules across, 42 pixels at a scale of 2.
+        assert_eq!(
+            vec![
+                std::env::temp_dir().join("qrgen_should_tile_codes_into_numbered_sheets-001.png"),
+                std::env::temp_dir().join("qrgen_should_tile_codes_into_numbered_sheets-002.png"),
+            ],
+            paths
+        );
+        assert_eq!((4 + 2 * (42 + 4), 4 + 2 * (42 + 4)), dimensions(&paths[0]));
+        assert_eq!((4 + 42 + 4, 4 + 42 + 4), dimensions(&paths[1]));
+        for path in paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_write_one_sheet_without_a_number() {
+        let path = std::env::temp_dir().join("qrgen_should_write_one_sheet_without_a_number.png");
+        let style = Style {
+            scale: 1,
+            ..Default::default()
+        };
+
+        let paths = write(&path, &batch(&["a", "b"]).codes(), Grid::default(), &style).unwrap();
+
+        assert_eq!(vec![path.clone()], paths);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_refuse_a_scale_too_large_for_a_sheet() {
+        let path =
+            std::env::temp_dir().join("qrgen_should_refuse_a_scale_too_large_for_a_sheet.png");
+        let style = Style {
+            scale: u32::MAX,
+            ..Default::default()
+        };
+
+        let res = write(&path, &batch(&["a"]).codes(), Grid::default(), &style);
+
+        assert_eq!(io::ErrorKind::InvalidInput, res.unwrap_err().kind());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,124 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{env, fmt, fs, path::Path};
+
+const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Appends an HMAC-SHA256 signature of each payload so it can be checked offline with the same key.
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+    separator: String,
+}
+
+impl Signer {
+    pub fn new(key: Vec<u8>, separator: String) -> Self {
+        Signer { key, separator }
+    }
+
+    /// The payload followed by the separator and the URL safe base64 signature of the payload.
+    pub fn sign(&self, payload: &str) -> String {
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(payload).finalize().into_bytes());
+        format!("{}{}{}", payload, self.separator, signature)
+    }
+
+    /// Check the signature after the last separator was made from the rest of the payload with this key.
+    pub fn verify(&self, signed: &str) -> bool {
+        let (payload, signature) = match signed.rsplit_once(self.separator.as_str()) {
+            Some(parts) => parts,
+            None => return false,
+        };
+
+        match URL_SAFE_NO_PAD.decode(signature) {
+            Ok(signature) => self.mac(payload).verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// An HS256 JSON Web Token holding the given JSON claims.
+    pub fn token(&self, claims: &str) -> String {
+        let unsigned = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(JWT_HEADER),
+            URL_SAFE_NO_PAD.encode(claims)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&unsigned).finalize().into_bytes());
+        format!("{}.{}", unsigned, signature)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+// Keep the key out of logs.
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("separator", &self.separator)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read the signing key from an environment variable or a file, ignoring a trailing line break in the file.
+pub fn read_key(variable: Option<&str>, file: Option<&Path>) -> Result<Option<Vec<u8>>, String> {
+    let key = match (variable, file) {
+        (Some(variable), _) => env::var(variable)
+            .map(String::into_bytes)
+            .map_err(|_| format!("The environment variable {} is not set.", variable))?,
+        (None, Some(file)) => {
+            let mut key = fs::read(file)
+                .map_err(|e| format!("Unable to read the key {}: {}", file.display(), e))?;
+            while key.last().is_some_and(|b| matches!(b, b'\r' | b'\n')) {
+                key.pop();
+            }
+            key
+        }
+        (None, None) => return Ok(None),
+    };
+
+    if key.is_empty() {
+        return Err(String::from("The signing key is empty."));
+    }
+
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_sign_and_verify() {
+        let signer = Signer::new(b"secret".to_vec(), ".".into());
+        let signed = signer.sign("TICKET-1.A");
+
+        assert!(signed.starts_with("TICKET-1.A."));
+        assert_eq!("TICKET-1.A.".len() + 43, signed.len());
+        assert!(signer.verify(&signed));
+        assert!(!signer.verify(&signed.replace("TICKET-1", "TICKET-2")));
+        assert!(!Signer::new(b"other".to_vec(), ".".into()).verify(&signed));
+        assert!(!signer.verify("TICKET-1"));
+    }
+
+    #[test]
+    fn should_read_key_from_file() {
+        let path = std::env::temp_dir().join("qrgen_should_read_key_from_file");
+        fs::write(&path, "secret\n").unwrap();
+
+        assert_eq!(Ok(Some(b"secret".to_vec())), read_key(None, Some(&path)));
+        assert_eq!(Ok(None), read_key(None, None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_not_show_key() {
+        let signer = Signer::new(b"secret".to_vec(), ".".into());
+
+        assert!(!format!("{:?}", signer).contains("115"));
+    }
+}
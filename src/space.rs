@@ -0,0 +1,119 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+// Files take up whole blocks, so on most file systems even the smallest output uses this much of the disk.
+const BLOCK_SIZE: u64 = 4096;
+
+// The lines are counted in this much of the start of a file and scaled up by its size.
+const SAMPLE_BYTES: u64 = 1 << 16;
+
+/// The least space the given number of outputs of about this size take up on disk.
+pub fn estimate(outputs: u64, output_bytes: u64) -> u64 {
+    outputs.saturating_mul(output_bytes.div_ceil(BLOCK_SIZE).max(1) * BLOCK_SIZE)
+}
+
+/// The space free for qrgen to use on the volume holding the directory.
+pub fn available(directory: &Path) -> io::Result<u64> {
+    fs2::available_space(directory)
+}
+
+/// An estimate of the number of lines in a file from those at its start, so a large file isn't read in full before
+/// the run reads it again.  A small file is counted exactly, including a last line without a line break.
+pub fn estimate_lines(path: &Path) -> io::Result<u64> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut sample = Vec::new();
+    file.take(SAMPLE_BYTES).read_to_end(&mut sample)?;
+    let lines = sample.iter().filter(|b| **b == b'\n').count() as u64;
+
+    if sample.len() as u64 >= size {
+        return Ok(lines + u64::from(sample.last().is_some_and(|b| *b != b'\n')));
+    }
+
+    // A sample of part of one long line still counts as a line.
+    Ok(lines
+        .max(1)
+        .saturating_mul(size)
+        .div_ceil(sample.len() as u64))
+}
+
+/// Parse a number of bytes greater than 0 with an optional K, M or G suffix, e.g. 500M.
+pub fn parse_bytes(src: &str) -> Option<u64> {
+    let src = src.trim().to_uppercase();
+    let (number, multiplier) = match src.char_indices().last() {
+        Some((i, 'K')) => (&src[..i], 1 << 10),
+        Some((i, 'M')) => (&src[..i], 1 << 20),
+        Some((i, 'G')) => (&src[..i], 1 << 30),
+        _ => (src.as_str(), 1),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|bytes| *bytes > 0)
+}
+
+/// A number of bytes rounded to one decimal place of the largest unit that fits, e.g. 1.5G.
+pub fn describe(bytes: u64) -> String {
+    const UNITS: [(&str, u64); 3] = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+    UNITS
+        .iter()
+        .find(|(_, size)| bytes >= *size)
+        .map(|(unit, size)| format!("{:.1}{}", bytes as f64 / *size as f64, unit))
+        .unwrap_or_else(|| format!("{}B", bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn should_estimate_whole_blocks() {
+        assert_eq!(10 * 4096, estimate(10, 1));
+        assert_eq!(10 * 8192, estimate(10, 4097));
+        assert_eq!(0, estimate(0, 1000));
+    }
+
+    #[test]
+    fn should_count_lines_of_small_files() {
+        let path = std::env::temp_dir().join("qrgen_should_count_lines_of_small_files");
+
+        fs::write(&path, "a,1\nb,2\nc,3").unwrap();
+        assert_eq!(3, estimate_lines(&path).unwrap());
+        fs::write(&path, "a,1\nb,2\n").unwrap();
+        assert_eq!(2, estimate_lines(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_estimate_lines_of_large_files_from_their_start() {
+        let path = std::env::temp_dir().join("qrgen_should_estimate_lines_of_large_files");
+
+        fs::write(&path, "a,1\n".repeat(100_000)).unwrap();
+        assert_eq!(100_000, estimate_lines(&path).unwrap());
+        fs::write(&path, "a".repeat(100_000)).unwrap();
+        assert_eq!(2, estimate_lines(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_parse_bytes() {
+        assert_eq!(Some(500 << 20), parse_bytes("500M"));
+        assert_eq!(Some(1024), parse_bytes("1024"));
+        assert_eq!(None, parse_bytes("0"));
+        assert_eq!(None, parse_bytes("lots"));
+    }
+
+    #[test]
+    fn should_describe_bytes() {
+        assert_eq!("512B", describe(512));
+        assert_eq!("1.5K", describe(1536));
+        assert_eq!("2.0G", describe(2 << 30));
+    }
+}
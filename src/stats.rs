@@ -0,0 +1,127 @@
+use crate::capacity;
+use crate::manifest;
+use std::{collections::BTreeMap, error::Error, fmt, fs::File, path::Path};
+
+/// Statistics gathered over every QR Code generated in a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    records: usize,
+    versions: BTreeMap<u8, usize>,
+    modes: BTreeMap<&'static str, usize>,
+    total_modules: u64,
+    min_payload: Option<usize>,
+    max_payload: Option<usize>,
+    max_utilization: f64,
+}
+
+impl Stats {
+    pub fn add(
+        &mut self,
+        qr_code: &qrcodegen::QrCode,
+        segments: &[qrcodegen::QrSegment],
+        payload_len: usize,
+    ) {
+        self.records += 1;
+        *self.versions.entry(qr_code.version().value()).or_insert(0) += 1;
+
+        for segment in segments {
+            *self
+                .modes
+                .entry(manifest::mode_name(segment.mode()))
+                .or_insert(0) += 1;
+        }
+
+        self.total_modules += qr_code.size() as u64;
+        self.min_payload = Some(self.min_payload.map_or(payload_len, |m| m.min(payload_len)));
+        self.max_payload = Some(self.max_payload.map_or(payload_len, |m| m.max(payload_len)));
+
+        let utilization = capacity::Utilization::new(qr_code, segments).percent();
+        self.max_utilization = self.max_utilization.max(utilization);
+    }
+
+    pub fn average_modules(&self) -> f64 {
+        if self.records == 0 {
+            return 0.0;
+        }
+
+        self.total_modules as f64 / self.records as f64
+    }
+
+    /// Write the statistics to the given path as JSON.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(
+            file,
+            &serde_json::json!({
+                "records": self.records,
+                "versions": self.versions,
+                "modes": self.modes,
+                "average_modules": self.average_modules(),
+                "min_payload": self.min_payload,
+                "max_payload": self.max_payload,
+                "max_utilization": self.max_utilization,
+            }),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Records: {}", self.records)?;
+
+        writeln!(f, "Versions:")?;
+        for (version, count) in &self.versions {
+            writeln!(f, "  {:>2}: {}", version, count)?;
+        }
+
+        writeln!(f, "Segment modes:")?;
+        for (mode, count) in &self.modes {
+            writeln!(f, "  {}: {}", mode, count)?;
+        }
+
+        writeln!(f, "Average module count: {:.2}", self.average_modules())?;
+        writeln!(
+            f,
+            "Payload length (bytes): min {}, max {}",
+            self.min_payload.unwrap_or(0),
+            self.max_payload.unwrap_or(0)
+        )?;
+        writeln!(f, "Max capacity utilization: {:.1}%", self.max_utilization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(stats: &mut Stats, text: &str) {
+        let segments = qrcodegen::QrSegment::make_segments(text);
+        let qr_code =
+            qrcodegen::QrCode::encode_segments(&segments, qrcodegen::QrCodeEcc::Low).unwrap();
+        stats.add(&qr_code, &segments, text.len());
+    }
+
+    #[test]
+    fn should_count_versions_and_modes() {
+        let mut stats = Stats::default();
+        add(&mut stats, "12345");
+        add(&mut stats, "hello");
+        add(&mut stats, &"x".repeat(100));
+
+        assert_eq!(3, stats.records);
+        assert_eq!(Some(&2), stats.versions.get(&1));
+        assert_eq!(Some(&1), stats.versions.get(&5));
+        assert_eq!(Some(&1), stats.modes.get("Numeric"));
+        assert_eq!(Some(&2), stats.modes.get("Byte"));
+        assert_eq!(Some(5), stats.min_payload);
+        assert_eq!(Some(100), stats.max_payload);
+        assert!((stats.average_modules() - 79.0 / 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn should_average_zero_when_empty() {
+        assert_eq!(0.0, Stats::default().average_modules());
+    }
+}
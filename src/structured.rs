@@ -0,0 +1,195 @@
+use crate::capacity;
+use qrcodegen::{BitBuffer, Mask, QrCode, QrCodeEcc, QrSegment, QrSegmentMode, Version};
+use std::time::Duration;
+
+/// The most QR Codes a structured append sequence can link.
+pub const MAX_PARTS: usize = 16;
+
+// The structured append mode indicator, the position of the symbol, the last position and the parity of the data.
+const HEADER_BITS: usize = 4 + 4 + 4 + 8;
+const STRUCTURED_APPEND_MODE: u32 = 0b0011;
+const BYTE_MODE: u32 = 0b0100;
+
+/// Whether a record too long for one QR Code is split across several linked by structured append, and how long an
+/// animated GIF shows each of them for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StructuredAppend {
+    pub split: bool,
+    pub interval: Duration,
+}
+
+impl StructuredAppend {
+    pub fn new(split: bool, interval: Duration) -> Self {
+        StructuredAppend { split, interval }
+    }
+}
+
+impl Default for StructuredAppend {
+    fn default() -> Self {
+        StructuredAppend::new(false, Duration::from_millis(500))
+    }
+}
+
+/// Split the data across as few QR Codes as hold it in the version range, up to 16, each starting with a structured
+/// append header so a scanner that reads them all puts the data back together.  The parts share the smallest version
+/// that holds that many, so they look alike.  Returns each QR Code with the segment of the data it holds.
+pub fn encode(
+    data: &[u8],
+    ecc: QrCodeEcc,
+    min: Version,
+    max: Version,
+    mask: Option<Mask>,
+) -> Result<Vec<(QrCode, Vec<QrSegment>)>, String> {
+    if data.is_empty() {
+        return Err(String::from("there is no data to split"));
+    }
+
+    let parts = |version| match part_bytes(version, ecc) {
+        0 => usize::MAX,
+        per_part => data.len().div_ceil(per_part),
+    };
+    let fewest = parts(max);
+    if fewest > MAX_PARTS {
+        return Err(format!(
+            "data of {} bytes is too long for {} QR Codes of version {} or lower",
+            data.len(),
+            MAX_PARTS,
+            max.value()
+        ));
+    }
+    let version = (min.value()..=max.value())
+        .map(Version::new)
+        .find(|version| parts(*version) <= fewest)
+        .unwrap_or(max);
+
+    // Even parts rather than full ones and a short last one.
+    let parity = data.iter().fold(0, |parity, byte| parity ^ byte);
+    let chunks: Vec<_> = data.chunks(data.len().div_ceil(fewest)).collect();
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(position, chunk)| {
+            let bits = part_bits(position, chunks.len(), parity, chunk, version);
+            let qr_code =
+                QrCode::encode_codewords(version, ecc, &codewords(bits, version, ecc), mask);
+            (qr_code, vec![QrSegment::make_bytes(chunk)])
+        })
+        .collect())
+}
+
+// The number of bytes of data a part of the version holds after the header and the byte mode segment's own header.
+fn part_bytes(version: Version, ecc: QrCodeEcc) -> usize {
+    let count_bits = capacity::num_char_count_bits(QrSegmentMode::Byte, version);
+    let bits = (capacity::num_data_codewords(version, ecc) * 8)
+        .saturating_sub(HEADER_BITS + 4 + count_bits);
+
+    (bits / 8).min((1 << count_bits) - 1)
+}
+
+fn part_bits(
+    position: usize,
+    parts: usize,
+    parity: u8,
+    chunk: &[u8],
+    version: Version,
+) -> BitBuffer {
+    let mut bits = BitBuffer(Vec::new());
+    bits.append_bits(STRUCTURED_APPEND_MODE, 4);
+    bits.append_bits(position as u32, 4);
+    bits.append_bits(parts as u32 - 1, 4);
+    bits.append_bits(u32::from(parity), 8);
+
+    bits.append_bits(BYTE_MODE, 4);
+    bits.append_bits(
+        chunk.len() as u32,
+        capacity::num_char_count_bits(QrSegmentMode::Byte, version) as u8,
+    );
+    for byte in chunk {
+        bits.append_bits(u32::from(*byte), 8);
+    }
+    bits
+}
+
+// Fill the version's data capacity the same way qrcodegen does, with a terminator, zeros to the next byte and then
+// alternating pad bytes, and pack the bits into codewords.
+fn codewords(mut bits: BitBuffer, version: Version, ecc: QrCodeEcc) -> Vec<u8> {
+    let capacity = capacity::num_data_codewords(version, ecc) * 8;
+    bits.append_bits(0, (capacity - bits.0.len()).min(4) as u8);
+    bits.append_bits(0, (bits.0.len().wrapping_neg() & 7) as u8);
+    for pad in [0xEC, 0x11].iter().cycle() {
+        if bits.0.len() >= capacity {
+            break;
+        }
+        bits.append_bits(*pad, 8);
+    }
+
+    let mut codewords = vec![0; capacity / 8];
+    for (i, bit) in bits.0.iter().enumerate() {
+        codewords[i / 8] |= u8::from(*bit) << (7 - i % 8);
+    }
+    codewords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_split_into_parts_of_one_version() {
+        let data = "https://example.com/".repeat(20);
+
+        let parts = encode(
+            data.as_bytes(),
+            QrCodeEcc::Low,
+            Version::new(1),
+            Version::new(4),
+            None,
+        )
+        .unwrap();
+
+        // Version 4 at Low holds 80 codewords, 76 bytes after the headers, so 400 bytes take 6 parts of 67 or less.
+        assert_eq!(6, parts.len());
+        assert!(parts.iter().all(|(qr, _)| qr.version().value() == 4));
+        let joined: Vec<u8> = parts
+            .iter()
+            .flat_map(|(_, segments)| segments[0].data().chunks(8))
+            .map(|bits| bits.iter().fold(0, |byte, bit| byte << 1 | u8::from(*bit)))
+            .collect();
+        assert_eq!(data.as_bytes(), &joined[..]);
+    }
+
+    #[test]
+    fn should_build_structured_append_header() {
+        let bits = part_bits(2, 3, 0x5A, b"A", Version::new(1));
+
+        let written: String = bits
+            .0
+            .iter()
+            .map(|bit| if *bit { '1' } else { '0' })
+            .collect();
+        // Mode 3, position 2, last position 2, parity, then a byte mode segment of one byte, A.
+        assert_eq!(
+            "0011 0010 0010 01011010 0100 00000001 01000001".replace(' ', ""),
+            written
+        );
+        let codewords = codewords(bits, Version::new(1), QrCodeEcc::Low);
+        assert_eq!(19, codewords.len());
+        assert_eq!([0x41, 0x00, 0xEC, 0x11], codewords[4..8]);
+    }
+
+    #[test]
+    fn should_refuse_data_too_long_for_sixteen_parts() {
+        let res = encode(
+            &[b'a'; 1000],
+            QrCodeEcc::High,
+            Version::new(1),
+            Version::new(2),
+            None,
+        );
+
+        assert_eq!(
+            Err("data of 1000 bytes is too long for 16 QR Codes of version 2 or lower".to_string()),
+            res.map(|parts| parts.len())
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::batch::{self, Style};
+use crate::pdf;
+
+/// Start an SVG document of the given size in points, the same units as the PDF outputs, with its width and height
+/// in millimetres so it prints at that size.
+pub fn begin(width: f32, height: f32) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{:.2}mm\" height=\"{:.2}mm\" viewBox=\"0 0 {} {}\" stroke=\"none\">\n",
+        width / pdf::POINTS_PER_MM,
+        height / pdf::POINTS_PER_MM,
+        width,
+        height
+    )
+}
+
+pub fn end(svg: &mut String) {
+    *svg += "</svg>\n";
+}
+
+/// Draw the QR Code as a square of the given side with its top left corner at x, y.
+pub fn code(
+    svg: &mut String,
+    qr_code: &qrcodegen::QrCode,
+    style: &Style,
+    x: f32,
+    y: f32,
+    side: f32,
+) {
+    let modules = style.modules(qr_code);
+    let border = i32::from(style.border);
+
+    *svg += &format!(
+        "\t<svg x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" viewBox=\"0 0 {3} {3}\">\n",
+        x, y, side, modules
+    );
+    if !style.no_rect {
+        *svg += &format!(
+            "\t\t<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            colour(style.background)
+        );
+    }
+    *svg += "\t\t<path d=\"";
+    for (i, (column, row, len)) in batch::runs(qr_code).enumerate() {
+        if i > 0 {
+            *svg += " ";
+        }
+        *svg += &format!("M{},{}h{}v1h-{}z", column + border, row + border, len, len);
+    }
+    *svg += &format!("\" fill=\"{}\"/>\n", colour(style.foreground));
+    *svg += "\t</svg>\n";
+}
+
+/// Write the text centred on x with its baseline at y, made smaller if it would be wider than the width.
+pub fn caption(svg: &mut String, text: &str, style: &Style, x: f32, y: f32, size: f32, width: f32) {
+    *svg += &format!(
+        "\t<text x=\"{}\" y=\"{}\" font-family=\"Helvetica, Arial, sans-serif\" font-size=\"{}\" text-anchor=\"middle\" fill=\"{}\">{}</text>\n",
+        x,
+        y,
+        pdf::fit_text(text, None, size, width),
+        colour(style.foreground),
+        escape(text)
+    );
+}
+
+/// Escape the text for use in XML content or an attribute.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The colour as a hex colour for SVG, e.g. #FF0000.
+pub fn colour((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_escape_xml() {
+        assert_eq!(
+            "Fish &amp; &quot;Chips&quot; &lt;3",
+            escape("Fish & \"Chips\" <3")
+        );
+    }
+}
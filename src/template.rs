@@ -0,0 +1,159 @@
+use crate::columns::Column;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part<C> {
+    Text(String),
+    Field(C),
+}
+
+/// A payload assembled from literal text and the values of columns, e.g. https://t.example/{sku}?batch={2}.
+/// Columns are given by number starting at 1 or by header name, and {{ or }} stand for a literal brace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part<Column>>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        field.push(c);
+                    }
+
+                    if !closed {
+                        return Err(format!("The template field {{{} is not closed.", field));
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(crate::parse_column(&field)?));
+                }
+                '}' => return Err(String::from("The template has a } without a matching {.")),
+                _ => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Template {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    /// Find the position of every column in the template, looking names up in the header row of the file.
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<ResolvedTemplate, String> {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => Ok(Part::Text(text.clone())),
+                Part::Field(column) => column.resolve(headers).map(Part::Field),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ResolvedTemplate { parts })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A template whose columns have been found in a particular file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedTemplate {
+    parts: Vec<Part<usize>>,
+}
+
+impl ResolvedTemplate {
+    /// The number of fields a row needs for every column in the template to be present.
+    fn width(&self) -> usize {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Field(index) => Some(index + 1),
+                Part::Text(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Fill in the template from the row, giving an empty payload if any of its columns are missing.
+    pub fn render(&self, row: &csv::StringRecord) -> String {
+        if row.len() < self.width() {
+            return String::new();
+        }
+
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                Part::Field(index) => &row[*index],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_named_and_indexed_columns() {
+        let headers = csv::StringRecord::from(vec!["sku", "batch"]);
+        let row = csv::StringRecord::from(vec!["A-1", "42"]);
+
+        let template = Template::parse("https://t.example/{sku}?batch={2}").unwrap();
+        let resolved = template.resolve(Some(&headers)).unwrap();
+
+        assert_eq!(2, resolved.width());
+        assert_eq!("https://t.example/A-1?batch=42", resolved.render(&row));
+        assert_eq!("", resolved.render(&csv::StringRecord::from(vec!["A-2"])));
+    }
+
+    #[test]
+    fn should_keep_escaped_braces() {
+        let row = csv::StringRecord::from(vec!["x"]);
+        let resolved = Template::parse("{{{1}}}").unwrap().resolve(None).unwrap();
+
+        assert_eq!("{x}", resolved.render(&row));
+    }
+
+    #[test]
+    fn should_reject_unbalanced_braces() {
+        assert_eq!(
+            Err("The template field {sku is not closed.".to_string()),
+            Template::parse("https://t.example/{sku")
+        );
+        assert!(Template::parse("a}b").is_err());
+        assert!(Template::parse("{}").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+/// Draw a QR Code with Unicode half blocks, two modules to a character, so it can be scanned from the terminal.
+/// Light modules are drawn as blocks, which suits the usual light text on a dark background.
+pub fn render(qr: &qrcodegen::QrCode, border: u8) -> String {
+    let border = i32::from(border);
+    let size = qr.size();
+    let is_light = |x: i32, y: i32| !qr.get_module(x, y);
+
+    let mut out = String::new();
+    for y in (-border..size + border).step_by(2) {
+        for x in -border..size + border {
+            out.push(match (is_light(x, y), is_light(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_draw_two_rows_per_line() {
+        let qr = qrcodegen::QrCode::encode_text("a", qrcodegen::QrCodeEcc::Low).unwrap();
+        let drawn = render(&qr, 1);
+        let lines: Vec<&str> = drawn.lines().collect();
+
+        // Version 1 is 21 modules wide, 23 with the border, which takes 12 lines.
+        assert_eq!(12, lines.len());
+        assert!(lines.iter().all(|l| l.chars().count() == 23));
+        assert!(lines[0].starts_with("█▀▀▀▀▀▀▀█"));
+    }
+}
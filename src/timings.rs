@@ -0,0 +1,90 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// A step in generating a QR Code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// Reading input and turning rows into records.
+    Parsing,
+    /// Choosing the version and mask and building the QR Code.
+    Encoding,
+    /// Drawing the SVG or PNG image.
+    Rendering,
+    /// Writing the image to its file.
+    Writing,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage::Parsing,
+    Stage::Encoding,
+    Stage::Rendering,
+    Stage::Writing,
+];
+
+/// The time spent in each stage over a run, added up across every thread, and the time the run took.
+pub struct Timings {
+    started: Instant,
+    nanos: [AtomicU64; 4],
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Timings {
+            started: Instant::now(),
+            nanos: Default::default(),
+        }
+    }
+
+    /// Run the given function, adding the time it takes to the stage.
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(stage, start.elapsed());
+        result
+    }
+
+    pub fn add(&self, stage: Stage, elapsed: Duration) {
+        self.nanos[stage as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        Duration::from_nanos(self.nanos[stage as usize].load(Ordering::Relaxed))
+    }
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings::new()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Timings (summed across threads):")?;
+        for stage in STAGES {
+            writeln!(f, "  {:?}: {:.3}s", stage, self.get(stage).as_secs_f64())?;
+        }
+        writeln!(f, "Elapsed: {:.3}s", self.started.elapsed().as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_add_time_to_stage() {
+        let timings = Timings::new();
+
+        assert_eq!(4, timings.time(Stage::Encoding, || 2 + 2));
+        timings.add(Stage::Writing, Duration::from_millis(1500));
+        timings.add(Stage::Writing, Duration::from_millis(500));
+
+        assert_eq!(Duration::from_secs(2), timings.get(Stage::Writing));
+        assert_eq!(Duration::ZERO, timings.get(Stage::Parsing));
+        assert!(timings.to_string().contains("  Writing: 2.000s\n"));
+    }
+}